@@ -0,0 +1,47 @@
+export type ErrorCode =
+	| 'database'
+	| 'crypto'
+	| 'locked'
+	| 'not_found'
+	| 'not_initialized'
+	| 'already_initialized'
+	| 'invalid_passphrase'
+	| 'invalid_totp'
+	| 'throttled'
+	| 'unsupported_schema'
+	| 'io'
+	| 'network'
+	| 'parse'
+	| 'validation';
+
+export interface FieldError {
+	field: string;
+	message: string;
+}
+
+/** Error returned by every backend command. */
+export interface AppError {
+	code: ErrorCode;
+	message: string;
+	details: Record<string, unknown> | null;
+}
+
+export function isAppError(e: unknown): e is AppError {
+	return typeof e === 'object' && e !== null && 'code' in e && 'message' in e;
+}
+
+export function hasCode(e: unknown, code: ErrorCode): e is AppError {
+	return isAppError(e) && e.code === code;
+}
+
+/** The failing fields of a `validation` error, otherwise empty. */
+export function fieldErrors(e: unknown): FieldError[] {
+	if (!hasCode(e, 'validation')) return [];
+	return (e.details?.fields as FieldError[] | undefined) ?? [];
+}
+
+export function errorMessage(e: unknown): string {
+	if (isAppError(e)) return e.message;
+	if (e instanceof Error) return e.message;
+	return String(e);
+}
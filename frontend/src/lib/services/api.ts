@@ -0,0 +1,23 @@
+import { invoke } from '@tauri-apps/api/core';
+
+export interface ApiSettings {
+	enabled: boolean;
+	port: number;
+	/** Read-only; set by `regenerateToken`. */
+	has_token: boolean;
+}
+
+export interface ApiAuditEntry {
+	id: number;
+	called_at: string;
+	method: string;
+	outcome: string;
+}
+
+export const ApiService = {
+	getSettings: () => invoke<ApiSettings>('get_api_settings'),
+	setSettings: (settings: ApiSettings) => invoke<void>('set_api_settings', { settings }),
+	/** Returns the new token; it cannot be read back later. */
+	regenerateToken: () => invoke<string>('regenerate_api_token'),
+	getAuditLog: (limit = 100) => invoke<ApiAuditEntry[]>('get_api_audit_log', { limit }),
+};
@@ -9,10 +9,66 @@ export interface BudgetMonth {
 
 export interface BudgetSummary {
 	month: string;
+	/** Sum of the month's income entries. */
 	income: number;
+	/** The figure typed in for the month, kept for comparison. */
+	planned_income: number;
 	total_expenses: number;
 	total_subscriptions_monthly: number;
 	remaining: number;
+	categories: CategorySpending[];
+}
+
+/** A spending limit for one category in one month, in EUR. */
+export interface CategoryBudget {
+	id: string;
+	category_id: string;
+	month: string;
+	amount: number;
+	created_at: string;
+}
+
+/** `spent` includes the expenses of subcategories. */
+export interface CategorySpending {
+	category_id: string;
+	name: string;
+	limit: number | null;
+	spent: number;
+	/** Negative once the limit is exceeded. */
+	remaining: number | null;
+	percent_used: number | null;
+}
+
+/** Money moved from one category envelope to another, in EUR. */
+export interface EnvelopeTransfer {
+	id: string;
+	month: string;
+	from_category_id: string;
+	to_category_id: string;
+	amount: number;
+	created_at: string;
+}
+
+/** Only the category's own expenses count; subcategories are envelopes of their own. */
+export interface EnvelopeLine {
+	category_id: string;
+	name: string;
+	parent_id: string | null;
+	assigned: number;
+	/** The month's expenses, negative. */
+	activity: number;
+	/** Includes what carried over from earlier months; negative when overspent. */
+	available: number;
+}
+
+export interface EnvelopeSummary {
+	month: string;
+	income: number;
+	ready_to_assign: number;
+	assigned: number;
+	activity: number;
+	available: number;
+	categories: EnvelopeLine[];
 }
 
 export const BudgetService = {
@@ -21,4 +77,17 @@ export const BudgetService = {
 		invoke<BudgetMonth>('upsert_budget_month', { month, income }),
 	getSummary: (month: string) =>
 		invoke<BudgetSummary>('get_budget_summary', { month }),
+	getCategoryBudgets: (month: string) =>
+		invoke<CategoryBudget[]>('get_category_budgets', { month }),
+	setCategoryBudget: (categoryId: string, month: string, amount: number) =>
+		invoke<CategoryBudget>('set_category_budget', { categoryId, month, amount }),
+	deleteCategoryBudget: (id: string) => invoke<void>('delete_category_budget', { id }),
+	getOverspent: (month: string) =>
+		invoke<CategorySpending[]>('get_overspent_categories', { month }),
+	getEnvelopes: (month: string) =>
+		invoke<EnvelopeSummary>('get_envelope_summary', { month }),
+	getTransfers: (month: string) =>
+		invoke<EnvelopeTransfer[]>('get_envelope_transfers', { month }),
+	moveBetweenEnvelopes: (month: string, fromCategoryId: string, toCategoryId: string, amount: number) =>
+		invoke<EnvelopeTransfer>('move_between_envelopes', { month, fromCategoryId, toCategoryId, amount }),
 };
@@ -0,0 +1,35 @@
+import { invoke } from '@tauri-apps/api/core';
+
+export interface Category {
+	id: string;
+	name: string;
+	parent_id: string | null;
+	color: string | null;
+	icon: string | null;
+	archived: boolean;
+	created_at: string;
+}
+
+export interface CreateCategory {
+	name: string;
+	parent_id?: string;
+	color?: string;
+	icon?: string;
+}
+
+/** An empty string clears `parent_id`, `color` or `icon`. */
+export interface UpdateCategory {
+	name?: string;
+	parent_id?: string;
+	color?: string;
+	icon?: string;
+	archived?: boolean;
+}
+
+export const CategoryService = {
+	getAll: () => invoke<Category[]>('get_categories'),
+	create: (input: CreateCategory) => invoke<Category>('create_category', { input }),
+	update: (id: string, input: UpdateCategory) => invoke<Category>('update_category', { id, input }),
+	delete: (id: string) => invoke<void>('delete_category', { id }),
+	merge: (sources: string[], into: string) => invoke<Category>('merge_categories', { sources, into }),
+};
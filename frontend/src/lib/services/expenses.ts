@@ -5,6 +5,8 @@ export interface Expense {
 	title: string;
 	amount: number;
 	currency: string;
+	category_id: string;
+	/** Name of the category, for display. */
 	category: string;
 	date: string;
 	created_at: string;
@@ -14,7 +16,7 @@ export interface CreateExpense {
 	title: string;
 	amount: number;
 	currency?: string;
-	category: string;
+	category_id: string;
 	date: string;
 }
 
@@ -22,13 +24,14 @@ export interface UpdateExpense {
 	title?: string;
 	amount?: number;
 	currency?: string;
-	category?: string;
+	category_id?: string;
 	date?: string;
 }
 
 export const ExpenseService = {
 	getAll: () => invoke<Expense[]>('get_expenses'),
 	create: (input: CreateExpense) => invoke<Expense>('create_expense', { input }),
-	update: (id: string, input: UpdateExpense) => invoke<void>('update_expense', { id, input }),
+	update: (id: string, input: UpdateExpense) => invoke<Expense>('update_expense', { id, input }),
 	delete: (id: string) => invoke<void>('delete_expense', { id }),
+	restore: (id: string) => invoke<Expense>('restore_expense', { id }),
 };
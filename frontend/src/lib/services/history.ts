@@ -0,0 +1,22 @@
+import { invoke } from '@tauri-apps/api/core';
+
+export type HistoryEntity = 'expense' | 'subscription' | 'position' | 'savings plan' | 'budget month';
+
+export type ChangeAction = 'create' | 'update' | 'delete' | 'restore' | 'undo';
+
+export interface Change<T = Record<string, unknown>> {
+	id: string;
+	entity: HistoryEntity;
+	entity_id: string;
+	action: ChangeAction;
+	before: T | null;
+	after: T | null;
+	reverts: string | null;
+	changed_at: string;
+}
+
+export const HistoryService = {
+	get: <T = Record<string, unknown>>(entity: HistoryEntity, id: string) =>
+		invoke<Change<T>[]>('get_history', { entity, id }),
+	undoLast: () => invoke<Change | null>('undo_last_change'),
+};
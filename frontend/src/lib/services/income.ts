@@ -0,0 +1,69 @@
+import { invoke } from '@tauri-apps/api/core';
+
+export interface Income {
+	id: string;
+	source: string;
+	amount: number;
+	currency: string;
+	date: string;
+	created_at: string;
+}
+
+export interface CreateIncome {
+	source: string;
+	amount: number;
+	currency?: string;
+	date: string;
+}
+
+export interface UpdateIncome {
+	source?: string;
+	amount?: number;
+	currency?: string;
+	date?: string;
+}
+
+/** Booked as an `Income` entry each time `next_date` comes around. */
+export interface RecurringIncome {
+	id: string;
+	source: string;
+	amount: number;
+	currency: string;
+	interval: string;
+	next_date: string;
+	/** The occurrence later ones count from. */
+	anchor_date: string;
+	created_at: string;
+}
+
+export interface CreateRecurringIncome {
+	source: string;
+	amount: number;
+	currency?: string;
+	interval: string;
+	next_date: string;
+}
+
+export interface UpdateRecurringIncome {
+	source?: string;
+	amount?: number;
+	currency?: string;
+	interval?: string;
+	next_date?: string;
+}
+
+export const IncomeService = {
+	getAll: () => invoke<Income[]>('get_income'),
+	create: (input: CreateIncome) => invoke<Income>('create_income', { input }),
+	update: (id: string, input: UpdateIncome) => invoke<Income>('update_income', { id, input }),
+	delete: (id: string) => invoke<void>('delete_income', { id }),
+	restore: (id: string) => invoke<Income>('restore_income', { id }),
+	getRecurring: () => invoke<RecurringIncome[]>('get_recurring_income'),
+	createRecurring: (input: CreateRecurringIncome) =>
+		invoke<RecurringIncome>('create_recurring_income', { input }),
+	updateRecurring: (id: string, input: UpdateRecurringIncome) =>
+		invoke<RecurringIncome>('update_recurring_income', { id, input }),
+	deleteRecurring: (id: string) => invoke<void>('delete_recurring_income', { id }),
+	restoreRecurring: (id: string) => invoke<RecurringIncome>('restore_recurring_income', { id }),
+	bookRecurring: () => invoke<Income[]>('book_recurring_income'),
+};
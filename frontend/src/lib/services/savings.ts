@@ -33,6 +33,7 @@ export interface UpdateSavingsPlan {
 export const SavingsService = {
 	getAll: () => invoke<SavingsPlan[]>('get_savings_plans'),
 	create: (input: CreateSavingsPlan) => invoke<SavingsPlan>('create_savings_plan', { input }),
-	update: (id: string, input: UpdateSavingsPlan) => invoke<void>('update_savings_plan', { id, input }),
+	update: (id: string, input: UpdateSavingsPlan) => invoke<SavingsPlan>('update_savings_plan', { id, input }),
 	delete: (id: string) => invoke<void>('delete_savings_plan', { id }),
+	restore: (id: string) => invoke<SavingsPlan>('restore_savings_plan', { id }),
 };
@@ -29,6 +29,7 @@ export interface UpdateSubscription {
 export const SubscriptionService = {
 	getAll: () => invoke<Subscription[]>('get_subscriptions'),
 	create: (input: CreateSubscription) => invoke<Subscription>('create_subscription', { input }),
-	update: (id: string, input: UpdateSubscription) => invoke<void>('update_subscription', { id, input }),
+	update: (id: string, input: UpdateSubscription) => invoke<Subscription>('update_subscription', { id, input }),
 	delete: (id: string) => invoke<void>('delete_subscription', { id }),
+	restore: (id: string) => invoke<Subscription>('restore_subscription', { id }),
 };
@@ -0,0 +1,21 @@
+import { invoke } from '@tauri-apps/api/core';
+
+export type TrashEntity = 'expense' | 'subscription' | 'position' | 'savings plan';
+
+export interface TrashItem {
+	entity: TrashEntity;
+	id: string;
+	label: string;
+	deleted_at: string;
+}
+
+export interface TrashSettings {
+	retention_days: number;
+}
+
+export const TrashService = {
+	getAll: () => invoke<TrashItem[]>('get_trash'),
+	empty: () => invoke<number>('empty_trash'),
+	getSettings: () => invoke<TrashSettings>('get_trash_settings'),
+	setSettings: (settings: TrashSettings) => invoke<void>('set_trash_settings', { settings }),
+};
@@ -1,5 +1,8 @@
 import { writable, derived, get } from 'svelte/store';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { toast } from 'svelte-sonner';
+import { errorMessage, type AppError } from '$lib/errors';
 
 type VaultStatus = 'checking' | 'uninitialized' | 'locked' | 'unlocked';
 
@@ -8,6 +11,11 @@ interface VaultState {
 	error: string | null;
 }
 
+/** What `unlock` could not finish; the vault is open either way. */
+interface UnlockReport {
+	income_booking_error: AppError | null;
+}
+
 const state = writable<VaultState>({ status: 'checking', error: null });
 
 export const vault = {
@@ -22,6 +30,10 @@ export const vault = {
 	},
 
 	async init() {
+		// The backend locks itself after inactivity, a long blur or a suspend.
+		await listen('vault-locked', () => {
+			state.set({ status: 'locked', error: null });
+		});
 		try {
 			const exists = await invoke<boolean>('vault_exists');
 			if (!exists) {
@@ -42,7 +54,7 @@ export const vault = {
 			state.set({ status: 'unlocked', error: null });
 			return true;
 		} catch (e) {
-			state.update((s) => ({ ...s, error: e as string }));
+			state.update((s) => ({ ...s, error: errorMessage(e) }));
 			return false;
 		}
 	},
@@ -50,11 +62,30 @@ export const vault = {
 	async unlock(passphrase: string, totpCode?: string): Promise<boolean> {
 		state.update((s) => ({ ...s, error: null }));
 		try {
-			await invoke('unlock', { passphrase, totpCode: totpCode ?? null });
+			const report = await invoke<UnlockReport>('unlock', {
+				passphrase,
+				totpCode: totpCode ?? null,
+			});
 			state.set({ status: 'unlocked', error: null });
+			if (report.income_booking_error) {
+				toast.error(
+					`Wiederkehrende Einnahmen konnten nicht gebucht werden: ${errorMessage(report.income_booking_error)}`,
+				);
+			}
+			return true;
+		} catch (e) {
+			state.update((s) => ({ ...s, error: errorMessage(e) }));
+			return false;
+		}
+	},
+
+	async changePassphrase(oldPassphrase: string, newPassphrase: string): Promise<boolean> {
+		state.update((s) => ({ ...s, error: null }));
+		try {
+			await invoke('change_passphrase', { oldPassphrase, newPassphrase });
 			return true;
 		} catch (e) {
-			state.update((s) => ({ ...s, error: e as string }));
+			state.update((s) => ({ ...s, error: errorMessage(e) }));
 			return false;
 		}
 	},
@@ -0,0 +1,3 @@
+fn main() -> std::process::ExitCode {
+    finance_vault_lib::run_cli()
+}
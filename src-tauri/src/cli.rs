@@ -0,0 +1,1006 @@
+//! `financevault-cli`: the vault without the GUI, for scripts and cron jobs.
+//! It opens `vault.db` the way `unlock` does and goes through the same
+//! `Vault` methods, so validation, encryption and the change log behave
+//! exactly as in the app.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use crate::commands::auth::open_vault;
+use crate::error::VaultError;
+use crate::validation::{self, FieldError};
+use crate::vault::budget::{BudgetSummary, CategoryBudget, CategorySpending};
+use crate::vault::categories::{Category, CreateCategory};
+use crate::vault::dashboard::DashboardSummary;
+use crate::vault::envelopes::{EnvelopeLine, EnvelopeSummary, EnvelopeTransfer};
+use crate::vault::expenses::{CreateExpense, Expense};
+use crate::vault::income::{CreateIncome, CreateRecurringIncome, Income, RecurringIncome};
+use crate::vault::portfolio::{CreatePosition, Position};
+use crate::vault::savings::{CreateSavingsPlan, SavingsPlan};
+use crate::vault::subscriptions::{CreateSubscription, Subscription};
+use crate::vault::Vault;
+
+const USAGE: &str = "\
+usage: financevault-cli [--vault PATH] [--passphrase-file PATH] [--totp CODE] [--json] COMMAND
+
+commands:
+  expenses list [--month YYYY-MM]
+  expenses add --title TEXT --amount N --category NAME --date YYYY-MM-DD [--currency CODE]
+  categories list
+  categories add --name TEXT [--parent NAME] [--color #RRGGBB] [--icon TEXT]
+  income list [--month YYYY-MM]
+  income add --source TEXT --amount N --date YYYY-MM-DD [--currency CODE]
+  income recurring list
+  income recurring add --source TEXT --amount N --interval monthly|quarterly|yearly
+                       --next-date YYYY-MM-DD [--currency CODE]
+  income book
+  subscriptions list
+  subscriptions add --name TEXT --amount N --billing monthly|yearly
+                    --next-billing YYYY-MM-DD [--currency CODE]
+  positions list
+  positions add --ticker TEXT --name TEXT --asset-type stock|etf|crypto|other
+                --quantity N --avg-buy-price N [--isin ISIN] [--currency CODE] [--country TEXT]
+  savings list
+  savings add --name TEXT --isin ISIN --ticker TEXT --amount N
+              --interval monthly|quarterly|yearly --next-date YYYY-MM-DD [--currency CODE]
+  expenses|income|subscriptions|positions|savings export [--format csv|json] [--output PATH]
+  budget [YYYY-MM]
+  budget limit --category NAME --amount N [--month YYYY-MM]
+  budget overspent [YYYY-MM]
+  budget envelopes [YYYY-MM]
+  budget move --from NAME --to NAME --amount N [--month YYYY-MM]
+  dashboard [YYYY-MM]
+
+The passphrase is read from --passphrase-file, then FINANCEVAULT_PASSPHRASE,
+then standard input. The TOTP code can also be set in FINANCEVAULT_TOTP.
+--vault or FINANCEVAULT_VAULT point at a vault other than the app's.
+A category NAME may be a path like Food/Snacks where names repeat.
+";
+
+/// The app keeps its data under its identifier from `tauri.conf.json`.
+const APP_IDENTIFIER: &str = "com.financevault.app";
+
+#[derive(Debug)]
+enum Failure {
+    /// The command line itself is wrong; exits with 2 and prints the usage.
+    Usage(String),
+    Vault(VaultError),
+}
+
+impl From<VaultError> for Failure {
+    fn from(e: VaultError) -> Self {
+        Failure::Vault(e)
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Vault(VaultError::Io(e))
+    }
+}
+
+type CliResult<T> = std::result::Result<T, Failure>;
+
+/// Positional words and `--name value` options, in any order.
+struct Args {
+    words: Vec<String>,
+    options: HashMap<String, String>,
+    json: bool,
+}
+
+impl Args {
+    fn parse(args: impl IntoIterator<Item = String>) -> CliResult<Args> {
+        let mut args = args.into_iter();
+        let mut parsed = Args { words: Vec::new(), options: HashMap::new(), json: false };
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.words.push(arg);
+                continue;
+            };
+            match name {
+                "json" => parsed.json = true,
+                "help" => parsed.words.insert(0, "help".into()),
+                _ => {
+                    let (name, value) = match name.split_once('=') {
+                        Some((name, value)) => (name.to_string(), value.to_string()),
+                        None => {
+                            let value = args
+                                .next()
+                                .ok_or_else(|| Failure::Usage(format!("--{name} needs a value")))?;
+                            (name.to_string(), value)
+                        }
+                    };
+                    if parsed.options.insert(name.clone(), value).is_some() {
+                        return Err(Failure::Usage(format!("--{name} given more than once")));
+                    }
+                }
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn take(&mut self, name: &str) -> Option<String> {
+        self.options.remove(name)
+    }
+
+    /// Fails on options no command asked for, so a typo is not ignored.
+    fn finish(&self) -> CliResult<()> {
+        match self.options.keys().next() {
+            Some(name) => Err(Failure::Usage(format!("unknown option --{name}"))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads the options of an `add` command, collecting every missing or
+/// malformed one so they are reported together like other invalid input.
+struct Fields<'a> {
+    args: &'a mut Args,
+    errors: Vec<FieldError>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(args: &'a mut Args) -> Self {
+        Fields { args, errors: Vec::new() }
+    }
+
+    fn optional(&mut self, name: &str) -> Option<String> {
+        self.args.take(name)
+    }
+
+    fn text(&mut self, name: &str) -> String {
+        self.args.take(name).unwrap_or_else(|| {
+            self.fail(name, "is required".into());
+            String::new()
+        })
+    }
+
+    fn parsed<T: FromStr + Default>(&mut self, name: &str) -> T
+    where
+        T::Err: Display,
+    {
+        let text = self.text(name);
+        if text.is_empty() {
+            return T::default();
+        }
+        text.parse().unwrap_or_else(|e| {
+            self.fail(name, format!("{e}"));
+            T::default()
+        })
+    }
+
+    fn fail(&mut self, name: &str, message: String) {
+        self.errors.push(FieldError { field: name.to_string(), message });
+    }
+
+    fn finish(self) -> CliResult<()> {
+        self.args.finish()?;
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(VaultError::Validation(self.errors).into())
+        }
+    }
+}
+
+/// Where the vault is and how to unlock it.
+struct Session {
+    vault_path: PathBuf,
+    passphrase_file: Option<String>,
+    totp_code: Option<String>,
+}
+
+impl Session {
+    fn from_args(args: &mut Args) -> CliResult<Session> {
+        let vault_path = args
+            .take("vault")
+            .or_else(|| env::var("FINANCEVAULT_VAULT").ok())
+            .map(PathBuf::from)
+            .or_else(default_vault_path)
+            .ok_or_else(|| Failure::Usage("cannot find the data directory; pass --vault".into()))?;
+        Ok(Session {
+            vault_path,
+            passphrase_file: args.take("passphrase-file"),
+            totp_code: args.take("totp").or_else(|| env::var("FINANCEVAULT_TOTP").ok()),
+        })
+    }
+
+    fn passphrase(&self) -> CliResult<Zeroizing<String>> {
+        let text = if let Some(path) = &self.passphrase_file {
+            Zeroizing::new(fs::read_to_string(path)?)
+        } else if let Ok(passphrase) = env::var("FINANCEVAULT_PASSPHRASE") {
+            Zeroizing::new(passphrase)
+        } else {
+            eprint!("Passphrase: ");
+            io::stderr().flush()?;
+            let mut line = Zeroizing::new(String::new());
+            io::stdin().lock().read_line(&mut line)?;
+            line
+        };
+        Ok(Zeroizing::new(text.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    /// Unlocks the vault for the duration of `f`. Failed attempts count
+    /// towards the same throttle as the app's.
+    fn with_vault<T>(&self, f: impl FnOnce(&Vault<'_>) -> crate::error::Result<T>) -> CliResult<T> {
+        let passphrase = self.passphrase()?;
+        let (conn, key, _) = open_vault(&self.vault_path, &passphrase, self.totp_code.as_deref())?;
+        Ok(f(&Vault::new(&conn, &key))?)
+    }
+}
+
+/// The app's data directory, as Tauri resolves it.
+fn default_vault_path() -> Option<PathBuf> {
+    let data_dir = if cfg!(target_os = "windows") {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(env::var_os("HOME")?).join("Library/Application Support")
+    } else {
+        match env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+            Some(dir) if dir.is_absolute() => dir,
+            _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+        }
+    };
+    Some(data_dir.join(APP_IDENTIFIER).join("vault.db"))
+}
+
+/// How entries are laid out as table rows and CSV records.
+trait Tabular: Serialize {
+    const COLUMNS: &'static [&'static str];
+
+    fn cells(&self) -> Vec<String>;
+}
+
+impl Tabular for Expense {
+    const COLUMNS: &'static [&'static str] =
+        &["id", "date", "title", "category", "amount", "currency"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.date.clone(),
+            self.title.clone(),
+            self.category.clone(),
+            self.amount.to_string(),
+            self.currency.clone(),
+        ]
+    }
+}
+
+impl Tabular for Subscription {
+    const COLUMNS: &'static [&'static str] =
+        &["id", "next_billing", "name", "billing", "amount", "currency"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.next_billing.clone(),
+            self.name.clone(),
+            self.billing.clone(),
+            self.amount.to_string(),
+            self.currency.clone(),
+        ]
+    }
+}
+
+impl Tabular for Position {
+    const COLUMNS: &'static [&'static str] = &[
+        "id",
+        "ticker",
+        "isin",
+        "name",
+        "asset_type",
+        "quantity",
+        "avg_buy_price",
+        "currency",
+        "country",
+    ];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.ticker.clone(),
+            self.isin.clone(),
+            self.name.clone(),
+            self.asset_type.clone(),
+            self.quantity.to_string(),
+            self.avg_buy_price.to_string(),
+            self.currency.clone(),
+            self.country.clone(),
+        ]
+    }
+}
+
+impl Tabular for Income {
+    const COLUMNS: &'static [&'static str] = &["id", "date", "source", "amount", "currency"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.date.clone(),
+            self.source.clone(),
+            self.amount.to_string(),
+            self.currency.clone(),
+        ]
+    }
+}
+
+impl Tabular for RecurringIncome {
+    const COLUMNS: &'static [&'static str] =
+        &["id", "next_date", "source", "interval", "amount", "currency"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.next_date.clone(),
+            self.source.clone(),
+            self.interval.clone(),
+            self.amount.to_string(),
+            self.currency.clone(),
+        ]
+    }
+}
+
+impl Tabular for Category {
+    const COLUMNS: &'static [&'static str] =
+        &["id", "name", "parent_id", "color", "icon", "archived"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.name.clone(),
+            self.parent_id.clone().unwrap_or_default(),
+            self.color.clone().unwrap_or_default(),
+            self.icon.clone().unwrap_or_default(),
+            self.archived.to_string(),
+        ]
+    }
+}
+
+impl Tabular for CategoryBudget {
+    const COLUMNS: &'static [&'static str] = &["id", "category_id", "month", "amount"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.category_id.clone(),
+            self.month.clone(),
+            self.amount.to_string(),
+        ]
+    }
+}
+
+impl Tabular for CategorySpending {
+    const COLUMNS: &'static [&'static str] =
+        &["category_id", "name", "limit", "spent", "remaining", "percent_used"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.category_id.clone(),
+            self.name.clone(),
+            self.limit.map(|m| m.to_string()).unwrap_or_default(),
+            self.spent.to_string(),
+            self.remaining.map(|m| m.to_string()).unwrap_or_default(),
+            self.percent_used.map(|p| format!("{p:.1}")).unwrap_or_default(),
+        ]
+    }
+}
+
+impl Tabular for EnvelopeLine {
+    const COLUMNS: &'static [&'static str] =
+        &["category_id", "name", "assigned", "activity", "available"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.category_id.clone(),
+            self.name.clone(),
+            self.assigned.to_string(),
+            self.activity.to_string(),
+            self.available.to_string(),
+        ]
+    }
+}
+
+impl Tabular for EnvelopeTransfer {
+    const COLUMNS: &'static [&'static str] =
+        &["id", "month", "from_category_id", "to_category_id", "amount"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.month.clone(),
+            self.from_category_id.clone(),
+            self.to_category_id.clone(),
+            self.amount.to_string(),
+        ]
+    }
+}
+
+impl Tabular for SavingsPlan {
+    const COLUMNS: &'static [&'static str] =
+        &["id", "next_date", "name", "isin", "ticker", "interval", "amount", "currency"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.next_date.clone(),
+            self.name.clone(),
+            self.isin.clone(),
+            self.ticker.clone(),
+            self.interval.clone(),
+            self.amount.to_string(),
+            self.currency.clone(),
+        ]
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> CliResult<String> {
+    serde_json::to_string_pretty(value).map_err(|e| VaultError::Parse(e.to_string()).into())
+}
+
+fn print_table(columns: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = columns.iter().map(|c| c.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(columns.to_vec());
+    for row in rows {
+        line(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn print_list<T: Tabular>(items: &[T], json: bool) -> CliResult<()> {
+    if json {
+        println!("{}", to_json(&items)?);
+    } else {
+        let rows: Vec<_> = items.iter().map(Tabular::cells).collect();
+        print_table(T::COLUMNS, &rows);
+    }
+    Ok(())
+}
+
+/// Prints a summary as `name  value` lines, or as a JSON object.
+fn print_summary<T: Serialize>(summary: &T, fields: &[(&str, String)], json: bool) -> CliResult<()> {
+    if json {
+        println!("{}", to_json(summary)?);
+    } else {
+        let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        for (name, value) in fields {
+            println!("{name:<width$}  {value}");
+        }
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn export<T: Tabular>(items: &[T], format: &str, output: Option<&str>) -> CliResult<()> {
+    let text = match format {
+        "json" => to_json(&items)? + "\n",
+        _ => {
+            let mut text = T::COLUMNS.join(",") + "\n";
+            for item in items {
+                let record: Vec<_> = item.cells().iter().map(|cell| csv_field(cell)).collect();
+                text += &(record.join(",") + "\n");
+            }
+            text
+        }
+    };
+    match output {
+        Some(path) => fs::write(path, text)?,
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+fn current_month() -> String {
+    chrono::Local::now().format("%Y-%m").to_string()
+}
+
+/// The month named on the command line, or the current one.
+fn month_arg(month: Option<&str>) -> CliResult<String> {
+    let month = month.map_or_else(current_month, str::to_string);
+    if let Err(message) = validation::iso_month(&month) {
+        return Err(VaultError::Validation(vec![FieldError { field: "month".into(), message }]).into());
+    }
+    Ok(month)
+}
+
+/// `Parent/Child` path of a category, up to its top-level ancestor.
+fn category_path(category: &Category, categories: &[Category]) -> String {
+    let mut names = vec![category.name.as_str()];
+    let mut parent_id = category.parent_id.as_deref();
+    // Bounded by the number of categories in case of a cycle.
+    for _ in 0..categories.len() {
+        let Some(parent) = parent_id.and_then(|id| categories.iter().find(|c| c.id == id)) else {
+            break;
+        };
+        names.push(&parent.name);
+        parent_id = parent.parent_id.as_deref();
+    }
+    names.reverse();
+    names.join("/")
+}
+
+/// Id of the category called `name`, ignoring case and archived ones.
+/// Subcategories sharing a name under different parents are told apart by
+/// their path, such as `Food/Snacks`. Reported against `field` when there is
+/// no such category or more than one.
+fn category_named(vault: &Vault<'_>, field: &str, name: &str) -> crate::error::Result<String> {
+    let normalize = |text: &str| {
+        text.split('/').map(|part| part.trim().to_lowercase()).collect::<Vec<_>>().join("/")
+    };
+    let wanted = normalize(name);
+    let categories = vault.list_categories()?;
+    let matching: Vec<(&Category, String)> = categories
+        .iter()
+        .filter(|c| !c.archived)
+        .map(|c| (c, category_path(c, &categories)))
+        .filter(|(c, path)| c.name.to_lowercase() == wanted || normalize(path) == wanted)
+        .collect();
+    let message = match matching.as_slice() {
+        [(category, _)] => return Ok(category.id.clone()),
+        [] => format!("no category named {name}"),
+        _ => {
+            let paths: Vec<&str> = matching.iter().map(|(_, path)| path.as_str()).collect();
+            format!("{name} is ambiguous, use one of {}", paths.join(", "))
+        }
+    };
+    Err(VaultError::Validation(vec![FieldError { field: field.into(), message }]))
+}
+
+fn execute(mut args: Args) -> CliResult<()> {
+    let json = args.json;
+    let words = std::mem::take(&mut args.words);
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    if matches!(words.as_slice(), [] | ["help", ..]) {
+        print!("{USAGE}");
+        return Ok(());
+    }
+    let session = Session::from_args(&mut args)?;
+
+    match words.as_slice() {
+        [entity, "export"] => {
+            let format = args.take("format").unwrap_or_else(|| if json { "json" } else { "csv" }.into());
+            if format != "csv" && format != "json" {
+                return Err(Failure::Usage(format!("unknown export format {format}")));
+            }
+            let output = args.take("output");
+            args.finish()?;
+            let output = output.as_deref();
+            match *entity {
+                "expenses" => export(&session.with_vault(|v| v.list_expenses())?, &format, output),
+                "income" => export(&session.with_vault(|v| v.list_income())?, &format, output),
+                "subscriptions" => {
+                    export(&session.with_vault(|v| v.list_subscriptions())?, &format, output)
+                }
+                "positions" => export(&session.with_vault(|v| v.list_positions())?, &format, output),
+                "savings" => export(&session.with_vault(|v| v.list_savings_plans())?, &format, output),
+                _ => Err(Failure::Usage(format!("nothing to export for {entity}"))),
+            }
+        }
+        ["expenses", "list"] => {
+            let month = args.take("month").map(|month| month_arg(Some(&month))).transpose()?;
+            args.finish()?;
+            let mut expenses = session.with_vault(|v| v.list_expenses())?;
+            if let Some(month) = month {
+                expenses.retain(|expense| expense.date.starts_with(&month));
+            }
+            print_list(&expenses, json)
+        }
+        ["expenses", "add"] => {
+            let mut fields = Fields::new(&mut args);
+            let category = fields.text("category");
+            let input = CreateExpense {
+                title: fields.text("title"),
+                amount: fields.parsed("amount"),
+                currency: fields.optional("currency"),
+                category_id: String::new(),
+                date: fields.text("date"),
+            };
+            fields.finish()?;
+            let expense = session.with_vault(|v| {
+                let category_id = category_named(v, "category", &category)?;
+                v.create_expense(CreateExpense { category_id, ..input })
+            })?;
+            print_list(&[expense], json)
+        }
+        ["categories", "list"] => {
+            args.finish()?;
+            print_list(&session.with_vault(|v| v.list_categories())?, json)
+        }
+        ["categories", "add"] => {
+            let mut fields = Fields::new(&mut args);
+            let parent = fields.optional("parent");
+            let input = CreateCategory {
+                name: fields.text("name"),
+                parent_id: None,
+                color: fields.optional("color"),
+                icon: fields.optional("icon"),
+            };
+            fields.finish()?;
+            let category = session.with_vault(|v| {
+                let parent_id = parent.map(|p| category_named(v, "parent", &p)).transpose()?;
+                v.create_category(CreateCategory { parent_id, ..input })
+            })?;
+            print_list(&[category], json)
+        }
+        ["income", "list"] => {
+            let month = args.take("month").map(|month| month_arg(Some(&month))).transpose()?;
+            args.finish()?;
+            let mut income = session.with_vault(|v| v.list_income())?;
+            if let Some(month) = month {
+                income.retain(|entry| entry.date.starts_with(&month));
+            }
+            print_list(&income, json)
+        }
+        ["income", "add"] => {
+            let mut fields = Fields::new(&mut args);
+            let input = CreateIncome {
+                source: fields.text("source"),
+                amount: fields.parsed("amount"),
+                currency: fields.optional("currency"),
+                date: fields.text("date"),
+            };
+            fields.finish()?;
+            print_list(&[session.with_vault(|v| v.create_income(input))?], json)
+        }
+        ["income", "recurring", "list"] => {
+            args.finish()?;
+            print_list(&session.with_vault(|v| v.list_recurring_income())?, json)
+        }
+        ["income", "recurring", "add"] => {
+            let mut fields = Fields::new(&mut args);
+            let input = CreateRecurringIncome {
+                source: fields.text("source"),
+                amount: fields.parsed("amount"),
+                currency: fields.optional("currency"),
+                interval: fields.text("interval"),
+                next_date: fields.text("next-date"),
+            };
+            fields.finish()?;
+            print_list(&[session.with_vault(|v| v.create_recurring_income(input))?], json)
+        }
+        ["income", "book"] => {
+            args.finish()?;
+            let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+            print_list(&session.with_vault(|v| v.book_recurring_income(&today))?, json)
+        }
+        ["subscriptions", "list"] => {
+            args.finish()?;
+            print_list(&session.with_vault(|v| v.list_subscriptions())?, json)
+        }
+        ["subscriptions", "add"] => {
+            let mut fields = Fields::new(&mut args);
+            let input = CreateSubscription {
+                name: fields.text("name"),
+                amount: fields.parsed("amount"),
+                currency: fields.optional("currency"),
+                billing: fields.text("billing"),
+                next_billing: fields.text("next-billing"),
+            };
+            fields.finish()?;
+            print_list(&[session.with_vault(|v| v.create_subscription(input))?], json)
+        }
+        ["positions", "list"] => {
+            args.finish()?;
+            print_list(&session.with_vault(|v| v.list_positions())?, json)
+        }
+        ["positions", "add"] => {
+            let mut fields = Fields::new(&mut args);
+            let input = CreatePosition {
+                isin: fields.optional("isin").unwrap_or_default(),
+                ticker: fields.text("ticker"),
+                name: fields.text("name"),
+                asset_type: fields.text("asset-type"),
+                quantity: fields.parsed("quantity"),
+                avg_buy_price: fields.parsed("avg-buy-price"),
+                currency: fields.optional("currency"),
+                country: fields.optional("country"),
+            };
+            fields.finish()?;
+            print_list(&[session.with_vault(|v| v.create_position(input))?], json)
+        }
+        ["savings", "list"] => {
+            args.finish()?;
+            print_list(&session.with_vault(|v| v.list_savings_plans())?, json)
+        }
+        ["savings", "add"] => {
+            let mut fields = Fields::new(&mut args);
+            let input = CreateSavingsPlan {
+                name: fields.text("name"),
+                isin: fields.text("isin"),
+                ticker: fields.text("ticker"),
+                amount: fields.parsed("amount"),
+                currency: fields.optional("currency"),
+                interval: fields.text("interval"),
+                next_date: fields.text("next-date"),
+            };
+            fields.finish()?;
+            print_list(&[session.with_vault(|v| v.create_savings_plan(input))?], json)
+        }
+        ["budget", "limit"] => {
+            let mut fields = Fields::new(&mut args);
+            let category = fields.text("category");
+            let amount = fields.parsed("amount");
+            let month = fields.optional("month");
+            fields.finish()?;
+            let month = month_arg(month.as_deref())?;
+            let budget = session.with_vault(|v| {
+                let category_id = category_named(v, "category", &category)?;
+                v.set_category_budget(category_id, month, amount)
+            })?;
+            print_list(&[budget], json)
+        }
+        ["budget", "overspent", month @ ..] if month.len() <= 1 => {
+            let month = month_arg(month.first().copied())?;
+            args.finish()?;
+            print_list(&session.with_vault(|v| v.overspent_categories(month))?, json)
+        }
+        ["budget", "envelopes", month @ ..] if month.len() <= 1 => {
+            let month = month_arg(month.first().copied())?;
+            args.finish()?;
+            let summary: EnvelopeSummary = session.with_vault(|v| v.envelope_summary(month))?;
+            let fields = [
+                ("month", summary.month.clone()),
+                ("income", summary.income.to_string()),
+                ("ready to assign", summary.ready_to_assign.to_string()),
+                ("assigned", summary.assigned.to_string()),
+                ("activity", summary.activity.to_string()),
+                ("available", summary.available.to_string()),
+            ];
+            print_summary(&summary, &fields, json)?;
+            if !json && !summary.categories.is_empty() {
+                println!();
+                print_list(&summary.categories, false)?;
+            }
+            Ok(())
+        }
+        ["budget", "move"] => {
+            let mut fields = Fields::new(&mut args);
+            let from = fields.text("from");
+            let to = fields.text("to");
+            let amount = fields.parsed("amount");
+            let month = fields.optional("month");
+            fields.finish()?;
+            let month = month_arg(month.as_deref())?;
+            let transfer = session.with_vault(|v| {
+                let from = category_named(v, "from", &from)?;
+                let to = category_named(v, "to", &to)?;
+                v.move_between_envelopes(month, from, to, amount)
+            })?;
+            print_list(&[transfer], json)
+        }
+        ["budget", month @ ..] if month.len() <= 1 => {
+            let month = month_arg(month.first().copied())?;
+            args.finish()?;
+            let summary: BudgetSummary = session.with_vault(|v| v.budget_summary(month))?;
+            let fields = [
+                ("month", summary.month.clone()),
+                ("income", summary.income.to_string()),
+                ("planned income", summary.planned_income.to_string()),
+                ("expenses", summary.total_expenses.to_string()),
+                ("subscriptions", summary.total_subscriptions_monthly.to_string()),
+                ("remaining", summary.remaining.to_string()),
+            ];
+            print_summary(&summary, &fields, json)?;
+            if !json && !summary.categories.is_empty() {
+                println!();
+                print_list(&summary.categories, false)?;
+            }
+            Ok(())
+        }
+        ["dashboard", month @ ..] if month.len() <= 1 => {
+            let month = month_arg(month.first().copied())?;
+            args.finish()?;
+            let summary: DashboardSummary = session.with_vault(|v| v.dashboard_summary(&month))?;
+            let fields = [
+                ("budget income", summary.budget_income.to_string()),
+                ("budget expenses", summary.budget_expenses.to_string()),
+                ("budget subscriptions", summary.budget_subscriptions.to_string()),
+                ("budget remaining", summary.budget_remaining.to_string()),
+                ("expenses this month", summary.expense_count_this_month.to_string()),
+                ("subscriptions", summary.subscriptions_count.to_string()),
+                ("savings plans", summary.savings_plans_count.to_string()),
+                ("savings per month", summary.savings_plans_monthly.to_string()),
+                ("positions", summary.portfolio_positions.to_string()),
+                ("invested", summary.portfolio_invested.to_string()),
+            ];
+            print_summary(&summary, &fields, json)
+        }
+        _ => Err(Failure::Usage(format!("unknown command: {}", words.join(" ")))),
+    }
+}
+
+/// Runs the CLI on `args` (without the program name).
+pub fn run(args: impl IntoIterator<Item = String>) -> ExitCode {
+    let args = match Args::parse(args) {
+        Ok(args) => args,
+        Err(failure) => return report(failure, false),
+    };
+    let json = args.json;
+    match execute(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => report(failure, json),
+    }
+}
+
+/// Prints the failure to stderr; in JSON mode vault errors keep the
+/// `{code, message, details}` shape the frontend gets.
+fn report(failure: Failure, json: bool) -> ExitCode {
+    match failure {
+        Failure::Usage(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Failure::Vault(e) => {
+            match serde_json::to_string(&e) {
+                Ok(text) if json => eprintln!("{text}"),
+                _ => eprintln!("error: {e}"),
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::test_support;
+
+    fn args(words: &[&str]) -> CliResult<Args> {
+        Args::parse(words.iter().map(|word| word.to_string()))
+    }
+
+    fn usage_message<T>(result: CliResult<T>) -> String {
+        match result {
+            Err(Failure::Usage(message)) => message,
+            Err(Failure::Vault(e)) => panic!("expected a usage error, got {e}"),
+            Ok(_) => panic!("expected a usage error"),
+        }
+    }
+
+    fn field_message(result: crate::error::Result<String>) -> String {
+        match result {
+            Err(VaultError::Validation(errors)) => errors[0].message.clone(),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn repeated_category_names_need_their_path() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = test_support::category(&vault, "Food");
+        let fun = test_support::category(&vault, "Fun");
+        let child = |parent: &str| CreateCategory {
+            name: "Snacks".into(),
+            parent_id: Some(parent.into()),
+            color: None,
+            icon: None,
+        };
+        let food_snacks = vault.create_category(child(&food)).unwrap().id;
+        vault.create_category(child(&fun)).unwrap();
+
+        assert_eq!(category_named(&vault, "category", " food ").unwrap(), food);
+        assert_eq!(category_named(&vault, "category", "food / snacks").unwrap(), food_snacks);
+        let ambiguous = field_message(category_named(&vault, "category", "Snacks"));
+        assert_eq!(ambiguous, "Snacks is ambiguous, use one of Food/Snacks, Fun/Snacks");
+        let missing = field_message(category_named(&vault, "category", "Rent"));
+        assert_eq!(missing, "no category named Rent");
+    }
+
+    #[test]
+    fn options_take_their_value_inline_or_from_the_next_argument() {
+        let words = ["expenses", "--month=2024-03", "list", "--format", "csv", "--json"];
+        let parsed = args(&words).unwrap();
+        assert_eq!(parsed.words, ["expenses", "list"]);
+        assert_eq!(parsed.options["month"], "2024-03");
+        assert_eq!(parsed.options["format"], "csv");
+        assert!(parsed.json);
+
+        let help = args(&["budget", "--help"]).unwrap();
+        assert_eq!(help.words, ["help", "budget"]);
+    }
+
+    #[test]
+    fn malformed_options_are_usage_errors() {
+        assert_eq!(usage_message(args(&["expenses", "list", "--month"])), "--month needs a value");
+        assert_eq!(
+            usage_message(args(&["--month=2024-03", "--month", "2024-04"])),
+            "--month given more than once"
+        );
+
+        let mut parsed = args(&["expenses", "list", "--mnth", "2024-03"]).unwrap();
+        assert_eq!(parsed.take("month"), None);
+        assert_eq!(usage_message(parsed.finish()), "unknown option --mnth");
+    }
+
+    #[test]
+    fn malformed_command_lines_exit_with_2() {
+        let run_with = |words: &[&str]| run(words.iter().map(|word| word.to_string()));
+        assert_eq!(run_with(&["expenses", "list", "--month"]), ExitCode::from(2));
+        let unknown_option = ["--vault", "/nonexistent/vault.db", "expenses", "list", "--mnth", "x"];
+        assert_eq!(run_with(&unknown_option), ExitCode::from(2));
+        let unknown_command = ["--vault", "/nonexistent/vault.db", "expenses", "remove"];
+        assert_eq!(run_with(&unknown_command), ExitCode::from(2));
+    }
+
+    #[test]
+    fn fields_report_every_missing_or_malformed_option() {
+        let mut parsed = args(&["--amount", "twelve", "--date", "2024-03-05"]).unwrap();
+        let mut fields = Fields::new(&mut parsed);
+        fields.text("title");
+        fields.parsed::<crate::money::Money>("amount");
+        fields.text("date");
+
+        match fields.finish() {
+            Err(Failure::Vault(VaultError::Validation(errors))) => {
+                let names: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+                assert_eq!(names, ["title", "amount"]);
+            }
+            _ => panic!("expected a validation error"),
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("Groceries"), "Groceries");
+        assert_eq!(csv_field("Food, drinks"), "\"Food, drinks\"");
+        assert_eq!(csv_field("the \"good\" one"), "\"the \"\"good\"\" one\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn export_writes_a_header_and_one_record_per_entry() {
+        let income = Income {
+            id: "i1".into(),
+            source: "Salary, March".into(),
+            amount: "3200".parse().unwrap(),
+            currency: "EUR".into(),
+            date: "2024-03-28".into(),
+            created_at: "2024-03-28T10:00:00Z".into(),
+        };
+        let path = env::temp_dir().join(format!("fv-export-{}.csv", uuid::Uuid::new_v4()));
+        export(&[income], "csv", path.to_str()).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            text,
+            "id,date,source,amount,currency\ni1,2024-03-28,\"Salary, March\",3200,EUR\n"
+        );
+    }
+
+    #[test]
+    fn month_defaults_to_the_current_one() {
+        assert_eq!(month_arg(Some("2024-03")).ok(), Some("2024-03".to_string()));
+        assert!(matches!(month_arg(Some("2024-3")), Err(Failure::Vault(VaultError::Validation(_)))));
+        assert_eq!(month_arg(None).ok(), Some(current_month()));
+    }
+}
@@ -0,0 +1,900 @@
+//! Opt-in HTTP API for scripts and other local tools. It listens on
+//! 127.0.0.1 only, takes JSON-RPC 2.0 calls on `POST /rpc` named like the
+//! Tauri commands, and answers them through the same unlocked session, so a
+//! locked vault refuses every call. Requests must carry the bearer token
+//! from `regenerate_api_token`; only its hash is stored. Every call is
+//! written to `api_audit_log`.
+
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Local, Utc};
+use rand::RngCore;
+use rusqlite::Connection;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager, State};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::commands::auth::VaultState;
+use crate::commands::backup::sha256_hex;
+use crate::db::{self, meta_get, meta_set};
+use crate::error::{Result, VaultError};
+use crate::money::Money;
+use crate::validation::{Check, Validator};
+use crate::vault::Vault;
+
+const DEFAULT_API_PORT: u16 = 8749;
+/// Request line and headers together.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+/// A client gets this long to send its whole request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Older audit entries are dropped as new ones are written.
+const AUDIT_LOG_KEEP: i64 = 10_000;
+/// Requests turned away before the RPC layer, e.g. without the token, are
+/// kept apart and fewer of them, so flooding them leaves real calls alone.
+const REJECTED_LOG_KEEP: i64 = 1_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Whether a token has been generated; the token itself is only shown
+    /// once, by `regenerate_api_token`.
+    #[serde(default, skip_deserializing)]
+    pub has_token: bool,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        ApiSettings {
+            enabled: false,
+            port: DEFAULT_API_PORT,
+            has_token: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub called_at: String,
+    /// The RPC method, or `-` when the request never got that far.
+    pub method: String,
+    /// `ok`, or the code of the error the caller got.
+    pub outcome: String,
+}
+
+/// The running server, if any, and the hash requests are checked against.
+#[derive(Default)]
+pub struct ApiState {
+    server: Mutex<Option<(u16, JoinHandle<()>)>>,
+    token_hash: Mutex<Option<String>>,
+}
+
+pub fn load_api_settings(conn: &Connection) -> Result<ApiSettings> {
+    let defaults = ApiSettings::default();
+    Ok(ApiSettings {
+        enabled: meta_get(conn, "api_enabled")?.map_or(defaults.enabled, |v| v != "0"),
+        port: meta_get(conn, "api_port")?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.port),
+        has_token: meta_get(conn, "api_token_hash")?.is_some(),
+    })
+}
+
+/// Starts the server if the vault at the app's data path has it enabled.
+/// Settings are stored unencrypted, so this works before the first unlock.
+pub fn spawn_api_server(app: AppHandle) {
+    let db_path = app.state::<VaultState>().db_path.clone();
+    if !db_path.exists() {
+        return;
+    }
+    let Ok(conn) = db::open(&db_path) else { return };
+    let Ok(settings) = load_api_settings(&conn) else {
+        return;
+    };
+    let api = app.state::<ApiState>();
+    *api.token_hash.lock().unwrap() = meta_get(&conn, "api_token_hash").ok().flatten();
+    // Nothing to report a bind failure to at startup; saving the settings
+    // again surfaces it.
+    let _ = apply_settings(&app, &api, &settings);
+}
+
+/// Starts, stops or moves the server to match `settings`.
+fn apply_settings(app: &AppHandle, api: &ApiState, settings: &ApiSettings) -> Result<()> {
+    let mut server = api.server.lock().unwrap();
+    if let Some((port, _)) = &*server {
+        if settings.enabled && *port == settings.port {
+            return Ok(());
+        }
+    }
+    if let Some((_, task)) = server.take() {
+        task.abort();
+    }
+    if !settings.enabled {
+        return Ok(());
+    }
+    // Bound here rather than in the task so a taken port is reported.
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, settings.port))?;
+    listener.set_nonblocking(true)?;
+    let task = tauri::async_runtime::spawn(serve(app.clone(), listener));
+    *server = Some((settings.port, task));
+    Ok(())
+}
+
+async fn serve(app: AppHandle, listener: std::net::TcpListener) {
+    let Ok(listener) = TcpListener::from_std(listener) else {
+        return;
+    };
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tauri::async_runtime::spawn(serve_connection(app.clone(), stream));
+            }
+            // Usually out of file descriptors; back off instead of spinning.
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+}
+
+async fn serve_connection(app: AppHandle, mut stream: TcpStream) {
+    let token_hash = app.state::<ApiState>().token_hash.lock().unwrap().clone();
+    let request = tokio::time::timeout(
+        READ_TIMEOUT,
+        read_request(&mut stream, token_hash.as_deref()),
+    )
+    .await
+    .unwrap_or(Err(HttpError::Timeout));
+    let handler_app = app.clone();
+    let (status, body) = tauri::async_runtime::spawn_blocking(move || {
+        let state = handler_app.state::<VaultState>();
+        respond(&state, request)
+    })
+    .await
+    .unwrap_or_else(|_| {
+        (
+            500,
+            json!({ "code": "internal", "message": "internal error" }),
+        )
+    });
+    let _ = stream.write_all(&http_response(status, &body)).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Answers an already read request and records it in the audit log.
+fn respond(state: &VaultState, request: std::result::Result<Vec<u8>, HttpError>) -> (u16, Value) {
+    let (status, body, method, outcome, rejected) = match request {
+        Ok(body) => {
+            let reply = rpc(state, &body);
+            (200, reply.body, reply.method, reply.outcome, false)
+        }
+        Err(e) => (e.status(), e.body(), "-".to_string(), e.code().to_string(), true),
+    };
+    record_call(state, &method, &outcome, rejected);
+    (status, body)
+}
+
+fn record_call(state: &VaultState, method: &str, outcome: &str, rejected: bool) {
+    // The session's connection when unlocked, so the write never waits on
+    // it. A locked vault whose schema predates the log has nowhere to put
+    // the entry, and a failed write must not change the caller's answer.
+    let _ = match state.conn() {
+        Ok(conn) => append_audit_entry(&conn, method, outcome, rejected),
+        Err(_) => db::open(&state.db_path)
+            .and_then(|conn| append_audit_entry(&conn, method, outcome, rejected)),
+    };
+}
+
+/// Writes one entry, then drops the oldest of its kind beyond what is kept.
+fn append_audit_entry(
+    conn: &Connection,
+    method: &str,
+    outcome: &str,
+    rejected: bool,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO api_audit_log (called_at, method, outcome, rejected)
+         VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![Utc::now().to_rfc3339(), method, outcome, rejected],
+    )?;
+    let keep = if rejected { REJECTED_LOG_KEEP } else { AUDIT_LOG_KEEP };
+    conn.execute(
+        "DELETE FROM api_audit_log WHERE rejected = ?1 AND id <= (
+             SELECT id FROM api_audit_log WHERE rejected = ?1
+             ORDER BY id DESC LIMIT 1 OFFSET ?2
+         )",
+        rusqlite::params![rejected, keep],
+    )?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HttpError {
+    BadRequest,
+    Unauthorized,
+    /// Browsers send `Origin`; refusing it keeps web pages from calling in.
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    Timeout,
+    LengthRequired,
+    PayloadTooLarge,
+}
+
+impl HttpError {
+    fn status(self) -> u16 {
+        match self {
+            HttpError::BadRequest => 400,
+            HttpError::Unauthorized => 401,
+            HttpError::Forbidden => 403,
+            HttpError::NotFound => 404,
+            HttpError::MethodNotAllowed => 405,
+            HttpError::Timeout => 408,
+            HttpError::LengthRequired => 411,
+            HttpError::PayloadTooLarge => 413,
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            HttpError::BadRequest => "bad_request",
+            HttpError::Unauthorized => "unauthorized",
+            HttpError::Forbidden => "forbidden",
+            HttpError::NotFound => "not_found",
+            HttpError::MethodNotAllowed => "method_not_allowed",
+            HttpError::Timeout => "timeout",
+            HttpError::LengthRequired => "length_required",
+            HttpError::PayloadTooLarge => "payload_too_large",
+        }
+    }
+
+    fn body(self) -> Value {
+        json!({ "code": self.code(), "message": reason(self.status()) })
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+fn http_response(status: u16, body: &Value) -> Vec<u8> {
+    let body = body.to_string();
+    let mut response = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        reason(status),
+        body.len(),
+    );
+    if status == 405 {
+        response.push_str("Allow: POST\r\n");
+    }
+    response.push_str("\r\n");
+    response.push_str(&body);
+    response.into_bytes()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Head {
+    content_length: usize,
+    /// The bearer token, if the request sent one.
+    token: Option<String>,
+}
+
+/// Reads one request and returns its body once the head has passed
+/// `parse_head` and the token matches `token_hash`.
+async fn read_request(
+    stream: &mut TcpStream,
+    token_hash: Option<&str>,
+) -> std::result::Result<Vec<u8>, HttpError> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_len = loop {
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Err(HttpError::BadRequest);
+        }
+        let n = stream
+            .read(&mut chunk)
+            .await
+            .map_err(|_| HttpError::BadRequest)?;
+        if n == 0 {
+            return Err(HttpError::BadRequest);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    let head = parse_head(&buf[..head_len])?;
+    authorize(&head, token_hash)?;
+
+    let mut body = buf.split_off(head_len);
+    while body.len() < head.content_length {
+        let n = stream
+            .read(&mut chunk)
+            .await
+            .map_err(|_| HttpError::BadRequest)?;
+        if n == 0 {
+            return Err(HttpError::BadRequest);
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(head.content_length);
+    Ok(body)
+}
+
+fn parse_head(head: &[u8]) -> std::result::Result<Head, HttpError> {
+    let head = std::str::from_utf8(head).map_err(|_| HttpError::BadRequest)?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target), Some(version), None) = (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) else {
+        return Err(HttpError::BadRequest);
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(HttpError::BadRequest);
+    }
+    if target != "/rpc" {
+        return Err(HttpError::NotFound);
+    }
+    if method != "POST" {
+        return Err(HttpError::MethodNotAllowed);
+    }
+
+    let mut content_length = None;
+    let mut token = None;
+    for line in lines.filter(|l| !l.is_empty()) {
+        let (name, value) = line.split_once(':').ok_or(HttpError::BadRequest)?;
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "origin" => return Err(HttpError::Forbidden),
+            "transfer-encoding" => return Err(HttpError::LengthRequired),
+            "content-length" => {
+                let length: usize = value.parse().map_err(|_| HttpError::BadRequest)?;
+                if content_length.is_some_and(|l| l != length) {
+                    return Err(HttpError::BadRequest);
+                }
+                content_length = Some(length);
+            }
+            "authorization" => {
+                token = value.strip_prefix("Bearer ").map(|t| t.trim().to_string());
+            }
+            _ => {}
+        }
+    }
+    let content_length = content_length.ok_or(HttpError::LengthRequired)?;
+    if content_length > MAX_BODY_BYTES {
+        return Err(HttpError::PayloadTooLarge);
+    }
+    Ok(Head {
+        content_length,
+        token,
+    })
+}
+
+fn authorize(head: &Head, token_hash: Option<&str>) -> std::result::Result<(), HttpError> {
+    match (&head.token, token_hash) {
+        (Some(token), Some(hash)) if digests_match(&sha256_hex(token.as_bytes()), hash) => Ok(()),
+        _ => Err(HttpError::Unauthorized),
+    }
+}
+
+/// Compares every byte rather than stopping at the first difference, so
+/// response times say nothing about how much of a guess was right.
+fn digests_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcCall {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, PartialEq)]
+struct RpcError {
+    code: i64,
+    /// What the audit log records as the outcome.
+    outcome: String,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, outcome: &str, message: String) -> Self {
+        RpcError {
+            code,
+            outcome: outcome.to_string(),
+            message,
+            data: None,
+        }
+    }
+}
+
+/// Vault errors keep their `{code, message, details}` shape under `data`.
+impl From<VaultError> for RpcError {
+    fn from(e: VaultError) -> Self {
+        RpcError {
+            code: -32000,
+            outcome: e.code().to_string(),
+            message: e.to_string(),
+            data: serde_json::to_value(&e).ok(),
+        }
+    }
+}
+
+struct Reply {
+    method: String,
+    outcome: String,
+    body: Value,
+}
+
+fn rpc(state: &VaultState, body: &[u8]) -> Reply {
+    let call = match serde_json::from_slice::<Value>(body) {
+        Err(e) => Err((
+            Value::Null,
+            RpcError::new(-32700, "parse_error", e.to_string()),
+        )),
+        Ok(value) => match serde_json::from_value::<RpcCall>(value) {
+            Ok(call) if call.jsonrpc == "2.0" => Ok(call),
+            Ok(call) => Err((
+                call.id,
+                RpcError::new(-32600, "invalid_request", "jsonrpc must be \"2.0\"".into()),
+            )),
+            Err(e) => Err((
+                Value::Null,
+                RpcError::new(-32600, "invalid_request", e.to_string()),
+            )),
+        },
+    };
+    let (id, method, result) = match call {
+        Ok(call) => {
+            let result = state
+                .with_vault(|vault| Ok(dispatch(vault, &call.method, call.params)))
+                .unwrap_or_else(|e| Err(e.into()));
+            (call.id, call.method, result)
+        }
+        Err((id, e)) => (id, "-".to_string(), Err(e)),
+    };
+    match result {
+        Ok(result) => Reply {
+            method,
+            outcome: "ok".to_string(),
+            body: json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        },
+        Err(e) => {
+            let mut error = json!({ "code": e.code, "message": e.message });
+            if let Some(data) = e.data {
+                error["data"] = data;
+            }
+            Reply {
+                method,
+                outcome: e.outcome,
+                body: json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct IdParams {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct InputParams<T> {
+    input: T,
+}
+
+#[derive(Deserialize)]
+struct UpdateParams<T> {
+    id: String,
+    input: T,
+}
+
+#[derive(Deserialize)]
+struct MergeParams {
+    sources: Vec<String>,
+    into: String,
+}
+
+#[derive(Deserialize)]
+struct MonthParams {
+    month: String,
+}
+
+#[derive(Deserialize)]
+struct BudgetMonthParams {
+    month: String,
+    income: Money,
+}
+
+#[derive(Deserialize)]
+struct CategoryBudgetParams {
+    category_id: String,
+    month: String,
+    amount: Money,
+}
+
+#[derive(Deserialize)]
+struct EnvelopeMoveParams {
+    month: String,
+    from_category_id: String,
+    to_category_id: String,
+    amount: Money,
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    serde_json::from_value(params)
+        .map_err(|e| RpcError::new(-32602, "invalid_params", e.to_string()))
+}
+
+fn reply<T: Serialize>(result: Result<T>) -> std::result::Result<Value, RpcError> {
+    serde_json::to_value(result?).map_err(|e| RpcError::new(-32603, "internal", e.to_string()))
+}
+
+/// Runs one call against the unlocked vault. Method names and parameters
+/// match the Tauri commands of the same name.
+fn dispatch(vault: &Vault<'_>, method: &str, p: Value) -> std::result::Result<Value, RpcError> {
+    match method {
+        "get_expenses" => reply(vault.list_expenses()),
+        "create_expense" => reply(vault.create_expense(params::<InputParams<_>>(p)?.input)),
+        "update_expense" => {
+            let p: UpdateParams<_> = params(p)?;
+            reply(vault.update_expense(&p.id, p.input))
+        }
+        "delete_expense" => reply(vault.delete_expense(&params::<IdParams>(p)?.id)),
+        "restore_expense" => reply(vault.restore_expense(&params::<IdParams>(p)?.id)),
+
+        "get_categories" => reply(vault.list_categories()),
+        "create_category" => reply(vault.create_category(params::<InputParams<_>>(p)?.input)),
+        "update_category" => {
+            let p: UpdateParams<_> = params(p)?;
+            reply(vault.update_category(&p.id, p.input))
+        }
+        "delete_category" => reply(vault.delete_category(&params::<IdParams>(p)?.id)),
+        "merge_categories" => {
+            let p: MergeParams = params(p)?;
+            reply(vault.merge_categories(p.sources, &p.into))
+        }
+
+        "get_subscriptions" => reply(vault.list_subscriptions()),
+        "create_subscription" => {
+            reply(vault.create_subscription(params::<InputParams<_>>(p)?.input))
+        }
+        "update_subscription" => {
+            let p: UpdateParams<_> = params(p)?;
+            reply(vault.update_subscription(&p.id, p.input))
+        }
+        "delete_subscription" => reply(vault.delete_subscription(&params::<IdParams>(p)?.id)),
+        "restore_subscription" => reply(vault.restore_subscription(&params::<IdParams>(p)?.id)),
+
+        "get_positions" => reply(vault.list_positions()),
+        "create_position" => reply(vault.create_position(params::<InputParams<_>>(p)?.input)),
+        "update_position" => {
+            let p: UpdateParams<_> = params(p)?;
+            reply(vault.update_position(&p.id, p.input))
+        }
+        "delete_position" => reply(vault.delete_position(&params::<IdParams>(p)?.id)),
+        "restore_position" => reply(vault.restore_position(&params::<IdParams>(p)?.id)),
+
+        "get_savings_plans" => reply(vault.list_savings_plans()),
+        "create_savings_plan" => {
+            reply(vault.create_savings_plan(params::<InputParams<_>>(p)?.input))
+        }
+        "update_savings_plan" => {
+            let p: UpdateParams<_> = params(p)?;
+            reply(vault.update_savings_plan(&p.id, p.input))
+        }
+        "delete_savings_plan" => reply(vault.delete_savings_plan(&params::<IdParams>(p)?.id)),
+        "restore_savings_plan" => reply(vault.restore_savings_plan(&params::<IdParams>(p)?.id)),
+
+        "get_income" => reply(vault.list_income()),
+        "create_income" => reply(vault.create_income(params::<InputParams<_>>(p)?.input)),
+        "update_income" => {
+            let p: UpdateParams<_> = params(p)?;
+            reply(vault.update_income(&p.id, p.input))
+        }
+        "delete_income" => reply(vault.delete_income(&params::<IdParams>(p)?.id)),
+        "restore_income" => reply(vault.restore_income(&params::<IdParams>(p)?.id)),
+        "get_recurring_income" => reply(vault.list_recurring_income()),
+        "create_recurring_income" => {
+            reply(vault.create_recurring_income(params::<InputParams<_>>(p)?.input))
+        }
+        "update_recurring_income" => {
+            let p: UpdateParams<_> = params(p)?;
+            reply(vault.update_recurring_income(&p.id, p.input))
+        }
+        "delete_recurring_income" => {
+            reply(vault.delete_recurring_income(&params::<IdParams>(p)?.id))
+        }
+        "restore_recurring_income" => {
+            reply(vault.restore_recurring_income(&params::<IdParams>(p)?.id))
+        }
+        "book_recurring_income" => {
+            let today = Local::now().format("%Y-%m-%d").to_string();
+            reply(vault.book_recurring_income(&today))
+        }
+
+        "get_budget_months" => reply(vault.budget_months()),
+        "upsert_budget_month" => {
+            let p: BudgetMonthParams = params(p)?;
+            reply(vault.upsert_budget_month(p.month, p.income))
+        }
+        "get_budget_summary" => reply(vault.budget_summary(params::<MonthParams>(p)?.month)),
+        "get_category_budgets" => reply(vault.category_budgets(params::<MonthParams>(p)?.month)),
+        "set_category_budget" => {
+            let p: CategoryBudgetParams = params(p)?;
+            reply(vault.set_category_budget(p.category_id, p.month, p.amount))
+        }
+        "delete_category_budget" => reply(vault.delete_category_budget(&params::<IdParams>(p)?.id)),
+        "get_overspent_categories" => {
+            reply(vault.overspent_categories(params::<MonthParams>(p)?.month))
+        }
+        "get_envelope_summary" => reply(vault.envelope_summary(params::<MonthParams>(p)?.month)),
+        "get_envelope_transfers" => {
+            reply(vault.envelope_transfers(params::<MonthParams>(p)?.month))
+        }
+        "move_between_envelopes" => {
+            let p: EnvelopeMoveParams = params(p)?;
+            let (from, to) = (p.from_category_id, p.to_category_id);
+            reply(vault.move_between_envelopes(p.month, from, to, p.amount))
+        }
+
+        "get_dashboard_summary" => {
+            let current_month = Local::now().format("%Y-%m").to_string();
+            reply(vault.dashboard_summary(&current_month))
+        }
+
+        _ => Err(RpcError::new(
+            -32601,
+            "method_not_found",
+            format!("unknown method {method}"),
+        )),
+    }
+}
+
+fn unprivileged_port(port: u16) -> Check {
+    if port < 1024 {
+        return Err("must be between 1024 and 65535".into());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_api_settings(state: State<'_, VaultState>) -> Result<ApiSettings> {
+    let conn = state.conn()?;
+    state.require_unlocked()?;
+    load_api_settings(&conn)
+}
+
+/// Saves the settings and starts, stops or moves the server to match.
+#[tauri::command]
+pub fn set_api_settings(
+    settings: ApiSettings,
+    app: AppHandle,
+    state: State<'_, VaultState>,
+    api: State<'_, ApiState>,
+) -> Result<()> {
+    state.require_unlocked()?;
+    let conn = state.conn()?;
+    let has_token = meta_get(&conn, "api_token_hash")?.is_some();
+    let token_first = if settings.enabled && !has_token {
+        Err("generate a token before enabling the API".into())
+    } else {
+        Ok(())
+    };
+    Validator::new()
+        .check("port", unprivileged_port(settings.port))
+        .check("enabled", token_first)
+        .finish()?;
+
+    apply_settings(&app, &api, &settings)?;
+    meta_set(
+        &conn,
+        "api_enabled",
+        if settings.enabled { "1" } else { "0" },
+    )?;
+    meta_set(&conn, "api_port", &settings.port.to_string())?;
+    Ok(())
+}
+
+/// Replaces the API token and returns the new one. This is the only time
+/// it is shown; callers holding the old token are refused from now on.
+#[tauri::command]
+pub fn regenerate_api_token(
+    state: State<'_, VaultState>,
+    api: State<'_, ApiState>,
+) -> Result<String> {
+    state.require_unlocked()?;
+    let conn = state.conn()?;
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = URL_SAFE_NO_PAD.encode(bytes);
+    let hash = sha256_hex(token.as_bytes());
+    meta_set(&conn, "api_token_hash", &hash)?;
+    *api.token_hash.lock().unwrap() = Some(hash);
+    Ok(token)
+}
+
+/// The newest `limit` API calls, newest first.
+#[tauri::command]
+pub fn get_api_audit_log(limit: u32, state: State<'_, VaultState>) -> Result<Vec<AuditEntry>> {
+    let conn = state.conn()?;
+    state.require_unlocked()?;
+    list_audit_log(&conn, limit)
+}
+
+fn list_audit_log(conn: &Connection, limit: u32) -> Result<Vec<AuditEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, called_at, method, outcome FROM api_audit_log ORDER BY id DESC LIMIT ?1",
+    )?;
+    let entries = stmt
+        .query_map([limit], |row| {
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                called_at: row.get(1)?,
+                method: row.get(2)?,
+                outcome: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::test_support;
+
+    fn head(lines: &[&str]) -> Vec<u8> {
+        format!("{}\r\n\r\n", lines.join("\r\n")).into_bytes()
+    }
+
+    #[test]
+    fn head_needs_post_to_rpc_with_a_length() {
+        let ok = head(&[
+            "POST /rpc HTTP/1.1",
+            "Content-Length: 2",
+            "Authorization: Bearer abc",
+        ]);
+        assert_eq!(
+            parse_head(&ok),
+            Ok(Head {
+                content_length: 2,
+                token: Some("abc".into())
+            })
+        );
+
+        let get = head(&["GET /rpc HTTP/1.1"]);
+        assert_eq!(parse_head(&get), Err(HttpError::MethodNotAllowed));
+        let elsewhere = head(&["POST / HTTP/1.1", "Content-Length: 2"]);
+        assert_eq!(parse_head(&elsewhere), Err(HttpError::NotFound));
+        let no_length = head(&["POST /rpc HTTP/1.1"]);
+        assert_eq!(parse_head(&no_length), Err(HttpError::LengthRequired));
+        let too_long = head(&["POST /rpc HTTP/1.1", "Content-Length: 2000000"]);
+        assert_eq!(parse_head(&too_long), Err(HttpError::PayloadTooLarge));
+    }
+
+    #[test]
+    fn browser_requests_are_refused() {
+        let from_page = head(&[
+            "POST /rpc HTTP/1.1",
+            "Content-Length: 2",
+            "Origin: https://example.com",
+        ]);
+        assert_eq!(parse_head(&from_page), Err(HttpError::Forbidden));
+    }
+
+    #[test]
+    fn rejected_requests_do_not_push_answered_calls_out_of_the_log() {
+        let (conn, _) = test_support::open();
+        append_audit_entry(&conn, "get_expenses", "ok", false).unwrap();
+        for _ in 0..REJECTED_LOG_KEEP + 5 {
+            append_audit_entry(&conn, "-", "unauthorized", true).unwrap();
+        }
+
+        let entries = list_audit_log(&conn, u32::MAX).unwrap();
+        assert_eq!(entries.len() as i64, REJECTED_LOG_KEEP + 1);
+        assert_eq!(entries.last().unwrap().method, "get_expenses");
+    }
+
+    #[test]
+    fn only_the_current_token_is_accepted() {
+        let hash = sha256_hex(b"secret");
+        let with = |token: Option<&str>| Head {
+            content_length: 0,
+            token: token.map(Into::into),
+        };
+
+        assert_eq!(authorize(&with(Some("secret")), Some(&hash)), Ok(()));
+        assert_eq!(
+            authorize(&with(Some("guess")), Some(&hash)),
+            Err(HttpError::Unauthorized)
+        );
+        assert_eq!(
+            authorize(&with(None), Some(&hash)),
+            Err(HttpError::Unauthorized)
+        );
+        assert_eq!(
+            authorize(&with(Some("secret")), None),
+            Err(HttpError::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn calls_reach_the_vault_by_command_name() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = dispatch(&vault, "create_category", json!({ "input": { "name": "Food" } })).unwrap();
+        let input = json!({ "input": {
+            "title": "Lunch", "amount": 12.5, "currency": null,
+            "category_id": food["id"], "date": "2024-03-05",
+        }});
+
+        let created = dispatch(&vault, "create_expense", input).unwrap();
+        let listed = dispatch(&vault, "get_expenses", Value::Null).unwrap();
+        assert_eq!(listed[0]["id"], created["id"]);
+        assert_eq!(listed[0]["amount"], 12.5);
+    }
+
+    #[test]
+    fn bad_calls_get_json_rpc_errors() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+
+        let unknown = dispatch(&vault, "import_pdf", Value::Null).unwrap_err();
+        assert_eq!(unknown.code, -32601);
+        let missing = dispatch(&vault, "delete_expense", json!({})).unwrap_err();
+        assert_eq!(missing.code, -32602);
+        let gone = dispatch(&vault, "restore_expense", json!({ "id": "nope" })).unwrap_err();
+        assert_eq!((gone.code, gone.outcome.as_str()), (-32000, "not_found"));
+    }
+
+    #[test]
+    fn a_locked_vault_refuses_calls() {
+        let state = VaultState::new("/nonexistent/vault.db".into());
+
+        let reply = rpc(
+            &state,
+            br#"{"jsonrpc":"2.0","id":1,"method":"get_expenses"}"#,
+        );
+        assert_eq!(reply.outcome, "locked");
+        assert_eq!(reply.body["id"], 1);
+        assert_eq!(reply.body["error"]["data"]["code"], "locked");
+
+        let garbled = rpc(&state, b"{");
+        assert_eq!(
+            (
+                garbled.method.as_str(),
+                garbled.body["error"]["code"].as_i64()
+            ),
+            ("-", Some(-32700))
+        );
+    }
+}
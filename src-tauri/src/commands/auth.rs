@@ -1,39 +1,281 @@
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use rusqlite::{Connection, TransactionBehavior};
+use serde::{Deserialize, Serialize};
 use tauri::State;
-use totp_rs::{Algorithm, Secret, TOTP};
+use zeroize::Zeroizing;
 
-use crate::commands::totp::{load_totp_state, TotpState};
-use crate::crypto::{decrypt, derive_key, encrypt, generate_salt_b64, DerivedKey};
-use crate::db;
+use crate::commands::auto_lock::{load_auto_lock_settings, AutoLockSettings};
+use crate::commands::backup;
+use crate::commands::totp::{check_second_factor, load_totp_state, TotpState};
+use crate::crypto::{
+    benchmark_kdf, decrypt, derive_key, encrypt, generate_salt_b64, DerivedKey, KdfParams,
+    KDF_ALGORITHM, KDF_VERSION,
+};
+use crate::db::{self, meta_get, meta_set};
 use crate::error::{Result, VaultError};
+use crate::validation::{self, Validator};
+use crate::vault::{trash, Vault};
 
 pub struct VaultState {
     pub key: Mutex<Option<DerivedKey>>,
+    /// Open for as long as the vault is unlocked. Lock it before `key` when
+    /// both are needed, so a command never pairs a connection with a key
+    /// that `change_passphrase` is replacing.
+    conn: Mutex<Option<Connection>>,
     pub db_path: PathBuf,
+    pub last_activity: Mutex<Instant>,
+    pub blurred_since: Mutex<Option<Instant>>,
+    pub auto_lock: Mutex<AutoLockSettings>,
 }
 
-fn meta_get(conn: &rusqlite::Connection, key: &str) -> rusqlite::Result<Option<String>> {
-    conn.query_row(
-        "SELECT value FROM vault_meta WHERE key = ?1",
-        [key],
-        |row| row.get::<_, String>(0),
-    )
-    .map(Some)
-    .or_else(|e| {
-        if matches!(e, rusqlite::Error::QueryReturnedNoRows) {
-            Ok(None)
-        } else {
-            Err(e)
+impl VaultState {
+    pub fn new(db_path: PathBuf) -> Self {
+        VaultState {
+            key: Mutex::new(None),
+            conn: Mutex::new(None),
+            db_path,
+            last_activity: Mutex::new(Instant::now()),
+            blurred_since: Mutex::new(None),
+            auto_lock: Mutex::new(AutoLockSettings::default()),
+        }
+    }
+
+    /// Marks the session as active, resetting the idle auto-lock timer.
+    pub fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    /// The session's connection, held exclusively until the guard drops.
+    pub fn conn(&self) -> Result<ConnGuard<'_>> {
+        let guard = self.conn.lock().unwrap();
+        if guard.is_none() {
+            return Err(VaultError::Locked);
         }
+        Ok(ConnGuard(guard))
+    }
+
+    /// The session key. Counts as activity, so every command that needs the
+    /// vault unlocked goes through here.
+    pub fn require_unlocked(&self) -> Result<DerivedKey> {
+        let key = self.key.lock().unwrap().clone().ok_or(VaultError::Locked)?;
+        self.touch();
+        Ok(key)
+    }
+
+    /// Runs `f` against the unlocked vault, holding the connection for the
+    /// whole call.
+    pub fn with_vault<T>(&self, f: impl FnOnce(&Vault<'_>) -> Result<T>) -> Result<T> {
+        let conn = self.conn()?;
+        let key = self.require_unlocked()?;
+        f(&Vault::new(&conn, &key))
+    }
+
+    fn open_session(&self, conn: Connection, key: DerivedKey) {
+        *self.conn.lock().unwrap() = Some(conn);
+        *self.key.lock().unwrap() = Some(key);
+        self.touch();
+    }
+}
+
+pub struct ConnGuard<'a>(MutexGuard<'a, Option<Connection>>);
+
+impl Deref for ConnGuard<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.0.as_ref().expect("checked by VaultState::conn")
+    }
+}
+
+impl DerefMut for ConnGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.0.as_mut().expect("checked by VaultState::conn")
+    }
+}
+
+/// Forgets the key and TOTP secret and closes the session's connection.
+/// Shared by `lock` and the auto-lock watcher.
+pub fn lock_session(state: &VaultState, totp_state: &TotpState) {
+    *state.key.lock().unwrap() = None;
+    *state.conn.lock().unwrap() = None;
+    *totp_state.secret.lock().unwrap() = None;
+    *totp_state.enabled.lock().unwrap() = false;
+}
+
+/// Reads the KDF parameters the vault was created with. Vaults from before
+/// they were stored get `KdfParams::DEFAULT`, which is what they used.
+pub fn load_kdf_params(conn: &rusqlite::Connection) -> Result<KdfParams> {
+    let Some(algorithm) = meta_get(conn, "kdf_algorithm")? else {
+        return Ok(KdfParams::DEFAULT);
+    };
+    let version = meta_get(conn, "kdf_version")?.unwrap_or_default();
+    if algorithm != KDF_ALGORITHM || version != KDF_VERSION.to_string() {
+        return Err(VaultError::Crypto(format!(
+            "unsupported kdf {algorithm} version {version}"
+        )));
+    }
+
+    let cost = |name: &str| -> Result<u32> {
+        meta_get(conn, name)?
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| VaultError::Crypto(format!("missing {name}")))
+    };
+    Ok(KdfParams {
+        m_cost: cost("kdf_m_cost")?,
+        t_cost: cost("kdf_t_cost")?,
+        p_cost: cost("kdf_p_cost")?,
     })
 }
 
-fn meta_set(conn: &rusqlite::Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+fn store_kdf_params(conn: &rusqlite::Connection, params: &KdfParams) -> Result<()> {
+    meta_set(conn, "kdf_algorithm", KDF_ALGORITHM)?;
+    meta_set(conn, "kdf_version", &KDF_VERSION.to_string())?;
+    meta_set(conn, "kdf_m_cost", &params.m_cost.to_string())?;
+    meta_set(conn, "kdf_t_cost", &params.t_cost.to_string())?;
+    meta_set(conn, "kdf_p_cost", &params.p_cost.to_string())?;
+    Ok(())
+}
+
+/// Checks that `key` decrypts the canary stored in `conn`.
+pub fn verify_key(conn: &rusqlite::Connection, key: &DerivedKey) -> Result<()> {
+    let canary = meta_get(conn, "canary")?.ok_or(VaultError::NotInitialized)?;
+    let plaintext = decrypt(key, &canary)?;
+
+    if plaintext.as_slice() != b"financevault" {
+        return Err(VaultError::InvalidPassphrase);
+    }
+    Ok(())
+}
+
+/// Derives the key for `passphrase` and checks it against the stored canary.
+pub fn verify_passphrase(conn: &rusqlite::Connection, passphrase: &str) -> Result<DerivedKey> {
+    let salt = meta_get(conn, "salt")?.ok_or(VaultError::NotInitialized)?;
+    let params = load_kdf_params(conn)?;
+
+    let key = derive_key(passphrase, &salt, &params)?;
+    verify_key(conn, &key)?;
+    Ok(key)
+}
+
+/// Derives a new key from `passphrase` with a fresh salt and `params`, and
+/// re-encrypts the vault under it in one transaction. Nothing changes on disk
+/// unless the transaction commits.
+fn rekey_vault(
+    conn: &mut rusqlite::Connection,
+    old_key: &DerivedKey,
+    passphrase: &str,
+    params: &KdfParams,
+) -> Result<DerivedKey> {
+    let salt = generate_salt_b64();
+    let new_key = derive_key(passphrase, &salt, params)?;
+
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    db::rekey(&tx, old_key, &new_key)?;
+    meta_set(&tx, "salt", &salt)?;
+    meta_set(&tx, "canary", &encrypt(&new_key, b"financevault")?)?;
+    store_kdf_params(&tx, params)?;
+    tx.commit()?;
+
+    Ok(new_key)
+}
+
+/// Checks the passphrase and, if enabled, the TOTP or recovery code. Returns
+/// the key and the TOTP secret when 2FA is on.
+pub fn authenticate(
+    conn: &rusqlite::Connection,
+    passphrase: &str,
+    totp_code: Option<&str>,
+) -> Result<(DerivedKey, Option<Zeroizing<String>>)> {
+    let key = verify_passphrase(conn, passphrase)?;
+
+    let (totp_enabled, totp_secret) = load_totp_state(conn, &key)?;
+    if !totp_enabled {
+        return Ok((key, None));
+    }
+    let secret = totp_secret.ok_or(VaultError::InvalidTotp)?;
+    let code = totp_code.ok_or(VaultError::InvalidTotp)?;
+    check_second_factor(conn, &secret, code)?;
+    Ok((key, Some(secret)))
+}
+
+/// Failed attempts that are not delayed at all.
+const FREE_UNLOCK_ATTEMPTS: u32 = 3;
+const MAX_UNLOCK_BACKOFF_SECS: u64 = 15 * 60;
+
+/// Optional hard cooldown after a number of failed unlocks, on top of the
+/// exponential backoff.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LockoutPolicy {
+    pub threshold: Option<u32>,
+    pub cooldown_secs: u64,
+}
+
+fn load_lockout_policy(conn: &rusqlite::Connection) -> Result<LockoutPolicy> {
+    Ok(LockoutPolicy {
+        threshold: meta_get(conn, "lockout_threshold")?.and_then(|v| v.parse().ok()),
+        cooldown_secs: meta_get(conn, "lockout_cooldown_secs")?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0),
+    })
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Refuses to check credentials while the delay earned by previous failures
+/// is running. State lives in `vault_meta`, so restarting the app does not
+/// reset it.
+pub fn check_unlock_throttle(conn: &rusqlite::Connection) -> Result<()> {
+    let failures: u32 = meta_get(conn, "unlock_failures")?
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    if failures == 0 {
+        return Ok(());
+    }
+    let last_failure: u64 = meta_get(conn, "unlock_last_failure")?
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    let mut delay = match failures.checked_sub(FREE_UNLOCK_ATTEMPTS) {
+        Some(over) => 2u64.saturating_pow(over).min(MAX_UNLOCK_BACKOFF_SECS),
+        None => 0,
+    };
+    let policy = load_lockout_policy(conn)?;
+    if policy.threshold.is_some_and(|threshold| failures >= threshold) {
+        delay = delay.max(policy.cooldown_secs);
+    }
+
+    let elapsed = unix_now().saturating_sub(last_failure);
+    if elapsed < delay {
+        return Err(VaultError::Throttled {
+            retry_after_secs: delay - elapsed,
+        });
+    }
+    Ok(())
+}
+
+pub fn record_unlock_failure(conn: &rusqlite::Connection) -> Result<()> {
     conn.execute(
-        "INSERT INTO vault_meta (key, value) VALUES (?1, ?2)
-         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
-        [key, value],
+        "INSERT INTO vault_meta (key, value) VALUES ('unlock_failures', '1')
+         ON CONFLICT(key) DO UPDATE SET value = CAST(value AS INTEGER) + 1",
+        [],
+    )?;
+    meta_set(conn, "unlock_last_failure", &unix_now().to_string())?;
+    Ok(())
+}
+
+fn clear_unlock_failures(conn: &rusqlite::Connection) -> Result<()> {
+    conn.execute(
+        "DELETE FROM vault_meta WHERE key IN ('unlock_failures', 'unlock_last_failure')",
+        [],
     )?;
     Ok(())
 }
@@ -49,83 +291,196 @@ pub fn is_locked(state: State<'_, VaultState>) -> bool {
 }
 
 #[tauri::command]
-pub fn setup_vault(passphrase: String, state: State<'_, VaultState>) -> Result<()> {
+pub fn setup_vault(
+    passphrase: Zeroizing<String>,
+    kdf_target_ms: Option<u64>,
+    state: State<'_, VaultState>,
+) -> Result<()> {
     if state.db_path.exists() {
-        return Err(VaultError::Crypto("vault already exists".into()));
+        return Err(VaultError::AlreadyInitialized);
     }
+    Validator::new()
+        .check_opt("kdf_target_ms", kdf_target_ms, validation::kdf_target_ms)
+        .finish()?;
 
+    let params = match kdf_target_ms {
+        Some(ms) => benchmark_kdf(Duration::from_millis(ms))?,
+        None => KdfParams::DEFAULT,
+    };
     let salt = generate_salt_b64();
-    let key = derive_key(&passphrase, &salt)?;
+    let key = derive_key(&passphrase, &salt, &params)?;
 
-    let conn = db::open(&state.db_path)?;
-    db::migrate(&conn)?;
+    let mut conn = db::open(&state.db_path)?;
+    db::migrate(&mut conn)?;
     meta_set(&conn, "salt", &salt)?;
+    store_kdf_params(&conn, &params)?;
 
     let canary = encrypt(&key, b"financevault")?;
     meta_set(&conn, "canary", &canary)?;
+    db::migrate_data(&mut conn, &key)?;
 
-    *state.key.lock().unwrap() = Some(key);
+    *state.auto_lock.lock().unwrap() = load_auto_lock_settings(&conn)?;
+    state.open_session(conn, key);
     Ok(())
 }
 
-#[tauri::command]
-pub fn unlock(
-    passphrase: String,
-    totp_code: Option<String>,
-    state: State<'_, VaultState>,
-    totp_state: State<'_, TotpState>,
-) -> Result<()> {
-    if !state.db_path.exists() {
+/// Opens the vault at `db_path` with the passphrase and, when 2FA is on, a
+/// TOTP or recovery code, and brings its schema and data up to date. Failed
+/// attempts count towards the unlock throttle. Returns the connection, the
+/// key and the TOTP secret; shared by `unlock` and the CLI.
+pub fn open_vault(
+    db_path: &Path,
+    passphrase: &str,
+    totp_code: Option<&str>,
+) -> Result<(Connection, DerivedKey, Option<Zeroizing<String>>)> {
+    if !db_path.exists() {
         return Err(VaultError::NotInitialized);
     }
 
-    let conn = db::open(&state.db_path)?;
+    let mut conn = db::open(db_path)?;
+    check_unlock_throttle(&conn)?;
 
-    let salt = meta_get(&conn, "salt")?.ok_or(VaultError::NotInitialized)?;
-    let canary = meta_get(&conn, "canary")?.ok_or(VaultError::NotInitialized)?;
+    let (key, totp_secret) = match authenticate(&conn, passphrase, totp_code) {
+        Ok(authenticated) => authenticated,
+        Err(e @ (VaultError::InvalidPassphrase | VaultError::InvalidTotp)) => {
+            record_unlock_failure(&conn)?;
+            return Err(e);
+        }
+        Err(e) => return Err(e),
+    };
+    clear_unlock_failures(&conn)?;
 
-    let key = derive_key(&passphrase, &salt)?;
-    let plaintext = decrypt(&key, &canary)?;
+    db::migrate(&mut conn)?;
+    db::migrate_data(&mut conn, &key)?;
+    Ok((conn, key, totp_secret))
+}
 
-    if plaintext != b"financevault" {
-        return Err(VaultError::InvalidPassphrase);
+/// What `unlock` could not finish; the vault is open either way.
+#[derive(Debug, Default, Serialize)]
+pub struct UnlockReport {
+    /// Why booking recurring income failed. It is tried again on the next
+    /// unlock.
+    pub income_booking_error: Option<VaultError>,
+}
+
+#[tauri::command]
+pub fn unlock(
+    passphrase: Zeroizing<String>,
+    totp_code: Option<String>,
+    kdf_target_ms: Option<u64>,
+    state: State<'_, VaultState>,
+    totp_state: State<'_, TotpState>,
+) -> Result<UnlockReport> {
+    Validator::new()
+        .check_opt("kdf_target_ms", kdf_target_ms, validation::kdf_target_ms)
+        .finish()?;
+    let (mut conn, key, totp_secret) =
+        open_vault(&state.db_path, &passphrase, totp_code.as_deref())?;
+
+    let mut params = load_kdf_params(&conn)?;
+    if meta_get(&conn, "kdf_algorithm")?.is_none() {
+        store_kdf_params(&conn, &params)?;
     }
 
-    let (totp_enabled, totp_secret) = load_totp_state(&conn, &key)?;
-    if totp_enabled {
-        let secret = totp_secret.ok_or(VaultError::InvalidTotp)?;
-        let code = totp_code.ok_or(VaultError::InvalidTotp)?;
-
-        let bytes = Secret::Encoded(secret.clone())
-            .to_bytes()
-            .map_err(|e| VaultError::Crypto(e.to_string()))?;
-        let totp = TOTP::new(
-            Algorithm::SHA1,
-            6,
-            1,
-            30,
-            bytes,
-            Some("FinanceVault".to_string()),
-            "FinanceVault".to_string(),
-        )
-        .map_err(|e| VaultError::Crypto(e.to_string()))?;
-
-        if !totp.check_current(&code).unwrap_or(false) {
-            return Err(VaultError::InvalidTotp);
+    // Upgrading needs the plaintext passphrase, so it can only happen here.
+    let key = match kdf_target_ms {
+        Some(ms) => {
+            let benchmarked = benchmark_kdf(Duration::from_millis(ms))?;
+            if benchmarked.cost() > params.cost() {
+                params = benchmarked;
+                rekey_vault(&mut conn, &key, &passphrase, &params)?
+            } else {
+                key
+            }
         }
+        None => key,
+    };
 
+    *state.auto_lock.lock().unwrap() = load_auto_lock_settings(&conn)?;
+    if let Some(secret) = totp_secret {
         *totp_state.enabled.lock().unwrap() = true;
         *totp_state.secret.lock().unwrap() = Some(secret);
     }
+    // A failed snapshot must not keep the owner out of their vault.
+    let _ = backup::take_auto_backup(&conn, &state.db_path);
+    // Purged after the snapshot, so it still holds the expired rows.
+    let _ = trash::purge_trash(&conn);
+    // Recurring income due while the vault was locked, by the owner's
+    // calendar.
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let report = UnlockReport {
+        income_booking_error: Vault::new(&conn, &key).book_recurring_income(&today).err(),
+    };
 
-    *state.key.lock().unwrap() = Some(key);
-    Ok(())
+    state.open_session(conn, key);
+    Ok(report)
 }
 
 #[tauri::command]
 pub fn lock(state: State<'_, VaultState>, totp_state: State<'_, TotpState>) -> Result<()> {
-    *state.key.lock().unwrap() = None;
-    *totp_state.secret.lock().unwrap() = None;
-    *totp_state.enabled.lock().unwrap() = false;
+    lock_session(&state, &totp_state);
+    Ok(())
+}
+
+/// Re-keys the vault under `new_passphrase`. The new salt, canary and all
+/// re-encrypted values are written in one transaction; the key in memory is
+/// only swapped after it commits, so a failure leaves the old vault intact.
+#[tauri::command]
+pub fn change_passphrase(
+    old_passphrase: Zeroizing<String>,
+    new_passphrase: Zeroizing<String>,
+    state: State<'_, VaultState>,
+) -> Result<()> {
+    // Both held for the whole re-key so no other command encrypts with the old key.
+    let mut conn = state.conn()?;
+    let mut key_guard = state.key.lock().unwrap();
+    if key_guard.is_none() {
+        return Err(VaultError::Locked);
+    }
+    state.touch();
+
+    let old_key = verify_passphrase(&conn, &old_passphrase)?;
+    let params = load_kdf_params(&conn)?;
+
+    *key_guard = Some(rekey_vault(&mut conn, &old_key, &new_passphrase, &params)?);
+    Ok(())
+}
+
+/// Returns the parameters the vault was derived with.
+#[tauri::command]
+pub fn get_kdf_params(state: State<'_, VaultState>) -> Result<KdfParams> {
+    state.require_unlocked()?;
+    let conn = state.conn()?;
+    load_kdf_params(&conn)
+}
+
+/// Benchmarks this machine and returns parameters that take about
+/// `target_ms` to derive a key. Pass the same target to `unlock` to apply them.
+#[tauri::command]
+pub fn kdf_benchmark(target_ms: u64) -> Result<KdfParams> {
+    Validator::new().check("target_ms", validation::kdf_target_ms(target_ms)).finish()?;
+    benchmark_kdf(Duration::from_millis(target_ms))
+}
+
+#[tauri::command]
+pub fn get_lockout_policy(state: State<'_, VaultState>) -> Result<LockoutPolicy> {
+    state.require_unlocked()?;
+    let conn = state.conn()?;
+    load_lockout_policy(&conn)
+}
+
+/// Sets the cooldown required after `threshold` failed unlocks. A `None`
+/// threshold turns the cooldown off; the exponential backoff always applies.
+#[tauri::command]
+pub fn set_lockout_policy(policy: LockoutPolicy, state: State<'_, VaultState>) -> Result<()> {
+    state.require_unlocked()?;
+    let conn = state.conn()?;
+    match policy.threshold {
+        Some(threshold) => meta_set(&conn, "lockout_threshold", &threshold.to_string())?,
+        None => {
+            conn.execute("DELETE FROM vault_meta WHERE key = 'lockout_threshold'", [])?;
+        }
+    }
+    meta_set(&conn, "lockout_cooldown_secs", &policy.cooldown_secs.to_string())?;
     Ok(())
 }
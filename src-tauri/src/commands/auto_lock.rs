@@ -0,0 +1,140 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::commands::auth::{lock_session, VaultState};
+use crate::commands::totp::TotpState;
+use crate::db::{meta_get, meta_set};
+use crate::error::Result;
+
+/// Emitted with a `LockReason` whenever the vault locks itself.
+pub const VAULT_LOCKED_EVENT: &str = "vault-locked";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Two checks this far apart, on either clock, mean the machine was
+/// suspended. Comparing the clocks alone is not enough: on Windows and macOS
+/// `Instant` keeps running during sleep.
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoLockSettings {
+    /// Lock after this many seconds without a command. `None` disables it.
+    pub idle_timeout_secs: Option<u64>,
+    /// Lock after the window has been unfocused this long. `None` disables it.
+    pub blur_timeout_secs: Option<u64>,
+}
+
+impl Default for AutoLockSettings {
+    fn default() -> Self {
+        AutoLockSettings {
+            idle_timeout_secs: Some(15 * 60),
+            blur_timeout_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockReason {
+    Idle,
+    Blur,
+    Suspend,
+}
+
+/// Reads the settings from `vault_meta`. A stored `0` means disabled.
+pub fn load_auto_lock_settings(conn: &rusqlite::Connection) -> Result<AutoLockSettings> {
+    let defaults = AutoLockSettings::default();
+    let secs = |name: &str, default: Option<u64>| -> Result<Option<u64>> {
+        Ok(match meta_get(conn, name)?.and_then(|v| v.parse::<u64>().ok()) {
+            Some(0) => None,
+            Some(secs) => Some(secs),
+            None => default,
+        })
+    };
+    Ok(AutoLockSettings {
+        idle_timeout_secs: secs("auto_lock_idle_secs", defaults.idle_timeout_secs)?,
+        blur_timeout_secs: secs("auto_lock_blur_secs", defaults.blur_timeout_secs)?,
+    })
+}
+
+#[tauri::command]
+pub fn get_auto_lock(state: State<'_, VaultState>) -> Result<AutoLockSettings> {
+    state.require_unlocked()?;
+    Ok(*state.auto_lock.lock().unwrap())
+}
+
+#[tauri::command]
+pub fn set_auto_lock(settings: AutoLockSettings, state: State<'_, VaultState>) -> Result<()> {
+    state.require_unlocked()?;
+
+    let conn = state.conn()?;
+    meta_set(
+        &conn,
+        "auto_lock_idle_secs",
+        &settings.idle_timeout_secs.unwrap_or(0).to_string(),
+    )?;
+    meta_set(
+        &conn,
+        "auto_lock_blur_secs",
+        &settings.blur_timeout_secs.unwrap_or(0).to_string(),
+    )?;
+    *state.auto_lock.lock().unwrap() = settings;
+    Ok(())
+}
+
+/// Records whether the main window has focus, for the blur timeout.
+pub fn window_focus_changed(state: &VaultState, focused: bool) {
+    *state.blurred_since.lock().unwrap() = if focused { None } else { Some(Instant::now()) };
+}
+
+fn due_reason(state: &VaultState, slept: bool) -> Option<LockReason> {
+    if slept {
+        return Some(LockReason::Suspend);
+    }
+    let settings = *state.auto_lock.lock().unwrap();
+
+    let idle = state.last_activity.lock().unwrap().elapsed();
+    if settings
+        .idle_timeout_secs
+        .is_some_and(|secs| idle >= Duration::from_secs(secs))
+    {
+        return Some(LockReason::Idle);
+    }
+
+    let blurred = state.blurred_since.lock().unwrap().map(|since| since.elapsed());
+    if let (Some(blurred), Some(secs)) = (blurred, settings.blur_timeout_secs) {
+        if blurred >= Duration::from_secs(secs) {
+            return Some(LockReason::Blur);
+        }
+    }
+    None
+}
+
+/// Starts the background task that locks the vault after inactivity, after
+/// the window stayed unfocused too long, or after a system suspend.
+pub fn spawn_watcher(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        let mut last_tick = (Instant::now(), SystemTime::now());
+
+        loop {
+            ticker.tick().await;
+            let now = (Instant::now(), SystemTime::now());
+            let monotonic = now.0.duration_since(last_tick.0);
+            let wall = now.1.duration_since(last_tick.1).unwrap_or_default();
+            last_tick = now;
+
+            let vault = app.state::<VaultState>();
+            if vault.key.lock().unwrap().is_none() {
+                continue;
+            }
+
+            let slept = monotonic.max(wall) >= SUSPEND_THRESHOLD;
+            if let Some(reason) = due_reason(&vault, slept) {
+                lock_session(&vault, &app.state::<TotpState>());
+                let _ = app.emit(VAULT_LOCKED_EVENT, reason);
+            }
+        }
+    });
+}
@@ -0,0 +1,655 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDateTime, TimeDelta, Utc};
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager, State};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::commands::auth::{
+    authenticate, check_unlock_throttle, load_kdf_params, lock_session, record_unlock_failure,
+    verify_key, verify_passphrase, VaultState,
+};
+use crate::commands::totp::TotpState;
+use crate::crypto::{decrypt, derive_key, encrypt, DerivedKey, KdfParams, KDF_ALGORITHM, KDF_VERSION};
+use crate::db::{self, meta_get, meta_set};
+use crate::error::{Result, VaultError};
+use crate::validation::{Check, Validator};
+
+const BACKUP_FORMAT: &str = "financevault-backup";
+const BACKUP_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupKdf {
+    algorithm: String,
+    version: u32,
+    #[serde(flatten)]
+    params: KdfParams,
+}
+
+/// On-disk backup: a JSON document that carries everything needed to derive
+/// the key from the passphrase, plus the encrypted SQLite snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupFile {
+    format: String,
+    version: u32,
+    created_at: String,
+    kdf: BackupKdf,
+    salt: String,
+    /// Hex SHA-256 of the decrypted snapshot.
+    checksum: String,
+    /// The snapshot, encrypted with the vault key in the `crypto::encrypt` format.
+    payload: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    /// Swap the whole vault for the backup. The vault is locked afterwards
+    /// and opens with the passphrase the backup was made with.
+    Replace,
+    /// Copy rows whose id is missing from the current vault into it,
+    /// re-encrypted with the current key.
+    Merge,
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A scratch file next to the vault, so renames stay on one filesystem.
+fn scratch_path(db_path: &Path, label: &str) -> PathBuf {
+    db_path.with_file_name(format!("vault-{label}-{}.db", Uuid::new_v4()))
+}
+
+fn remove_db_files(path: &Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.as_os_str().to_owned();
+        file.push(suffix);
+        let _ = fs::remove_file(PathBuf::from(file));
+    }
+}
+
+#[tauri::command]
+pub fn export_backup(path: String, state: State<'_, VaultState>) -> Result<()> {
+    let conn = state.conn()?;
+    let key = state.require_unlocked()?;
+    let salt = meta_get(&conn, "salt")?.ok_or(VaultError::NotInitialized)?;
+    let params = load_kdf_params(&conn)?;
+
+    let snapshot_path = scratch_path(&state.db_path, "export");
+    db::snapshot(&conn, &snapshot_path)?;
+    let bytes = fs::read(&snapshot_path);
+    remove_db_files(&snapshot_path);
+    let bytes = Zeroizing::new(bytes?);
+
+    let backup = BackupFile {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: Utc::now().to_rfc3339(),
+        kdf: BackupKdf {
+            algorithm: KDF_ALGORITHM.to_string(),
+            version: KDF_VERSION,
+            params,
+        },
+        salt,
+        checksum: sha256_hex(&bytes),
+        payload: encrypt(&key, &bytes)?,
+    };
+    let json = serde_json::to_vec_pretty(&backup).map_err(|e| VaultError::Parse(e.to_string()))?;
+
+    // Written next to the target first so a failed write never leaves a
+    // truncated backup under the requested name.
+    let partial = PathBuf::from(format!("{path}.partial"));
+    fs::write(&partial, json)?;
+    fs::rename(&partial, &path)?;
+    Ok(())
+}
+
+fn check_integrity(conn: &rusqlite::Connection) -> Result<()> {
+    let integrity: String = conn.query_row("PRAGMA integrity_check", [], |r| r.get(0))?;
+    if integrity != "ok" {
+        return Err(VaultError::Crypto(format!("backup is corrupted: {integrity}")));
+    }
+    Ok(())
+}
+
+/// Reads and checks a backup file, writes the decrypted snapshot to a scratch
+/// file and brings it up to the current schema. Returns the scratch path and
+/// the key the backup is encrypted with.
+fn open_backup(path: &str, passphrase: &str, db_path: &Path) -> Result<(PathBuf, DerivedKey)> {
+    let json = fs::read(path)?;
+    let backup: BackupFile = serde_json::from_slice(&json)
+        .map_err(|e| VaultError::Parse(format!("not a FinanceVault backup: {e}")))?;
+
+    if backup.format != BACKUP_FORMAT {
+        return Err(VaultError::Parse("not a FinanceVault backup".into()));
+    }
+    if backup.version > BACKUP_VERSION {
+        return Err(VaultError::UnsupportedSchema { version: backup.version });
+    }
+    if backup.kdf.algorithm != KDF_ALGORITHM || backup.kdf.version != KDF_VERSION {
+        return Err(VaultError::Parse(format!(
+            "unsupported kdf {} version {}",
+            backup.kdf.algorithm, backup.kdf.version
+        )));
+    }
+
+    let key = derive_key(passphrase, &backup.salt, &backup.kdf.params)?;
+    let bytes = decrypt(&key, &backup.payload)?;
+    if sha256_hex(&bytes) != backup.checksum {
+        return Err(VaultError::Crypto("backup checksum mismatch".into()));
+    }
+
+    let snapshot_path = scratch_path(db_path, "restore");
+    fs::write(&snapshot_path, &*bytes)?;
+
+    let prepared = (|| -> Result<()> {
+        let mut snapshot = db::open(&snapshot_path)?;
+        check_integrity(&snapshot)?;
+        verify_key(&snapshot, &key)?;
+        db::migrate(&mut snapshot)?;
+        db::migrate_data(&mut snapshot, &key)?;
+        Ok(())
+    })();
+
+    match prepared {
+        Ok(()) => Ok((snapshot_path, key)),
+        Err(e) => {
+            remove_db_files(&snapshot_path);
+            Err(e)
+        }
+    }
+}
+
+/// Columns besides `id` that identify a row; a backup row matching one in
+/// the vault on them is left out like one with the same id.
+const NATURAL_KEYS: &[(&str, &[&str])] = &[
+    ("budget", &["month"]),
+    ("category_budgets", &["category_id", "month"]),
+];
+
+/// Copies rows that only exist in the attached `backup` database into the
+/// vault, moving encrypted columns from `backup_key` to `key`.
+fn merge_rows(conn: &rusqlite::Connection, backup_key: &DerivedKey, key: &DerivedKey) -> Result<()> {
+    // Subcategories may come before their parent; references are checked
+    // when the transaction commits.
+    conn.pragma_update(None, "defer_foreign_keys", true)?;
+    for (table, encrypted) in db::ENCRYPTED_COLUMNS {
+        // The backup's history describes a different sequence of changes;
+        // mixing it in would let undo revert changes this vault never had.
+        if *table == "change_log" {
+            continue;
+        }
+        let natural_key = NATURAL_KEYS
+            .iter()
+            .find(|(name, _)| name == table)
+            .map(|(_, columns)| {
+                let matching: Vec<String> = columns.iter().map(|c| format!("m.{c} = b.{c}")).collect();
+                format!(
+                    " AND NOT EXISTS (SELECT 1 FROM main.{table} m WHERE {})",
+                    matching.join(" AND ")
+                )
+            })
+            .unwrap_or_default();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT b.* FROM backup.{table} b
+             WHERE b.id NOT IN (SELECT id FROM main.{table}){natural_key}"
+        ))?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let rows = stmt
+            .query_map([], |row| {
+                (0..columns.len())
+                    .map(|i| row.get::<_, Value>(i))
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{i}")).collect();
+        let insert = format!(
+            "INSERT INTO main.{table} ({}) VALUES ({})",
+            columns.join(", "),
+            placeholders.join(", ")
+        );
+        for mut values in rows {
+            for (value, column) in values.iter_mut().zip(&columns) {
+                if let (true, Value::Text(encoded)) = (encrypted.contains(&column.as_str()), &*value) {
+                    *value = Value::Text(encrypt(key, &decrypt(backup_key, encoded)?)?);
+                }
+            }
+            conn.execute(&insert, rusqlite::params_from_iter(values))?;
+        }
+    }
+    Ok(())
+}
+
+/// Locks the vault and moves `replacement` over it. The vault being replaced
+/// is kept next to it as `vault-pre-restore-<timestamp>.db`.
+fn replace_vault(state: &VaultState, totp_state: &TotpState, replacement: &Path) -> Result<()> {
+    if state.db_path.exists() {
+        let stamp = Utc::now().format("%Y%m%d-%H%M%S");
+        let kept = state.db_path.with_file_name(format!("vault-pre-restore-{stamp}.db"));
+        // A snapshot includes anything still in the WAL; a vault too damaged
+        // for that is kept byte for byte instead.
+        let snapshotted = db::open(&state.db_path).and_then(|conn| db::snapshot(&conn, &kept));
+        if snapshotted.is_err() {
+            remove_db_files(&kept);
+            fs::copy(&state.db_path, &kept)?;
+        }
+    }
+    lock_session(state, totp_state);
+    remove_db_files(&state.db_path);
+    fs::rename(replacement, &state.db_path)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn restore_backup(
+    path: String,
+    passphrase: Zeroizing<String>,
+    mode: RestoreMode,
+    state: State<'_, VaultState>,
+    totp_state: State<'_, TotpState>,
+) -> Result<()> {
+    // Without an existing vault anyone may restore; otherwise only its owner.
+    let vault_exists = state.db_path.exists();
+    if vault_exists {
+        state.require_unlocked()?;
+    }
+
+    let (snapshot_path, backup_key) = open_backup(&path, &passphrase, &state.db_path)?;
+
+    let result = match mode {
+        RestoreMode::Merge if vault_exists => (|| -> Result<()> {
+            let mut conn = state.conn()?;
+            let key = state.require_unlocked()?;
+            conn.execute("ATTACH DATABASE ?1 AS backup", [snapshot_path.to_string_lossy()])?;
+            let merged = (|| -> Result<()> {
+                let tx = conn.transaction()?;
+                merge_rows(&tx, &backup_key, &key)?;
+                tx.commit()?;
+                Ok(())
+            })();
+            conn.execute("DETACH DATABASE backup", [])?;
+            merged
+        })(),
+        RestoreMode::Merge | RestoreMode::Replace => {
+            replace_vault(&state, &totp_state, &snapshot_path)
+        }
+    };
+
+    remove_db_files(&snapshot_path);
+    result
+}
+
+const SNAPSHOT_PREFIX: &str = "vault-";
+const SNAPSHOT_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// How often an unlocked session checks whether the daily snapshot is due.
+const AUTO_BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoBackupSettings {
+    pub enabled: bool,
+    /// Directory for snapshots, relative to the app data directory.
+    pub directory: String,
+    /// Keep the newest snapshot of each of this many most recent days.
+    pub keep_daily: u32,
+    /// Keep the newest snapshot of each of this many most recent ISO weeks.
+    pub keep_weekly: u32,
+}
+
+impl Default for AutoBackupSettings {
+    fn default() -> Self {
+        AutoBackupSettings {
+            enabled: true,
+            directory: "backups".to_string(),
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupEntry {
+    pub file_name: String,
+    pub created_at: String,
+    pub size_bytes: u64,
+}
+
+pub fn load_auto_backup_settings(conn: &rusqlite::Connection) -> Result<AutoBackupSettings> {
+    let defaults = AutoBackupSettings::default();
+    let count = |name: &str, default: u32| -> Result<u32> {
+        Ok(meta_get(conn, name)?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default))
+    };
+    Ok(AutoBackupSettings {
+        enabled: meta_get(conn, "auto_backup_enabled")?.map_or(defaults.enabled, |v| v != "0"),
+        directory: meta_get(conn, "auto_backup_dir")?.unwrap_or(defaults.directory),
+        keep_daily: count("auto_backup_keep_daily", defaults.keep_daily)?,
+        keep_weekly: count("auto_backup_keep_weekly", defaults.keep_weekly)?,
+    })
+}
+
+/// Settings of the vault at `db_path`, or the defaults when it cannot be
+/// read, which is exactly when its snapshots are needed.
+fn current_auto_backup_settings(db_path: &Path) -> AutoBackupSettings {
+    db::open(db_path)
+        .map_err(VaultError::from)
+        .and_then(|conn| load_auto_backup_settings(&conn))
+        .unwrap_or_default()
+}
+
+fn auto_backup_dir(db_path: &Path, settings: &AutoBackupSettings) -> PathBuf {
+    db_path.parent().unwrap_or(Path::new(".")).join(&settings.directory)
+}
+
+fn snapshot_time(file_name: &str) -> Option<NaiveDateTime> {
+    let stamp = file_name.strip_prefix(SNAPSHOT_PREFIX)?.strip_suffix(".db")?;
+    NaiveDateTime::parse_from_str(stamp, SNAPSHOT_TIME_FORMAT).ok()
+}
+
+/// Snapshots in `dir` with their UTC creation time, newest first.
+fn list_snapshots(dir: &Path) -> Result<Vec<(NaiveDateTime, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if let Some(time) = entry.file_name().to_str().and_then(snapshot_time) {
+            snapshots.push((time, entry.path()));
+        }
+    }
+    snapshots.sort_by_key(|(time, _)| Reverse(*time));
+    Ok(snapshots)
+}
+
+/// Deletes every snapshot that is neither the newest of one of the last
+/// `keep_daily` days nor the newest of one of the last `keep_weekly` weeks.
+fn prune_snapshots(dir: &Path, settings: &AutoBackupSettings) -> Result<()> {
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    for (time, path) in list_snapshots(dir)? {
+        let day = time.date();
+        let week = (day.iso_week().year(), day.iso_week().week());
+        let mut keep = false;
+        if days.len() < settings.keep_daily as usize && days.insert(day) {
+            keep = true;
+        }
+        if weeks.len() < settings.keep_weekly as usize && weeks.insert(week) {
+            keep = true;
+        }
+        if !keep {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a snapshot of the vault into the backup directory and applies the
+/// retention policy. Does nothing when automatic backups are turned off.
+pub fn take_auto_backup(conn: &rusqlite::Connection, db_path: &Path) -> Result<()> {
+    let settings = load_auto_backup_settings(conn)?;
+    if !settings.enabled {
+        return Ok(());
+    }
+    let dir = auto_backup_dir(db_path, &settings);
+    fs::create_dir_all(&dir)?;
+
+    let name = format!("{SNAPSHOT_PREFIX}{}.db", Utc::now().format(SNAPSHOT_TIME_FORMAT));
+    let partial = dir.join(format!("{name}.partial"));
+    remove_db_files(&partial);
+    db::snapshot(conn, &partial)?;
+    fs::rename(&partial, dir.join(&name))?;
+
+    prune_snapshots(&dir, &settings)
+}
+
+/// Takes an automatic backup when the newest one is more than a day old.
+fn auto_backup_if_due(state: &VaultState) -> Result<()> {
+    let conn = state.conn()?;
+    let settings = load_auto_backup_settings(&conn)?;
+    if !settings.enabled {
+        return Ok(());
+    }
+    let newest = list_snapshots(&auto_backup_dir(&state.db_path, &settings))?
+        .first()
+        .map(|(time, _)| *time);
+    if newest.is_none_or(|time| Utc::now().naive_utc() - time >= TimeDelta::days(1)) {
+        take_auto_backup(&conn, &state.db_path)?;
+    }
+    Ok(())
+}
+
+/// Starts the background task that keeps a daily snapshot while the vault is
+/// unlocked. Unlocking takes one as well.
+pub fn spawn_auto_backup(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(AUTO_BACKUP_CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            // Locked vaults are skipped, and there is nothing to report a
+            // failure to; the next check tries again.
+            let _ = auto_backup_if_due(&app.state::<VaultState>());
+        }
+    });
+}
+
+fn backup_dir_inside_data_dir(directory: &str) -> Check {
+    let inside_data_dir = !directory.is_empty()
+        && Path::new(directory)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+    if !inside_data_dir {
+        return Err("must be a relative path inside the app data directory".into());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_auto_backup(state: State<'_, VaultState>) -> Result<AutoBackupSettings> {
+    let conn = state.conn()?;
+    state.require_unlocked()?;
+    load_auto_backup_settings(&conn)
+}
+
+#[tauri::command]
+pub fn set_auto_backup(settings: AutoBackupSettings, state: State<'_, VaultState>) -> Result<()> {
+    state.require_unlocked()?;
+    let keeps_a_snapshot = if settings.keep_daily == 0 && settings.keep_weekly == 0 {
+        Err("daily and weekly retention must not both be zero".into())
+    } else {
+        Ok(())
+    };
+    Validator::new()
+        .check("directory", backup_dir_inside_data_dir(&settings.directory))
+        .check("keep_daily", keeps_a_snapshot)
+        .finish()?;
+
+    let conn = state.conn()?;
+    meta_set(&conn, "auto_backup_enabled", if settings.enabled { "1" } else { "0" })?;
+    meta_set(&conn, "auto_backup_dir", &settings.directory)?;
+    meta_set(&conn, "auto_backup_keep_daily", &settings.keep_daily.to_string())?;
+    meta_set(&conn, "auto_backup_keep_weekly", &settings.keep_weekly.to_string())?;
+    Ok(())
+}
+
+/// Lists automatic snapshots, newest first. Works while locked so a vault
+/// that no longer opens can still be recovered.
+#[tauri::command]
+pub fn list_backups(state: State<'_, VaultState>) -> Result<Vec<BackupEntry>> {
+    let settings = current_auto_backup_settings(&state.db_path);
+    list_snapshots(&auto_backup_dir(&state.db_path, &settings))?
+        .into_iter()
+        .map(|(time, path)| {
+            Ok(BackupEntry {
+                file_name: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                created_at: time.and_utc().to_rfc3339(),
+                size_bytes: fs::metadata(&path)?.len(),
+            })
+        })
+        .collect()
+}
+
+/// Replaces the vault with an automatic snapshot. The passphrase must open
+/// the snapshot, which is what authorizes the restore: the current vault
+/// may be the damaged one. While the current vault is readable and has TOTP
+/// on, it also needs `totp_code` and the passphrase must open it as well,
+/// as for `unlock`.
+#[tauri::command]
+pub fn restore_auto_backup(
+    file_name: String,
+    passphrase: Zeroizing<String>,
+    totp_code: Option<String>,
+    state: State<'_, VaultState>,
+    totp_state: State<'_, TotpState>,
+) -> Result<()> {
+    if snapshot_time(&file_name).is_none() {
+        return Err(VaultError::NotFound { entity: "backup", id: file_name });
+    }
+    let settings = current_auto_backup_settings(&state.db_path);
+    let source = auto_backup_dir(&state.db_path, &settings).join(&file_name);
+
+    // Failed attempts count against the current vault's throttle when it is
+    // still readable.
+    let current = db::open(&state.db_path)
+        .ok()
+        .filter(|conn| meta_get(conn, "salt").is_ok());
+    if let Some(conn) = &current {
+        check_unlock_throttle(conn)?;
+        if meta_get(conn, "totp_enabled")?.as_deref() == Some("1") {
+            match authenticate(conn, &passphrase, totp_code.as_deref()) {
+                Err(e @ (VaultError::InvalidPassphrase | VaultError::InvalidTotp)) => {
+                    record_unlock_failure(conn)?;
+                    return Err(e);
+                }
+                result => {
+                    result?;
+                }
+            }
+        }
+    }
+
+    let scratch = scratch_path(&state.db_path, "restore");
+    fs::copy(&source, &scratch)?;
+    let result = (|| -> Result<()> {
+        let snapshot = db::open(&scratch)?;
+        check_integrity(&snapshot)?;
+        match verify_passphrase(&snapshot, &passphrase) {
+            Err(VaultError::InvalidPassphrase) => {
+                if let Some(conn) = &current {
+                    record_unlock_failure(conn)?;
+                }
+                return Err(VaultError::InvalidPassphrase);
+            }
+            result => result?,
+        };
+        drop(snapshot);
+        drop(current);
+        replace_vault(&state, &totp_state, &scratch)
+    })();
+
+    remove_db_files(&scratch);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use crate::vault::{test_support, Vault};
+
+    fn money(text: &str) -> Money {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn merge_skips_months_the_vault_already_has() {
+        let dir = std::env::temp_dir().join(format!("fv-merge-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let backup_path = dir.join("backup.db");
+        let (_, key) = test_support::open();
+
+        // The backup is taken after the category was created, so both have it.
+        let mut main = db::open(&dir.join("main.db")).unwrap();
+        db::migrate(&mut main).unwrap();
+        let food = test_support::category(&Vault::new(&main, &key), "Food");
+        db::snapshot(&main, &backup_path).unwrap();
+        let backup = db::open(&backup_path).unwrap();
+
+        let old = Vault::new(&backup, &key);
+        old.upsert_budget_month("2024-03".into(), money("50")).unwrap();
+        old.upsert_budget_month("2024-04".into(), money("70")).unwrap();
+        old.set_category_budget(food.clone(), "2024-03".into(), money("200")).unwrap();
+        old.set_category_budget(food.clone(), "2024-04".into(), money("250")).unwrap();
+        drop(backup);
+
+        let vault = Vault::new(&main, &key);
+        vault.upsert_budget_month("2024-03".into(), money("100")).unwrap();
+        vault.set_category_budget(food.clone(), "2024-03".into(), money("300")).unwrap();
+        main.execute("ATTACH DATABASE ?1 AS backup", [backup_path.to_string_lossy()]).unwrap();
+        let tx = main.transaction().unwrap();
+        merge_rows(&tx, &key, &key).unwrap();
+        tx.commit().unwrap();
+
+        let vault = Vault::new(&main, &key);
+        let months: Vec<_> =
+            vault.budget_months().unwrap().into_iter().map(|m| (m.month, m.income)).collect();
+        assert_eq!(months.len(), 2);
+        assert!(months.contains(&("2024-03".into(), money("100"))));
+        assert!(months.contains(&("2024-04".into(), money("70"))));
+        let march = vault.category_budgets("2024-03".into()).unwrap();
+        assert_eq!(march.len(), 1);
+        assert_eq!(march[0].amount, money("300"));
+        assert_eq!(vault.category_budgets("2024-04".into()).unwrap().len(), 1);
+
+        drop(main);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn old_backups_do_not_book_a_month_of_budget_income_twice() {
+        let dir = std::env::temp_dir().join(format!("fv-merge-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let backup_path = dir.join("backup.db");
+        let (_, key) = test_support::open();
+
+        // Both from before income entries, with different rows for March.
+        let open_old = |path: &Path, row_id: &str, income: &str| {
+            let mut conn = db::open(path).unwrap();
+            db::migrate(&mut conn).unwrap();
+            conn.execute(
+                "INSERT INTO budget (id, month, income, created_at) VALUES (?1, '2024-03', ?2, ?1)",
+                [row_id, &crate::crypto::encrypt_value(&key, income).unwrap()],
+            )
+            .unwrap();
+            meta_set(&conn, "data_version", "3").unwrap();
+            db::migrate_data(&mut conn, &key).unwrap();
+            conn
+        };
+        let mut main = open_old(&dir.join("main.db"), "live", "100");
+        drop(open_old(&backup_path, "restored", "100"));
+
+        main.execute("ATTACH DATABASE ?1 AS backup", [backup_path.to_string_lossy()]).unwrap();
+        let tx = main.transaction().unwrap();
+        merge_rows(&tx, &key, &key).unwrap();
+        tx.commit().unwrap();
+
+        let income = Vault::new(&main, &key).list_income().unwrap();
+        assert_eq!(income.len(), 1);
+        assert_eq!(income[0].amount, money("100"));
+
+        drop(main);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,144 +1,86 @@
-use chrono::Utc;
-use serde::{Deserialize, Serialize};
 use tauri::State;
-use uuid::Uuid;
 
 use crate::commands::auth::VaultState;
-use crate::db;
-use crate::error::{Result, VaultError};
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BudgetMonth {
-    pub id: String,
-    pub month: String,
-    pub income: f64,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct BudgetSummary {
-    pub month: String,
-    pub income: f64,
-    pub total_expenses: f64,
-    pub total_subscriptions_monthly: f64,
-    pub remaining: f64,
-}
-
-fn require_unlocked(state: &VaultState) -> Result<()> {
-    if state.key.lock().unwrap().is_none() {
-        return Err(VaultError::Locked);
-    }
-    Ok(())
-}
+use crate::error::Result;
+use crate::money::Money;
+use crate::vault::budget::{BudgetMonth, BudgetSummary, CategoryBudget, CategorySpending};
+use crate::vault::envelopes::{EnvelopeSummary, EnvelopeTransfer};
 
 #[tauri::command]
 pub fn get_budget_months(state: State<'_, VaultState>) -> Result<Vec<BudgetMonth>> {
-    require_unlocked(&state)?;
-    let conn = db::open(&state.db_path)?;
-    let mut stmt = conn
-        .prepare("SELECT id, month, income, created_at FROM budget ORDER BY month DESC")
-        .map_err(VaultError::Database)?;
-
-    let rows = stmt
-        .query_map([], |row| {
-            Ok(BudgetMonth {
-                id: row.get(0)?,
-                month: row.get(1)?,
-                income: row.get(2)?,
-                created_at: row.get(3)?,
-            })
-        })
-        .map_err(VaultError::Database)?;
-
-    rows.collect::<rusqlite::Result<Vec<_>>>()
-        .map_err(VaultError::Database)
+    state.with_vault(|vault| vault.budget_months())
 }
 
 #[tauri::command]
 pub fn upsert_budget_month(
     month: String,
-    income: f64,
+    income: Money,
     state: State<'_, VaultState>,
 ) -> Result<BudgetMonth> {
-    require_unlocked(&state)?;
-    let conn = db::open(&state.db_path)?;
-    let now = Utc::now().to_rfc3339();
-
-    let existing: Option<String> = conn
-        .query_row(
-            "SELECT id FROM budget WHERE month = ?1",
-            [&month],
-            |row| row.get(0),
-        )
-        .ok();
-
-    if let Some(id) = existing {
-        conn.execute(
-            "UPDATE budget SET income = ?1 WHERE id = ?2",
-            rusqlite::params![income, id],
-        )
-        .map_err(VaultError::Database)?;
-        Ok(BudgetMonth { id, month, income, created_at: now })
-    } else {
-        let id = Uuid::new_v4().to_string();
-        conn.execute(
-            "INSERT INTO budget (id, month, income, created_at) VALUES (?1, ?2, ?3, ?4)",
-            rusqlite::params![id, month, income, now],
-        )
-        .map_err(VaultError::Database)?;
-        Ok(BudgetMonth { id, month, income, created_at: now })
-    }
+    state.with_vault(|vault| vault.upsert_budget_month(month, income))
 }
 
 #[tauri::command]
 pub fn get_budget_summary(month: String, state: State<'_, VaultState>) -> Result<BudgetSummary> {
-    require_unlocked(&state)?;
-    let conn = db::open(&state.db_path)?;
+    state.with_vault(|vault| vault.budget_summary(month))
+}
 
-    let income: f64 = conn
-        .query_row(
-            "SELECT income FROM budget WHERE month = ?1",
-            [&month],
-            |row| row.get(0),
-        )
-        .unwrap_or(0.0);
+#[tauri::command]
+pub fn get_category_budgets(
+    month: String,
+    state: State<'_, VaultState>,
+) -> Result<Vec<CategoryBudget>> {
+    state.with_vault(|vault| vault.category_budgets(month))
+}
 
-    let year_month = month.clone();
-    let total_expenses: f64 = conn
-        .query_row(
-            "SELECT COALESCE(SUM(amount), 0) FROM expenses
-             WHERE strftime('%Y-%m', date) = ?1",
-            [&year_month],
-            |row| row.get(0),
-        )
-        .unwrap_or(0.0);
+#[tauri::command]
+pub fn set_category_budget(
+    category_id: String,
+    month: String,
+    amount: Money,
+    state: State<'_, VaultState>,
+) -> Result<CategoryBudget> {
+    state.with_vault(|vault| vault.set_category_budget(category_id, month, amount))
+}
 
-    let total_subscriptions_monthly: f64 = {
-        let mut stmt = conn
-            .prepare("SELECT amount, billing FROM subscriptions")
-            .map_err(VaultError::Database)?;
-        let rows = stmt
-            .query_map([], |row| {
-                Ok((row.get::<_, f64>(0)?, row.get::<_, String>(1)?))
-            })
-            .map_err(VaultError::Database)?;
+#[tauri::command]
+pub fn delete_category_budget(id: String, state: State<'_, VaultState>) -> Result<()> {
+    state.with_vault(|vault| vault.delete_category_budget(&id))
+}
 
-        rows.fold(0.0, |acc, r| {
-            if let Ok((amount, billing)) = r {
-                acc + if billing == "yearly" { amount / 12.0 } else { amount }
-            } else {
-                acc
-            }
-        })
-    };
+#[tauri::command]
+pub fn get_overspent_categories(
+    month: String,
+    state: State<'_, VaultState>,
+) -> Result<Vec<CategorySpending>> {
+    state.with_vault(|vault| vault.overspent_categories(month))
+}
+
+#[tauri::command]
+pub fn get_envelope_summary(
+    month: String,
+    state: State<'_, VaultState>,
+) -> Result<EnvelopeSummary> {
+    state.with_vault(|vault| vault.envelope_summary(month))
+}
 
-    let remaining = income - total_expenses - total_subscriptions_monthly;
+#[tauri::command]
+pub fn get_envelope_transfers(
+    month: String,
+    state: State<'_, VaultState>,
+) -> Result<Vec<EnvelopeTransfer>> {
+    state.with_vault(|vault| vault.envelope_transfers(month))
+}
 
-    Ok(BudgetSummary {
-        month,
-        income,
-        total_expenses,
-        total_subscriptions_monthly,
-        remaining,
+#[tauri::command]
+pub fn move_between_envelopes(
+    month: String,
+    from_category_id: String,
+    to_category_id: String,
+    amount: Money,
+    state: State<'_, VaultState>,
+) -> Result<EnvelopeTransfer> {
+    state.with_vault(|vault| {
+        vault.move_between_envelopes(month, from_category_id, to_category_id, amount)
     })
 }
@@ -0,0 +1,39 @@
+use tauri::State;
+
+use crate::commands::auth::VaultState;
+use crate::error::Result;
+use crate::vault::categories::{Category, CreateCategory, UpdateCategory};
+
+#[tauri::command]
+pub fn get_categories(state: State<'_, VaultState>) -> Result<Vec<Category>> {
+    state.with_vault(|vault| vault.list_categories())
+}
+
+#[tauri::command]
+pub fn create_category(input: CreateCategory, state: State<'_, VaultState>) -> Result<Category> {
+    state.with_vault(|vault| vault.create_category(input))
+}
+
+#[tauri::command]
+pub fn update_category(
+    id: String,
+    input: UpdateCategory,
+    state: State<'_, VaultState>,
+) -> Result<Category> {
+    state.with_vault(|vault| vault.update_category(&id, input))
+}
+
+#[tauri::command]
+pub fn delete_category(id: String, state: State<'_, VaultState>) -> Result<()> {
+    state.with_vault(|vault| vault.delete_category(&id))
+}
+
+/// Moves everything in `sources` into `into` and deletes the sources.
+#[tauri::command]
+pub fn merge_categories(
+    sources: Vec<String>,
+    into: String,
+    state: State<'_, VaultState>,
+) -> Result<Category> {
+    state.with_vault(|vault| vault.merge_categories(sources, &into))
+}
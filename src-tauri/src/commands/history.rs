@@ -0,0 +1,18 @@
+use tauri::State;
+
+use crate::commands::auth::VaultState;
+use crate::error::Result;
+use crate::vault::history::Change;
+
+/// Reverts the newest change that has not been undone yet and returns the
+/// log entry recording the undo, or `None` when there is nothing left.
+#[tauri::command]
+pub fn undo_last_change(state: State<'_, VaultState>) -> Result<Option<Change>> {
+    state.with_vault(|vault| vault.undo_last_change())
+}
+
+/// Every logged change of one entity, newest first.
+#[tauri::command]
+pub fn get_history(entity: String, id: String, state: State<'_, VaultState>) -> Result<Vec<Change>> {
+    state.with_vault(|vault| vault.history(&entity, &id))
+}
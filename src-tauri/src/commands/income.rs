@@ -0,0 +1,79 @@
+use tauri::State;
+
+use crate::commands::auth::VaultState;
+use crate::error::Result;
+use crate::vault::income::{
+    CreateIncome, CreateRecurringIncome, Income, RecurringIncome, UpdateIncome,
+    UpdateRecurringIncome,
+};
+
+#[tauri::command]
+pub fn get_income(state: State<'_, VaultState>) -> Result<Vec<Income>> {
+    state.with_vault(|vault| vault.list_income())
+}
+
+#[tauri::command]
+pub fn create_income(input: CreateIncome, state: State<'_, VaultState>) -> Result<Income> {
+    state.with_vault(|vault| vault.create_income(input))
+}
+
+#[tauri::command]
+pub fn update_income(
+    id: String,
+    input: UpdateIncome,
+    state: State<'_, VaultState>,
+) -> Result<Income> {
+    state.with_vault(|vault| vault.update_income(&id, input))
+}
+
+#[tauri::command]
+pub fn delete_income(id: String, state: State<'_, VaultState>) -> Result<()> {
+    state.with_vault(|vault| vault.delete_income(&id))
+}
+
+#[tauri::command]
+pub fn restore_income(id: String, state: State<'_, VaultState>) -> Result<Income> {
+    state.with_vault(|vault| vault.restore_income(&id))
+}
+
+#[tauri::command]
+pub fn get_recurring_income(state: State<'_, VaultState>) -> Result<Vec<RecurringIncome>> {
+    state.with_vault(|vault| vault.list_recurring_income())
+}
+
+#[tauri::command]
+pub fn create_recurring_income(
+    input: CreateRecurringIncome,
+    state: State<'_, VaultState>,
+) -> Result<RecurringIncome> {
+    state.with_vault(|vault| vault.create_recurring_income(input))
+}
+
+#[tauri::command]
+pub fn update_recurring_income(
+    id: String,
+    input: UpdateRecurringIncome,
+    state: State<'_, VaultState>,
+) -> Result<RecurringIncome> {
+    state.with_vault(|vault| vault.update_recurring_income(&id, input))
+}
+
+#[tauri::command]
+pub fn delete_recurring_income(id: String, state: State<'_, VaultState>) -> Result<()> {
+    state.with_vault(|vault| vault.delete_recurring_income(&id))
+}
+
+#[tauri::command]
+pub fn restore_recurring_income(
+    id: String,
+    state: State<'_, VaultState>,
+) -> Result<RecurringIncome> {
+    state.with_vault(|vault| vault.restore_recurring_income(&id))
+}
+
+/// Books recurring income that has come due since the vault was unlocked.
+#[tauri::command]
+pub fn book_recurring_income(state: State<'_, VaultState>) -> Result<Vec<Income>> {
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    state.with_vault(|vault| vault.book_recurring_income(&today))
+}
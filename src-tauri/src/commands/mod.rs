@@ -1,9 +1,16 @@
+pub mod api;
 pub mod auth;
+pub mod auto_lock;
+pub mod backup;
 pub mod budget;
+pub mod categories;
 pub mod dashboard;
 pub mod expenses;
+pub mod history;
+pub mod income;
 pub mod portfolio;
 pub mod savings;
 pub mod subscriptions;
 pub mod totp;
+pub mod trash;
 pub mod yahoo;
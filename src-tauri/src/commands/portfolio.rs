@@ -1,176 +1,55 @@
-use chrono::Utc;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use tauri::State;
-use uuid::Uuid;
 
 use crate::commands::auth::VaultState;
-use crate::db;
 use crate::error::{Result, VaultError};
+use crate::money::{Money, Quantity};
+use crate::vault::portfolio::{CreatePosition, Position, UpdatePosition};
 
 #[derive(Debug, Serialize)]
 pub struct ParsedPdfPosition {
     pub isin: String,
     pub name: String,
-    pub quantity: f64,
-    pub price: f64,
+    pub quantity: Quantity,
+    pub price: Money,
     pub currency: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Position {
-    pub id: String,
-    pub isin: String,
-    pub ticker: String,
-    pub name: String,
-    pub asset_type: String,
-    pub quantity: f64,
-    pub avg_buy_price: f64,
-    pub currency: String,
-    pub country: String,
-    pub created_at: String,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct CreatePosition {
-    pub isin: String,
-    pub ticker: String,
-    pub name: String,
-    pub asset_type: String,
-    pub quantity: f64,
-    pub avg_buy_price: f64,
-    pub currency: Option<String>,
-    pub country: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct UpdatePosition {
-    pub quantity: Option<f64>,
-    pub avg_buy_price: Option<f64>,
-    pub currency: Option<String>,
-    pub country: Option<String>,
-    pub asset_type: Option<String>,
-}
-
-fn require_unlocked(state: &VaultState) -> Result<()> {
-    if state.key.lock().unwrap().is_none() {
-        return Err(VaultError::Locked);
-    }
-    Ok(())
-}
-
 #[tauri::command]
 pub fn get_positions(state: State<'_, VaultState>) -> Result<Vec<Position>> {
-    require_unlocked(&state)?;
-    let conn = db::open(&state.db_path)?;
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, isin, ticker, name, asset_type, quantity, avg_buy_price, currency, country, created_at
-             FROM portfolio_positions ORDER BY created_at ASC",
-        )
-        .map_err(VaultError::Database)?;
-
-    let rows = stmt
-        .query_map([], |row| {
-            Ok(Position {
-                id: row.get(0)?,
-                isin: row.get(1)?,
-                ticker: row.get(2)?,
-                name: row.get(3)?,
-                asset_type: row.get(4)?,
-                quantity: row.get(5)?,
-                avg_buy_price: row.get(6)?,
-                currency: row.get(7)?,
-                country: row.get(8).unwrap_or_default(),
-                created_at: row.get(9)?,
-            })
-        })
-        .map_err(VaultError::Database)?;
-
-    rows.collect::<rusqlite::Result<Vec<_>>>()
-        .map_err(VaultError::Database)
+    state.with_vault(|vault| vault.list_positions())
 }
 
 #[tauri::command]
 pub fn create_position(input: CreatePosition, state: State<'_, VaultState>) -> Result<Position> {
-    require_unlocked(&state)?;
-    let conn = db::open(&state.db_path)?;
-    let id = Uuid::new_v4().to_string();
-    let now = Utc::now().to_rfc3339();
-    let currency = input.currency.unwrap_or_else(|| "EUR".to_string());
-    let country = input.country.unwrap_or_default();
-
-    conn.execute(
-        "INSERT INTO portfolio_positions (id, isin, ticker, name, asset_type, quantity, avg_buy_price, currency, country, created_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-        rusqlite::params![
-            id, input.isin, input.ticker, input.name, input.asset_type,
-            input.quantity, input.avg_buy_price, currency, country, now
-        ],
-    )
-    .map_err(VaultError::Database)?;
-
-    Ok(Position {
-        id,
-        isin: input.isin,
-        ticker: input.ticker,
-        name: input.name,
-        asset_type: input.asset_type,
-        quantity: input.quantity,
-        avg_buy_price: input.avg_buy_price,
-        currency,
-        country,
-        created_at: now,
-    })
+    state.with_vault(|vault| vault.create_position(input))
 }
 
 #[tauri::command]
-pub fn update_position(id: String, input: UpdatePosition, state: State<'_, VaultState>) -> Result<()> {
-    require_unlocked(&state)?;
-    let conn = db::open(&state.db_path)?;
-
-    if let Some(quantity) = input.quantity {
-        conn.execute("UPDATE portfolio_positions SET quantity = ?1 WHERE id = ?2", rusqlite::params![quantity, id])
-            .map_err(VaultError::Database)?;
-    }
-    if let Some(avg_buy_price) = input.avg_buy_price {
-        conn.execute("UPDATE portfolio_positions SET avg_buy_price = ?1 WHERE id = ?2", rusqlite::params![avg_buy_price, id])
-            .map_err(VaultError::Database)?;
-    }
-    if let Some(currency) = input.currency {
-        conn.execute("UPDATE portfolio_positions SET currency = ?1 WHERE id = ?2", [&currency, &id])
-            .map_err(VaultError::Database)?;
-    }
-    if let Some(country) = input.country {
-        conn.execute("UPDATE portfolio_positions SET country = ?1 WHERE id = ?2", [&country, &id])
-            .map_err(VaultError::Database)?;
-    }
-    if let Some(asset_type) = input.asset_type {
-        conn.execute("UPDATE portfolio_positions SET asset_type = ?1 WHERE id = ?2", [&asset_type, &id])
-            .map_err(VaultError::Database)?;
-    }
-    Ok(())
+pub fn update_position(id: String, input: UpdatePosition, state: State<'_, VaultState>) -> Result<Position> {
+    state.with_vault(|vault| vault.update_position(&id, input))
 }
 
 #[tauri::command]
 pub fn delete_position(id: String, state: State<'_, VaultState>) -> Result<()> {
-    require_unlocked(&state)?;
-    let conn = db::open(&state.db_path)?;
-    conn.execute("DELETE FROM portfolio_positions WHERE id = ?1", [&id])
-        .map_err(VaultError::Database)?;
-    Ok(())
+    state.with_vault(|vault| vault.delete_position(&id))
+}
+
+#[tauri::command]
+pub fn restore_position(id: String, state: State<'_, VaultState>) -> Result<Position> {
+    state.with_vault(|vault| vault.restore_position(&id))
 }
 
 /// Parse a broker PDF (Trade Republic, Scalable, etc.) and extract positions.
 /// Returns a list of detected positions — the user confirms before saving.
 #[tauri::command]
 pub fn import_pdf(path: String, state: State<'_, VaultState>) -> Result<Vec<ParsedPdfPosition>> {
-    require_unlocked(&state)?;
+    state.require_unlocked()?;
 
-    let bytes = std::fs::read(&path)
-        .map_err(|e| VaultError::Crypto(format!("cannot read file: {e}")))?;
+    let bytes = std::fs::read(&path)?;
 
     let text = pdf_extract::extract_text_from_mem(&bytes)
-        .map_err(|e| VaultError::Crypto(format!("pdf parse error: {e}")))?;
+        .map_err(|e| VaultError::Parse(format!("pdf: {e}")))?;
 
     Ok(parse_pdf_text(&text))
 }
@@ -191,14 +70,11 @@ fn parse_pdf_text(text: &str) -> Vec<ParsedPdfPosition> {
             let name = find_name(&lines, i);
             let (quantity, price, currency) = find_quantity_price(&lines, i);
 
-            if quantity > 0.0 && price > 0.0 {
-                positions.push(ParsedPdfPosition {
-                    isin,
-                    name,
-                    quantity,
-                    price,
-                    currency,
-                });
+            let parsed = (Quantity::from_f64(quantity), Money::from_major(price, &currency));
+            if let (Ok(quantity), Ok(price)) = parsed {
+                if quantity.is_positive() && !price.is_negative() && price != Money::ZERO {
+                    positions.push(ParsedPdfPosition { isin, name, quantity, price, currency });
+                }
             }
         }
         i += 1;
@@ -249,8 +125,8 @@ fn find_quantity_price(lines: &[&str], isin_idx: usize) -> (f64, f64, String) {
     }
 
     // Heuristic: first reasonable number = quantity, last reasonable = price
-    let quantity = numbers.iter().find(|&&n| n >= 0.001 && n < 100_000.0).copied().unwrap_or(0.0);
-    let price = numbers.iter().rev().find(|&&n| n >= 0.01 && n < 1_000_000.0).copied().unwrap_or(0.0);
+    let quantity = numbers.iter().find(|&&n| (0.001..100_000.0).contains(&n)).copied().unwrap_or(0.0);
+    let price = numbers.iter().rev().find(|&&n| (0.01..1_000_000.0).contains(&n)).copied().unwrap_or(0.0);
 
     (quantity, price, currency)
 }
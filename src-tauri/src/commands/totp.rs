@@ -1,25 +1,82 @@
 use std::sync::Mutex;
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use tauri::State;
 use totp_rs::{Algorithm, Secret, TOTP};
+use zeroize::Zeroizing;
 
-use crate::commands::auth::VaultState;
+use crate::commands::auth::{verify_passphrase, VaultState};
+use crate::crypto::DerivedKey;
+use crate::db::{meta_get, meta_set};
 use crate::error::{Result, VaultError};
+use crate::validation::FieldError;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
 
 pub struct TotpState {
     pub enabled: Mutex<bool>,
-    pub secret: Mutex<Option<String>>,
+    pub secret: Mutex<Option<Zeroizing<String>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TotpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
 }
 
-fn build_totp(secret_base32: &str, account: &str) -> Result<TOTP> {
+/// How codes are generated and checked. Stored as JSON in `vault_meta`
+/// next to the secret; vaults without it use the defaults (SHA1, 6 digits,
+/// one step of skew), which is what they were enabled with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TotpConfig {
+    pub algorithm: TotpAlgorithm,
+    pub digits: usize,
+    /// Number of 30 second steps accepted before and after the current one.
+    pub skew: u8,
+}
+
+impl Default for TotpConfig {
+    fn default() -> Self {
+        TotpConfig {
+            algorithm: TotpAlgorithm::Sha1,
+            digits: 6,
+            skew: 1,
+        }
+    }
+}
+
+const TOTP_STEP: u64 = 30;
+const MAX_TOTP_SKEW: u8 = 10;
+
+/// The single place a `TOTP` is constructed. Skew is left at zero here and
+/// applied by `matching_step`, which needs to know which step matched.
+fn build_totp(secret_base32: &str, account: &str, config: &TotpConfig) -> Result<TOTP> {
+    if config.skew > MAX_TOTP_SKEW {
+        return Err(VaultError::Validation(vec![FieldError {
+            field: "skew".into(),
+            message: format!("must be at most {MAX_TOTP_SKEW}"),
+        }]));
+    }
     let bytes = Secret::Encoded(secret_base32.to_string())
         .to_bytes()
         .map_err(|e| VaultError::Crypto(e.to_string()))?;
+    let algorithm = match config.algorithm {
+        TotpAlgorithm::Sha1 => Algorithm::SHA1,
+        TotpAlgorithm::Sha256 => Algorithm::SHA256,
+        TotpAlgorithm::Sha512 => Algorithm::SHA512,
+    };
 
     TOTP::new(
-        Algorithm::SHA1,
-        6,
-        1,
-        30,
+        algorithm,
+        config.digits,
+        0,
+        TOTP_STEP,
         bytes,
         Some("FinanceVault".to_string()),
         account.to_string(),
@@ -27,10 +84,149 @@ fn build_totp(secret_base32: &str, account: &str) -> Result<TOTP> {
     .map_err(|e| VaultError::Crypto(e.to_string()))
 }
 
+/// Returns the newest time step within the configured skew for which `code`
+/// is valid.
+fn matching_step(secret_base32: &str, config: &TotpConfig, code: &str) -> Result<Option<u64>> {
+    let totp = build_totp(secret_base32, "FinanceVault", config)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| VaultError::Crypto(e.to_string()))?
+        .as_secs();
+    let current = now / TOTP_STEP;
+    let skew = config.skew as u64;
+
+    Ok((current.saturating_sub(skew)..=current + skew)
+        .rev()
+        .find(|step| totp.check(code, step * TOTP_STEP)))
+}
+
+fn load_totp_config(conn: &rusqlite::Connection) -> Result<TotpConfig> {
+    match meta_get(conn, "totp_config")? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| VaultError::Parse(e.to_string())),
+        None => Ok(TotpConfig::default()),
+    }
+}
+
+fn store_totp_config(conn: &rusqlite::Connection, config: &TotpConfig) -> Result<()> {
+    let json = serde_json::to_string(config).map_err(|e| VaultError::Crypto(e.to_string()))?;
+    meta_set(conn, "totp_config", &json)?;
+    Ok(())
+}
+
+/// Accepts `code` only if it matches a time step after the last accepted
+/// one, then records that step so the same code cannot be replayed.
+fn accept_totp_code(
+    conn: &rusqlite::Connection,
+    secret_base32: &str,
+    config: &TotpConfig,
+    code: &str,
+) -> Result<bool> {
+    let Some(step) = matching_step(secret_base32, config, code)? else {
+        return Ok(false);
+    };
+    let last_step: Option<u64> = meta_get(conn, "totp_last_step")?.and_then(|v| v.parse().ok());
+    if last_step.is_some_and(|last| step <= last) {
+        return Ok(false);
+    }
+    meta_set(conn, "totp_last_step", &step.to_string())?;
+    Ok(true)
+}
+
+/// Generates fresh one-time recovery codes, stores their Argon2 hashes in
+/// `vault_meta` (replacing any previous set) and returns the plaintext codes.
+fn issue_recovery_codes(conn: &rusqlite::Connection) -> Result<Vec<String>> {
+    let mut rng = rand::thread_rng();
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut chars = (0..10).map(|_| {
+                RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char
+            });
+            let first: String = chars.by_ref().take(5).collect();
+            let second: String = chars.collect();
+            format!("{first}-{second}")
+        })
+        .collect();
+
+    let argon2 = Argon2::default();
+    let hashes = codes
+        .iter()
+        .map(|code| {
+            let salt = SaltString::generate(&mut rand_core::OsRng);
+            argon2
+                .hash_password(code.as_bytes(), &salt)
+                .map(|h| h.to_string())
+                .map_err(|e| VaultError::Crypto(e.to_string()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let json = serde_json::to_string(&hashes).map_err(|e| VaultError::Crypto(e.to_string()))?;
+    meta_set(conn, "totp_recovery_codes", &json)?;
+    Ok(codes)
+}
+
+fn load_recovery_hashes(conn: &rusqlite::Connection) -> Result<Vec<String>> {
+    match meta_get(conn, "totp_recovery_codes")? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| VaultError::Parse(e.to_string())),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Checks `code` against the stored recovery codes and burns it on a match.
+fn redeem_recovery_code(conn: &rusqlite::Connection, code: &str) -> Result<bool> {
+    let normalized = Zeroizing::new(code.trim().to_lowercase());
+    let mut hashes = load_recovery_hashes(conn)?;
+
+    let argon2 = Argon2::default();
+    let matched = hashes.iter().position(|hash| {
+        PasswordHash::new(hash)
+            .map(|parsed| argon2.verify_password(normalized.as_bytes(), &parsed).is_ok())
+            .unwrap_or(false)
+    });
+
+    let Some(index) = matched else {
+        return Ok(false);
+    };
+    hashes.remove(index);
+    let json = serde_json::to_string(&hashes).map_err(|e| VaultError::Crypto(e.to_string()))?;
+    meta_set(conn, "totp_recovery_codes", &json)?;
+    Ok(true)
+}
+
+/// Accepts either a current TOTP code for `secret_base32` or an unused
+/// recovery code, which is consumed.
+pub fn check_second_factor(
+    conn: &rusqlite::Connection,
+    secret_base32: &str,
+    code: &str,
+) -> Result<()> {
+    let config = load_totp_config(conn)?;
+    if accept_totp_code(conn, secret_base32, &config, code)? || redeem_recovery_code(conn, code)? {
+        Ok(())
+    } else {
+        Err(VaultError::InvalidTotp)
+    }
+}
+
+/// Verifies the passphrase and a second factor for changes to TOTP itself.
+/// Returns the vault key and the current TOTP secret.
+fn authorize_totp_change(
+    conn: &rusqlite::Connection,
+    state: &VaultState,
+    passphrase: &str,
+    code: &str,
+) -> Result<(DerivedKey, Zeroizing<String>)> {
+    state.require_unlocked()?;
+    let key = verify_passphrase(conn, passphrase)?;
+    let (enabled, secret) = load_totp_state(conn, &key)?;
+    let secret = secret.filter(|_| enabled).ok_or(VaultError::InvalidTotp)?;
+    check_second_factor(conn, &secret, code)?;
+    Ok((key, secret))
+}
+
 fn new_secret_base32() -> String {
     use rand::RngCore;
-    let mut bytes = [0u8; 20];
-    rand::thread_rng().fill_bytes(&mut bytes);
+    let mut bytes = Zeroizing::new([0u8; 20]);
+    rand::thread_rng().fill_bytes(bytes.as_mut());
     Secret::Raw(bytes.to_vec()).to_encoded().to_string()
 }
 
@@ -40,66 +236,184 @@ pub fn totp_generate_secret() -> String {
 }
 
 #[tauri::command]
-pub fn totp_get_qr_base64(secret_base32: String, account: String) -> Result<String> {
-    let totp = build_totp(&secret_base32, &account)?;
-    totp.get_qr_base64().map_err(|e| VaultError::Crypto(e))
+pub fn totp_get_qr_base64(
+    secret_base32: Zeroizing<String>,
+    account: String,
+    config: Option<TotpConfig>,
+) -> Result<String> {
+    let totp = build_totp(&secret_base32, &account, &config.unwrap_or_default())?;
+    totp.get_qr_base64().map_err(VaultError::Crypto)
 }
 
 #[tauri::command]
-pub fn totp_get_url(secret_base32: String, account: String) -> Result<String> {
-    let totp = build_totp(&secret_base32, &account)?;
+pub fn totp_get_url(
+    secret_base32: Zeroizing<String>,
+    account: String,
+    config: Option<TotpConfig>,
+) -> Result<String> {
+    let totp = build_totp(&secret_base32, &account, &config.unwrap_or_default())?;
     Ok(totp.get_url())
 }
 
+/// Checks a code against a secret that is not stored yet, e.g. during setup.
 #[tauri::command]
-pub fn totp_verify(code: String, secret_base32: String) -> Result<bool> {
-    let totp = build_totp(&secret_base32, "verify")?;
-    Ok(totp.check_current(&code).unwrap_or(false))
+pub fn totp_verify(
+    code: String,
+    secret_base32: Zeroizing<String>,
+    config: Option<TotpConfig>,
+) -> Result<bool> {
+    Ok(matching_step(&secret_base32, &config.unwrap_or_default(), &code)?.is_some())
 }
 
+/// Returns the settings codes are checked with, for showing them in the UI.
+#[tauri::command]
+pub fn totp_get_config(state: State<'_, VaultState>) -> Result<TotpConfig> {
+    state.require_unlocked()?;
+    let conn = state.conn()?;
+    load_totp_config(&conn)
+}
+
+/// Stores `secret_base32` as the TOTP secret once `code` proves it is set
+/// up. Refused while TOTP is on: replacing the secret goes through
+/// `totp_rotate`, which asks for the passphrase and a current code.
+fn enable_totp(
+    conn: &mut rusqlite::Connection,
+    key: &DerivedKey,
+    secret_base32: &str,
+    config: &TotpConfig,
+    code: &str,
+) -> Result<Vec<String>> {
+    // A failure part-way must not leave TOTP enabled without recovery codes.
+    let tx = conn.transaction()?;
+    if meta_get(&tx, "totp_enabled")?.as_deref() == Some("1") {
+        return Err(VaultError::Validation(vec![FieldError {
+            field: "secret_base32".into(),
+            message: "TOTP is already enabled; rotate the secret instead".into(),
+        }]));
+    }
+    if !accept_totp_code(&tx, secret_base32, config, code)? {
+        return Err(VaultError::InvalidTotp);
+    }
+
+    let encrypted = crate::crypto::encrypt(key, secret_base32.as_bytes())?;
+    meta_set(&tx, "totp_secret", &encrypted)?;
+    meta_set(&tx, "totp_enabled", "1")?;
+    store_totp_config(&tx, config)?;
+    let recovery_codes = issue_recovery_codes(&tx)?;
+    tx.commit()?;
+    Ok(recovery_codes)
+}
+
+/// Turns on TOTP and returns a fresh set of one-time recovery codes, which
+/// are only ever shown here.
 #[tauri::command]
 pub fn totp_enable(
-    secret_base32: String,
+    secret_base32: Zeroizing<String>,
     code: String,
+    config: Option<TotpConfig>,
     state: State<'_, VaultState>,
     totp_state: State<'_, TotpState>,
-) -> Result<()> {
-    let totp = build_totp(&secret_base32, "FinanceVault")?;
-    if !totp.check_current(&code).unwrap_or(false) {
-        return Err(VaultError::InvalidTotp);
-    }
+) -> Result<Vec<String>> {
+    let config = config.unwrap_or_default();
+    let mut conn = state.conn()?;
+    let key = state.require_unlocked()?;
+    let recovery_codes = enable_totp(&mut conn, &key, &secret_base32, &config, &code)?;
 
-    let key_guard = state.key.lock().unwrap();
-    let key = key_guard.as_ref().ok_or(VaultError::Locked)?;
+    *totp_state.enabled.lock().unwrap() = true;
+    *totp_state.secret.lock().unwrap() = Some(secret_base32);
+    Ok(recovery_codes)
+}
 
-    let encrypted = crate::crypto::encrypt(key, secret_base32.as_bytes())?;
+#[tauri::command]
+pub fn totp_is_enabled(totp_state: State<'_, TotpState>) -> bool {
+    *totp_state.enabled.lock().unwrap()
+}
 
-    let conn = crate::db::open(&state.db_path)?;
-    conn.execute(
-        "INSERT INTO vault_meta (key, value) VALUES ('totp_secret', ?1)
-         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
-        [&encrypted],
-    )?;
-    conn.execute(
-        "INSERT INTO vault_meta (key, value) VALUES ('totp_enabled', '1')
-         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+/// Turns TOTP off. Needs the passphrase plus a TOTP or recovery code.
+#[tauri::command]
+pub fn totp_disable(
+    passphrase: Zeroizing<String>,
+    code: String,
+    state: State<'_, VaultState>,
+    totp_state: State<'_, TotpState>,
+) -> Result<()> {
+    let mut conn = state.conn()?;
+    authorize_totp_change(&conn, &state, &passphrase, &code)?;
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM vault_meta
+         WHERE key IN ('totp_secret', 'totp_config', 'totp_recovery_codes', 'totp_last_step')",
         [],
     )?;
+    meta_set(&tx, "totp_enabled", "0")?;
+    tx.commit()?;
 
-    *totp_state.enabled.lock().unwrap() = true;
-    *totp_state.secret.lock().unwrap() = Some(secret_base32);
+    *totp_state.enabled.lock().unwrap() = false;
+    *totp_state.secret.lock().unwrap() = None;
     Ok(())
 }
 
+/// Replaces the TOTP secret. Needs the passphrase plus a code for the current
+/// secret (or a recovery code), and `new_code` proves the new secret is set up.
+/// Existing recovery codes stay valid; `new_config` defaults to the current one.
 #[tauri::command]
-pub fn totp_is_enabled(totp_state: State<'_, TotpState>) -> bool {
-    *totp_state.enabled.lock().unwrap()
+pub fn totp_rotate(
+    passphrase: Zeroizing<String>,
+    code: String,
+    new_secret_base32: Zeroizing<String>,
+    new_code: String,
+    new_config: Option<TotpConfig>,
+    state: State<'_, VaultState>,
+    totp_state: State<'_, TotpState>,
+) -> Result<()> {
+    let mut conn = state.conn()?;
+    let (key, _) = authorize_totp_change(&conn, &state, &passphrase, &code)?;
+
+    let config = match new_config {
+        Some(config) => config,
+        None => load_totp_config(&conn)?,
+    };
+    // The step of `code` was just recorded, so `new_code` is checked without
+    // replay protection; it is for a different secret.
+    if matching_step(&new_secret_base32, &config, &new_code)?.is_none() {
+        return Err(VaultError::InvalidTotp);
+    }
+
+    let encrypted = crate::crypto::encrypt(&key, new_secret_base32.as_bytes())?;
+    let tx = conn.transaction()?;
+    meta_set(&tx, "totp_secret", &encrypted)?;
+    store_totp_config(&tx, &config)?;
+    tx.commit()?;
+
+    *totp_state.secret.lock().unwrap() = Some(new_secret_base32);
+    Ok(())
+}
+
+/// Replaces all recovery codes with a new set. Needs the passphrase plus a
+/// TOTP or recovery code.
+#[tauri::command]
+pub fn totp_regenerate_recovery_codes(
+    passphrase: Zeroizing<String>,
+    code: String,
+    state: State<'_, VaultState>,
+) -> Result<Vec<String>> {
+    let conn = state.conn()?;
+    authorize_totp_change(&conn, &state, &passphrase, &code)?;
+    issue_recovery_codes(&conn)
+}
+
+#[tauri::command]
+pub fn totp_recovery_codes_remaining(state: State<'_, VaultState>) -> Result<usize> {
+    state.require_unlocked()?;
+    let conn = state.conn()?;
+    Ok(load_recovery_hashes(&conn)?.len())
 }
 
 pub fn load_totp_state(
     conn: &rusqlite::Connection,
     key: &crate::crypto::DerivedKey,
-) -> Result<(bool, Option<String>)> {
+) -> Result<(bool, Option<Zeroizing<String>>)> {
     let enabled = conn
         .query_row(
             "SELECT value FROM vault_meta WHERE key = 'totp_enabled'",
@@ -121,8 +435,44 @@ pub fn load_totp_state(
         )
         .map_err(VaultError::Database)?;
 
-    let plain = crate::crypto::decrypt(key, &encrypted)?;
-    let secret = String::from_utf8(plain).map_err(|e| VaultError::Crypto(e.to_string()))?;
+    let mut plain = crate::crypto::decrypt(key, &encrypted)?;
+    let secret = Zeroizing::new(
+        String::from_utf8(std::mem::take(&mut *plain))
+            .map_err(|e| VaultError::Crypto(e.utf8_error().to_string()))?,
+    );
 
     Ok((true, Some(secret)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::test_support;
+
+    fn current_code(secret_base32: &str) -> String {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        build_totp(secret_base32, "FinanceVault", &TotpConfig::default())
+            .unwrap()
+            .generate(now)
+    }
+
+    #[test]
+    fn a_second_enable_is_refused() {
+        let (mut conn, key) = test_support::open();
+        let config = TotpConfig::default();
+        let first = new_secret_base32();
+        enable_totp(&mut conn, &key, &first, &config, &current_code(&first)).unwrap();
+
+        let second = new_secret_base32();
+        let result = enable_totp(&mut conn, &key, &second, &config, &current_code(&second));
+        match result {
+            Err(VaultError::Validation(errors)) => assert_eq!(errors[0].field, "secret_base32"),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+        let (_, stored) = load_totp_state(&conn, &key).unwrap();
+        assert_eq!(stored.as_deref().map(String::as_str), Some(first.as_str()));
+    }
+}
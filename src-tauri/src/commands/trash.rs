@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::commands::auth::VaultState;
+use crate::error::Result;
+use crate::vault::trash::{purge_trash, TrashItem, TrashSettings};
+
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Starts the background task that purges expired trash while the vault is
+/// unlocked. Unlocking purges as well.
+pub fn spawn_trash_purge(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(TRASH_PURGE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            // Locked vaults are skipped; the next check tries again.
+            if let Ok(conn) = app.state::<VaultState>().conn() {
+                let _ = purge_trash(&conn);
+            }
+        }
+    });
+}
+
+/// Everything in the trash, most recently deleted first.
+#[tauri::command]
+pub fn get_trash(state: State<'_, VaultState>) -> Result<Vec<TrashItem>> {
+    state.with_vault(|vault| vault.trash())
+}
+
+/// Permanently deletes everything in the trash and returns how many rows
+/// were removed.
+#[tauri::command]
+pub fn empty_trash(state: State<'_, VaultState>) -> Result<usize> {
+    state.with_vault(|vault| vault.empty_trash())
+}
+
+#[tauri::command]
+pub fn get_trash_settings(state: State<'_, VaultState>) -> Result<TrashSettings> {
+    state.with_vault(|vault| vault.trash_settings())
+}
+
+#[tauri::command]
+pub fn set_trash_settings(settings: TrashSettings, state: State<'_, VaultState>) -> Result<()> {
+    state.with_vault(|vault| vault.set_trash_settings(settings))
+}
@@ -53,18 +53,11 @@ struct YahooResult {
     regular_market_change_percent: Option<f64>,
 }
 
-fn require_unlocked(state: &VaultState) -> Result<()> {
-    if state.key.lock().unwrap().is_none() {
-        return Err(VaultError::Locked);
-    }
-    Ok(())
-}
-
 fn http_client() -> Result<reqwest::Client> {
     reqwest::Client::builder()
         .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36")
         .build()
-        .map_err(|e| VaultError::Crypto(e.to_string()))
+        .map_err(|e| VaultError::Network(e.to_string()))
 }
 
 async fn isin_to_ticker(client: &reqwest::Client, isin: &str) -> Result<(String, String)> {
@@ -77,17 +70,20 @@ async fn isin_to_ticker(client: &reqwest::Client, isin: &str) -> Result<(String,
         .get(&url)
         .send()
         .await
-        .map_err(|e| VaultError::Crypto(format!("search network error: {e}")))?;
+        .map_err(|e| VaultError::Network(format!("search failed: {e}")))?;
 
     let body: SearchResponse = resp
         .json()
         .await
-        .map_err(|e| VaultError::Crypto(format!("search parse error: {e}")))?;
+        .map_err(|e| VaultError::Parse(format!("search response: {e}")))?;
 
     let first = body
         .quotes
         .and_then(|q| q.into_iter().next())
-        .ok_or_else(|| VaultError::Crypto(format!("no result for ISIN {isin}")))?;
+        .ok_or_else(|| VaultError::NotFound {
+            entity: "ISIN",
+            id: isin.to_string(),
+        })?;
 
     let name = first.longname.or(first.shortname).unwrap_or_default();
     Ok((first.symbol, name))
@@ -95,7 +91,7 @@ async fn isin_to_ticker(client: &reqwest::Client, isin: &str) -> Result<(String,
 
 #[tauri::command]
 pub async fn resolve_isin(isin: String, state: State<'_, VaultState>) -> Result<(String, String)> {
-    require_unlocked(&state)?;
+    state.require_unlocked()?;
     let client = http_client()?;
     isin_to_ticker(&client, &isin).await
 }
@@ -105,7 +101,7 @@ pub async fn fetch_quotes(
     positions: Vec<(String, String)>,
     state: State<'_, VaultState>,
 ) -> Result<Vec<Quote>> {
-    require_unlocked(&state)?;
+    state.require_unlocked()?;
 
     if positions.is_empty() {
         return Ok(vec![]);
@@ -127,12 +123,12 @@ pub async fn fetch_quotes(
         .get(&url)
         .send()
         .await
-        .map_err(|e| VaultError::Crypto(format!("quote network error: {e}")))?;
+        .map_err(|e| VaultError::Network(format!("quote request failed: {e}")))?;
 
     let body: QuoteResponse = resp
         .json()
         .await
-        .map_err(|e| VaultError::Crypto(format!("quote parse error: {e}")))?;
+        .map_err(|e| VaultError::Parse(format!("quote response: {e}")))?;
 
     let results = body.quote_response.result.unwrap_or_default();
 
@@ -2,38 +2,103 @@ use aes_gcm::{
     aead::{Aead, AeadCore, KeyInit, OsRng},
     Aes256Gcm, Key, Nonce,
 };
-use argon2::{Argon2, PasswordHasher};
 use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 use crate::error::{Result, VaultError};
 
 pub const KEY_LEN: usize = 32;
 
+/// Wiped from memory when dropped, including every clone handed to commands.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
 pub struct DerivedKey(pub [u8; KEY_LEN]);
 
-pub fn derive_key(passphrase: &str, salt_b64: &str) -> Result<DerivedKey> {
+pub const KDF_ALGORITHM: &str = "argon2id";
+pub const KDF_VERSION: u32 = 0x13;
+
+/// Upper bound for benchmarked memory cost (1 GiB, in KiB).
+const MAX_BENCH_M_COST: u32 = 1024 * 1024;
+/// Upper bound for benchmarked time cost, reached only on machines fast
+/// enough to fill the memory cap well within the target.
+const MAX_BENCH_T_COST: u32 = 16;
+
+/// Argon2id cost parameters, stored in `vault_meta` next to the salt so a
+/// vault keeps opening if the argon2 crate changes its defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl KdfParams {
+    /// The argon2 0.5 defaults, pinned. Vaults created before parameters were
+    /// stored were derived with these.
+    pub const DEFAULT: KdfParams = KdfParams {
+        m_cost: 19 * 1024,
+        t_cost: 2,
+        p_cost: 1,
+    };
+
+    /// Rough amount of work, used to tell whether other parameters are stronger.
+    pub fn cost(&self) -> u64 {
+        self.m_cost as u64 * self.t_cost as u64
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(KEY_LEN))
+            .map_err(|e| VaultError::Crypto(e.to_string()))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+pub fn derive_key(passphrase: &str, salt_b64: &str, params: &KdfParams) -> Result<DerivedKey> {
     let salt_bytes = STANDARD
         .decode(salt_b64)
         .map_err(|e| VaultError::Crypto(e.to_string()))?;
-    let salt = SaltString::encode_b64(&salt_bytes)
-        .map_err(|e| VaultError::Crypto(e.to_string()))?;
 
-    let argon2 = Argon2::default();
-    let hash = argon2
-        .hash_password(passphrase.as_bytes(), &salt)
+    // Argon2 writes straight into the key, so no unwiped copy is left behind.
+    let mut key = DerivedKey([0u8; KEY_LEN]);
+    params
+        .argon2()?
+        .hash_password_into(passphrase.as_bytes(), &salt_bytes, &mut key.0)
         .map_err(|e| VaultError::Crypto(e.to_string()))?;
+    Ok(key)
+}
 
-    let hash_bytes = hash.hash.ok_or_else(|| VaultError::Crypto("no hash output".into()))?;
-    let bytes = hash_bytes.as_bytes();
+/// Picks parameters that take about `target` to derive a key on this machine.
+/// Memory cost is scaled first and time cost only once memory hits its cap,
+/// up to `MAX_BENCH_T_COST`; the result is never weaker than
+/// `KdfParams::DEFAULT`.
+pub fn benchmark_kdf(target: Duration) -> Result<KdfParams> {
+    let base = KdfParams::DEFAULT;
+    let salt = generate_salt_b64();
 
-    if bytes.len() < KEY_LEN {
-        return Err(VaultError::Crypto("derived key too short".into()));
+    let started = Instant::now();
+    derive_key("financevault-benchmark", &salt, &base)?;
+    let elapsed = started.elapsed().as_secs_f64().max(f64::EPSILON);
+
+    let scale = target.as_secs_f64() / elapsed;
+    if scale <= 1.0 {
+        return Ok(base);
     }
 
-    let mut key = [0u8; KEY_LEN];
-    key.copy_from_slice(&bytes[..KEY_LEN]);
-    Ok(DerivedKey(key))
+    let wanted_m_cost = base.m_cost as f64 * scale;
+    let m_cost = (wanted_m_cost.min(MAX_BENCH_M_COST as f64) as u32 / 1024 * 1024).max(base.m_cost);
+    let t_cost = ((base.t_cost as f64 * wanted_m_cost / m_cost as f64).round() as u32)
+        .clamp(base.t_cost, MAX_BENCH_T_COST);
+
+    Ok(KdfParams {
+        m_cost,
+        t_cost,
+        p_cost: base.p_cost,
+    })
 }
 
 pub fn generate_salt_b64() -> String {
@@ -53,7 +118,7 @@ pub fn encrypt(key: &DerivedKey, plaintext: &[u8]) -> Result<String> {
     Ok(STANDARD.encode(&combined))
 }
 
-pub fn decrypt(key: &DerivedKey, encoded: &str) -> Result<Vec<u8>> {
+pub fn decrypt(key: &DerivedKey, encoded: &str) -> Result<Zeroizing<Vec<u8>>> {
     let combined = STANDARD
         .decode(encoded)
         .map_err(|e| VaultError::Crypto(e.to_string()))?;
@@ -68,5 +133,27 @@ pub fn decrypt(key: &DerivedKey, encoded: &str) -> Result<Vec<u8>> {
 
     cipher
         .decrypt(nonce, ciphertext)
+        .map(Zeroizing::new)
         .map_err(|_| VaultError::InvalidPassphrase)
 }
+
+/// Encrypts a single column value using its `ToString` representation.
+pub fn encrypt_value<T: ToString + ?Sized>(key: &DerivedKey, value: &T) -> Result<String> {
+    let plain = Zeroizing::new(value.to_string());
+    encrypt(key, plain.as_bytes())
+}
+
+/// Decrypts a column value written by `encrypt_value` and parses it back.
+pub fn decrypt_value<T>(key: &DerivedKey, encoded: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let mut plain = decrypt(key, encoded)?;
+    let text = Zeroizing::new(
+        String::from_utf8(std::mem::take(&mut *plain))
+            .map_err(|e| VaultError::Crypto(e.utf8_error().to_string()))?,
+    );
+    text.parse::<T>()
+        .map_err(|e| VaultError::Crypto(format!("invalid encrypted value: {e}")))
+}
@@ -1,5 +1,58 @@
-use rusqlite::{Connection, Result as SqlResult};
+use chrono::Utc;
+use rusqlite::types::{Type, Value};
+use rusqlite::{Connection, OptionalExtension, Result as SqlResult, Row};
+use std::collections::HashMap;
+use std::fmt::Display;
 use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::crypto::{decrypt, decrypt_value, encrypt, encrypt_value, DerivedKey};
+use crate::error::{Result, VaultError};
+use crate::money::{Money, Quantity, DEFAULT_CURRENCY};
+
+/// Columns that hold user data and are stored encrypted with the vault key.
+/// Ids, dates, currencies and enum columns stay plaintext so SQLite can still
+/// filter, order and CHECK them.
+pub const ENCRYPTED_COLUMNS: &[(&str, &[&str])] = &[
+    // Before `expenses`, which reference them; backup merge copies in order.
+    ("categories", &["name"]),
+    ("expenses", &["title", "amount", "category"]),
+    ("subscriptions", &["name", "amount"]),
+    (
+        "portfolio_positions",
+        &["ticker", "isin", "name", "quantity", "avg_buy_price", "country"],
+    ),
+    ("budget", &["income"]),
+    ("category_budgets", &["amount"]),
+    ("envelope_transfers", &["amount"]),
+    ("income", &["source", "amount"]),
+    ("recurring_income", &["source", "amount"]),
+    ("savings_plans", &["name", "isin", "ticker", "amount"]),
+    ("change_log", &["before_json", "after_json"]),
+];
+
+/// `vault_meta` keys whose values are encrypted with the vault key.
+pub const ENCRYPTED_META_KEYS: &[&str] = &["totp_secret"];
+
+/// Encrypted columns holding decimals, with the SQL expression for the
+/// row's currency. `None` marks a quantity rather than money.
+const DECIMAL_COLUMNS: &[(&str, &str, Option<&str>)] = &[
+    ("expenses", "amount", Some("currency")),
+    ("subscriptions", "amount", Some("currency")),
+    ("portfolio_positions", "quantity", None),
+    ("portfolio_positions", "avg_buy_price", Some("currency")),
+    ("budget", "income", Some("'EUR'")),
+    ("savings_plans", "amount", Some("currency")),
+];
+
+/// Version of the row format, tracked in `vault_meta.data_version`.
+/// Vaults without the key predate field encryption and hold plaintext rows;
+/// version 1 stored amounts as float text, version 2 as exact decimals in
+/// the currency's minor unit. From version 3 every expense links to a row
+/// of `categories`; from version 4 income is kept as `income` entries, and
+/// the income set on budget months until then was booked once as entries.
+pub const DATA_VERSION: u32 = 4;
 
 pub fn open(db_path: &Path) -> SqlResult<Connection> {
     let conn = Connection::open(db_path)?;
@@ -10,64 +63,735 @@ pub fn open(db_path: &Path) -> SqlResult<Connection> {
     Ok(conn)
 }
 
-pub fn migrate(conn: &Connection) -> SqlResult<()> {
-    conn.execute_batch("
-        CREATE TABLE IF NOT EXISTS vault_meta (
-            key   TEXT PRIMARY KEY,
-            value TEXT NOT NULL
-        );
-
-        CREATE TABLE IF NOT EXISTS expenses (
-            id          TEXT PRIMARY KEY,
-            title       TEXT NOT NULL,
-            amount      REAL NOT NULL,
-            currency    TEXT NOT NULL DEFAULT 'EUR',
-            category    TEXT NOT NULL,
-            date        TEXT NOT NULL,
-            created_at  TEXT NOT NULL
-        );
-
-        CREATE TABLE IF NOT EXISTS subscriptions (
-            id           TEXT PRIMARY KEY,
-            name         TEXT NOT NULL,
-            amount       REAL NOT NULL,
-            currency     TEXT NOT NULL DEFAULT 'EUR',
-            billing      TEXT NOT NULL CHECK(billing IN ('monthly','yearly')),
-            next_billing TEXT NOT NULL,
-            created_at   TEXT NOT NULL
-        );
-
-        CREATE TABLE IF NOT EXISTS portfolio_positions (
-            id             TEXT PRIMARY KEY,
-            ticker         TEXT NOT NULL,
-            isin           TEXT,
-            name           TEXT NOT NULL,
-            asset_type     TEXT NOT NULL CHECK(asset_type IN ('stock','etf','crypto','other')),
-            quantity       REAL NOT NULL,
-            avg_buy_price  REAL NOT NULL,
-            currency       TEXT NOT NULL DEFAULT 'EUR',
-            country        TEXT,
-            created_at     TEXT NOT NULL
-        );
-
-        CREATE TABLE IF NOT EXISTS budget (
-            id         TEXT PRIMARY KEY,
-            month      TEXT NOT NULL,
-            income     REAL NOT NULL,
-            created_at TEXT NOT NULL
-        );
-
-        CREATE TABLE IF NOT EXISTS savings_plans (
-            id           TEXT PRIMARY KEY,
-            name         TEXT NOT NULL,
-            isin         TEXT NOT NULL,
-            ticker       TEXT NOT NULL,
-            amount       REAL NOT NULL,
-            currency     TEXT NOT NULL DEFAULT 'EUR',
-            interval     TEXT NOT NULL CHECK(interval IN ('monthly','quarterly','yearly')),
-            next_date    TEXT NOT NULL,
-            created_at   TEXT NOT NULL
-        );
-    ")?;
+/// Schema migrations, applied in order. A vault's `PRAGMA user_version` is
+/// the number of migrations it has had, so entries must never be edited or
+/// reordered once released; changes go into a new entry at the end.
+///
+/// The first entry is the schema from before versioning and uses
+/// `IF NOT EXISTS`, because those vaults already have it at version 0.
+pub const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "
+    CREATE TABLE IF NOT EXISTS vault_meta (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS expenses (
+        id          TEXT PRIMARY KEY,
+        title       TEXT NOT NULL,
+        amount      TEXT NOT NULL,
+        currency    TEXT NOT NULL DEFAULT 'EUR',
+        category    TEXT NOT NULL,
+        date        TEXT NOT NULL,
+        created_at  TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS subscriptions (
+        id           TEXT PRIMARY KEY,
+        name         TEXT NOT NULL,
+        amount       TEXT NOT NULL,
+        currency     TEXT NOT NULL DEFAULT 'EUR',
+        billing      TEXT NOT NULL CHECK(billing IN ('monthly','yearly')),
+        next_billing TEXT NOT NULL,
+        created_at   TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS portfolio_positions (
+        id             TEXT PRIMARY KEY,
+        ticker         TEXT NOT NULL,
+        isin           TEXT,
+        name           TEXT NOT NULL,
+        asset_type     TEXT NOT NULL CHECK(asset_type IN ('stock','etf','crypto','other')),
+        quantity       TEXT NOT NULL,
+        avg_buy_price  TEXT NOT NULL,
+        currency       TEXT NOT NULL DEFAULT 'EUR',
+        country        TEXT,
+        created_at     TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS budget (
+        id         TEXT PRIMARY KEY,
+        month      TEXT NOT NULL,
+        income     TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS savings_plans (
+        id           TEXT PRIMARY KEY,
+        name         TEXT NOT NULL,
+        isin         TEXT NOT NULL,
+        ticker       TEXT NOT NULL,
+        amount       TEXT NOT NULL,
+        currency     TEXT NOT NULL DEFAULT 'EUR',
+        interval     TEXT NOT NULL CHECK(interval IN ('monthly','quarterly','yearly')),
+        next_date    TEXT NOT NULL,
+        created_at   TEXT NOT NULL
+    );
+    ",
+    // 2: amount columns of vaults from before field encryption were REAL;
+    // they hold exact decimal text now, so give them TEXT affinity
+    "
+    CREATE TABLE expenses_new (
+        id          TEXT PRIMARY KEY,
+        title       TEXT NOT NULL,
+        amount      TEXT NOT NULL,
+        currency    TEXT NOT NULL DEFAULT 'EUR',
+        category    TEXT NOT NULL,
+        date        TEXT NOT NULL,
+        created_at  TEXT NOT NULL
+    );
+    INSERT INTO expenses_new SELECT id, title, CAST(amount AS TEXT), currency, category, date, created_at FROM expenses;
+    DROP TABLE expenses;
+    ALTER TABLE expenses_new RENAME TO expenses;
+
+    CREATE TABLE subscriptions_new (
+        id           TEXT PRIMARY KEY,
+        name         TEXT NOT NULL,
+        amount       TEXT NOT NULL,
+        currency     TEXT NOT NULL DEFAULT 'EUR',
+        billing      TEXT NOT NULL CHECK(billing IN ('monthly','yearly')),
+        next_billing TEXT NOT NULL,
+        created_at   TEXT NOT NULL
+    );
+    INSERT INTO subscriptions_new SELECT id, name, CAST(amount AS TEXT), currency, billing, next_billing, created_at FROM subscriptions;
+    DROP TABLE subscriptions;
+    ALTER TABLE subscriptions_new RENAME TO subscriptions;
+
+    CREATE TABLE portfolio_positions_new (
+        id             TEXT PRIMARY KEY,
+        ticker         TEXT NOT NULL,
+        isin           TEXT,
+        name           TEXT NOT NULL,
+        asset_type     TEXT NOT NULL CHECK(asset_type IN ('stock','etf','crypto','other')),
+        quantity       TEXT NOT NULL,
+        avg_buy_price  TEXT NOT NULL,
+        currency       TEXT NOT NULL DEFAULT 'EUR',
+        country        TEXT,
+        created_at     TEXT NOT NULL
+    );
+    INSERT INTO portfolio_positions_new SELECT id, ticker, isin, name, asset_type, CAST(quantity AS TEXT), CAST(avg_buy_price AS TEXT), currency, country, created_at FROM portfolio_positions;
+    DROP TABLE portfolio_positions;
+    ALTER TABLE portfolio_positions_new RENAME TO portfolio_positions;
+
+    CREATE TABLE budget_new (
+        id         TEXT PRIMARY KEY,
+        month      TEXT NOT NULL,
+        income     TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    INSERT INTO budget_new SELECT id, month, CAST(income AS TEXT), created_at FROM budget;
+    DROP TABLE budget;
+    ALTER TABLE budget_new RENAME TO budget;
+
+    CREATE TABLE savings_plans_new (
+        id           TEXT PRIMARY KEY,
+        name         TEXT NOT NULL,
+        isin         TEXT NOT NULL,
+        ticker       TEXT NOT NULL,
+        amount       TEXT NOT NULL,
+        currency     TEXT NOT NULL DEFAULT 'EUR',
+        interval     TEXT NOT NULL CHECK(interval IN ('monthly','quarterly','yearly')),
+        next_date    TEXT NOT NULL,
+        created_at   TEXT NOT NULL
+    );
+    INSERT INTO savings_plans_new SELECT id, name, isin, ticker, CAST(amount AS TEXT), currency, interval, next_date, created_at FROM savings_plans;
+    DROP TABLE savings_plans;
+    ALTER TABLE savings_plans_new RENAME TO savings_plans;
+    ",
+    // 3: soft delete; rows with a `deleted_at` are in the trash
+    "
+    ALTER TABLE expenses ADD COLUMN deleted_at TEXT;
+    ALTER TABLE subscriptions ADD COLUMN deleted_at TEXT;
+    ALTER TABLE portfolio_positions ADD COLUMN deleted_at TEXT;
+    ALTER TABLE savings_plans ADD COLUMN deleted_at TEXT;
+    ",
+    // 4: append-only log of changes with encrypted before/after snapshots
+    "
+    CREATE TABLE change_log (
+        id           TEXT PRIMARY KEY,
+        entity       TEXT NOT NULL,
+        entity_id    TEXT NOT NULL,
+        action       TEXT NOT NULL CHECK(action IN ('create','update','delete','restore','undo')),
+        before_json  TEXT,
+        after_json   TEXT,
+        reverts      TEXT REFERENCES change_log(id),
+        changed_at   TEXT NOT NULL
+    );
+    CREATE INDEX change_log_entity ON change_log(entity, entity_id);
+    CREATE INDEX change_log_reverts ON change_log(reverts);
+    ",
+    // 5: calls to the local HTTP API; `outcome` is 'ok' or an error code
+    "
+    CREATE TABLE api_audit_log (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        called_at  TEXT NOT NULL,
+        method     TEXT NOT NULL,
+        outcome    TEXT NOT NULL
+    );
+    ",
+    // 6: categories with nesting; `expenses.category` keeps the name as of
+    // the expense's last write, `category_id` is what counts. Existing
+    // expenses are linked by `migrate_data`, since their names are encrypted
+    "
+    CREATE TABLE categories (
+        id          TEXT PRIMARY KEY,
+        name        TEXT NOT NULL,
+        parent_id   TEXT REFERENCES categories(id),
+        color       TEXT,
+        icon        TEXT,
+        archived    INTEGER NOT NULL DEFAULT 0 CHECK(archived IN (0, 1)),
+        created_at  TEXT NOT NULL
+    );
+    CREATE INDEX categories_parent ON categories(parent_id);
+    ALTER TABLE expenses ADD COLUMN category_id TEXT REFERENCES categories(id);
+    CREATE INDEX expenses_category ON expenses(category_id);
+    ",
+    // 7: spending limits per category and month, in the default currency
+    "
+    CREATE TABLE category_budgets (
+        id          TEXT PRIMARY KEY,
+        category_id TEXT NOT NULL REFERENCES categories(id),
+        month       TEXT NOT NULL,
+        amount      TEXT NOT NULL,
+        created_at  TEXT NOT NULL,
+        UNIQUE(category_id, month)
+    );
+    ",
+    // 8: money moved between category envelopes, in the default currency
+    "
+    CREATE TABLE envelope_transfers (
+        id               TEXT PRIMARY KEY,
+        month            TEXT NOT NULL,
+        from_category_id TEXT NOT NULL REFERENCES categories(id),
+        to_category_id   TEXT NOT NULL REFERENCES categories(id),
+        amount           TEXT NOT NULL,
+        created_at       TEXT NOT NULL
+    );
+    CREATE INDEX envelope_transfers_month ON envelope_transfers(month);
+    ",
+    // 9: income as individual entries, and definitions that book them on a
+    // schedule; `budget.income` stays as the planned figure
+    "
+    CREATE TABLE income (
+        id          TEXT PRIMARY KEY,
+        source      TEXT NOT NULL,
+        amount      TEXT NOT NULL,
+        currency    TEXT NOT NULL DEFAULT 'EUR',
+        date        TEXT NOT NULL,
+        created_at  TEXT NOT NULL,
+        deleted_at  TEXT
+    );
+    CREATE INDEX income_date ON income(date);
+    CREATE TABLE recurring_income (
+        id          TEXT PRIMARY KEY,
+        source      TEXT NOT NULL,
+        amount      TEXT NOT NULL,
+        currency    TEXT NOT NULL DEFAULT 'EUR',
+        interval    TEXT NOT NULL CHECK(interval IN ('monthly','quarterly','yearly')),
+        next_date   TEXT NOT NULL,
+        anchor_date TEXT NOT NULL,
+        created_at  TEXT NOT NULL,
+        deleted_at  TEXT
+    );
+    ",
+    // 10: API requests turned away before reaching the vault are trimmed
+    // apart from answered calls, so they cannot push those out of the log
+    "
+    ALTER TABLE api_audit_log ADD COLUMN rejected INTEGER NOT NULL DEFAULT 0;
+    ",
+];
+
+/// Brings the schema up to the latest entry of `MIGRATIONS`. Each migration
+/// runs in its own transaction together with the `user_version` bump, so an
+/// interrupted upgrade resumes from the last one that committed.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    apply_migrations(conn, MIGRATIONS)
+}
+
+fn apply_migrations(conn: &mut Connection, migrations: &[&str]) -> Result<()> {
+    let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version as usize > migrations.len() {
+        return Err(VaultError::UnsupportedSchema { version });
+    }
+
+    for (applied, sql) in migrations.iter().enumerate().skip(version as usize) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", applied as u32 + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Writes a consistent copy of the open database to `dest`, which must not
+/// exist yet. Safe to run while other connections use the vault.
+pub fn snapshot(conn: &Connection, dest: &Path) -> SqlResult<()> {
+    conn.execute("VACUUM INTO ?1", [dest.to_string_lossy()])?;
+    Ok(())
+}
+
+pub fn meta_get(conn: &Connection, key: &str) -> SqlResult<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM vault_meta WHERE key = ?1",
+        [key],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()
+}
+
+pub fn meta_set(conn: &Connection, key: &str, value: &str) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO vault_meta (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        [key, value],
+    )?;
+    Ok(())
+}
+
+/// Reads an encrypted column inside a row mapper and parses the plaintext.
+pub fn get_decrypted<T>(row: &Row, idx: usize, key: &DerivedKey) -> SqlResult<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let encoded: String = row.get(idx)?;
+    decrypt_value(key, &encoded)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+/// Like `get_decrypted`, for nullable columns.
+pub fn get_decrypted_opt<T>(row: &Row, idx: usize, key: &DerivedKey) -> SqlResult<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match row.get::<_, Option<String>>(idx)? {
+        Some(encoded) => decrypt_value(key, &encoded)
+            .map(Some)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e))),
+        None => Ok(None),
+    }
+}
+
+/// Brings the stored rows up to `DATA_VERSION`. Needs the vault key, so it
+/// runs on unlock rather than together with the schema in `migrate`.
+pub fn migrate_data(conn: &mut Connection, key: &DerivedKey) -> Result<()> {
+    let version: u32 = meta_get(conn, "data_version")?
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    if version >= DATA_VERSION {
+        return Ok(());
+    }
+
+    let tx = conn.transaction()?;
+    if version < 1 {
+        encrypt_plaintext_rows(&tx, key)?;
+    }
+    if version < 2 {
+        normalize_decimals(&tx, key)?;
+    }
+    if version < 3 {
+        link_expense_categories(&tx, key)?;
+    }
+    if version < 4 {
+        record_budget_income(&tx, key)?;
+    }
+    meta_set(&tx, "data_version", &DATA_VERSION.to_string())?;
+    tx.commit()?;
     Ok(())
 }
+
+/// Encrypts every value in `ENCRYPTED_COLUMNS` of a vault written before
+/// field encryption existed. NULLs stay NULL.
+fn encrypt_plaintext_rows(conn: &Connection, key: &DerivedKey) -> Result<()> {
+    rewrite_encrypted_columns(conn, |value| {
+        let plain = match value {
+            Value::Null => return Ok(Value::Null),
+            Value::Integer(i) => i.to_string(),
+            Value::Real(f) => f.to_string(),
+            Value::Text(t) => t,
+            Value::Blob(b) => String::from_utf8_lossy(&b).into_owned(),
+        };
+        Ok(Value::Text(encrypt_value(key, &plain)?))
+    })
+}
+
+/// Rewrites amounts stored as float text (`12.990000000000002`) as exact
+/// decimals rounded to their currency (`12.99`), and quantities to eight
+/// decimal places.
+fn normalize_decimals(conn: &Connection, key: &DerivedKey) -> Result<()> {
+    for (table, column, currency) in DECIMAL_COLUMNS {
+        let rows = conn
+            .prepare(&format!(
+                "SELECT id, {column}, {} FROM {table}",
+                currency.unwrap_or("NULL")
+            ))?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        for (id, encoded, currency) in rows {
+            let normalized = match currency {
+                Some(currency) => decrypt_value::<Money>(key, &encoded)?
+                    .in_currency(&currency)
+                    .to_string(),
+                None => decrypt_value::<Quantity>(key, &encoded)?.to_string(),
+            };
+            conn.execute(
+                &format!("UPDATE {table} SET {column} = ?1 WHERE id = ?2"),
+                [encrypt_value(key, &normalized)?, id],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Name given to expenses whose category text is blank.
+const UNNAMED_CATEGORY: &str = "Uncategorized";
+
+/// Creates a category for every distinct expense category text and links
+/// the expenses to it. Texts are compared trimmed and ignoring case, so
+/// "Food" and "food " end up in one category named after the oldest
+/// expense; anything else that belongs together is left to a merge.
+fn link_expense_categories(conn: &Connection, key: &DerivedKey) -> Result<()> {
+    let rows = conn
+        .prepare("SELECT id, category FROM expenses WHERE category_id IS NULL ORDER BY created_at")?
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    let now = Utc::now().to_rfc3339();
+    let mut categories: HashMap<String, String> = HashMap::new();
+    for (id, encoded) in rows {
+        let text: String = decrypt_value(key, &encoded)?;
+        let name = match text.trim() {
+            "" => UNNAMED_CATEGORY,
+            name => name,
+        };
+        let category_id = match categories.get(&name.to_lowercase()) {
+            Some(category_id) => category_id.clone(),
+            None => {
+                let category_id = Uuid::new_v4().to_string();
+                conn.execute(
+                    "INSERT INTO categories (id, name, created_at) VALUES (?1, ?2, ?3)",
+                    [&category_id, &encrypt_value(key, name)?, &now],
+                )?;
+                categories.insert(name.to_lowercase(), category_id.clone());
+                category_id
+            }
+        };
+        conn.execute("UPDATE expenses SET category_id = ?1 WHERE id = ?2", [category_id, id])?;
+    }
+    Ok(())
+}
+
+/// Source given to the entries made from budget months' income.
+const BUDGET_INCOME_SOURCE: &str = "Income";
+
+/// Books the income of every budget month as one entry on the first of the
+/// month, so summaries that add up entries keep showing it. Entry ids are
+/// made from the month, which is also what a backup merge matches budget
+/// months on, so restoring an old backup never books a month twice.
+fn record_budget_income(conn: &Connection, key: &DerivedKey) -> Result<()> {
+    let rows = conn
+        .prepare("SELECT month, income FROM budget")?
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, get_money(row, 1, key, DEFAULT_CURRENCY)?))
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    let now = Utc::now().to_rfc3339();
+    for (month, income) in rows.into_iter().filter(|(_, income)| *income != Money::ZERO) {
+        conn.execute(
+            "INSERT OR IGNORE INTO income (id, source, amount, currency, date, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            [
+                format!("budget-{month}"),
+                encrypt_value(key, BUDGET_INCOME_SOURCE)?,
+                encrypt_value(key, &income)?,
+                DEFAULT_CURRENCY.to_string(),
+                format!("{month}-01"),
+                now.clone(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Reads an encrypted amount and puts it in the minor unit of `currency`.
+pub fn get_money(row: &Row, idx: usize, key: &DerivedKey, currency: &str) -> SqlResult<Money> {
+    Ok(get_decrypted::<Money>(row, idx, key)?.in_currency(currency))
+}
+
+/// Re-encrypts every encrypted column and meta value from `old` to `new`.
+/// Callers run this inside the transaction that also stores the new salt
+/// and canary, so a failure leaves the vault readable with `old`.
+pub fn rekey(conn: &Connection, old: &DerivedKey, new: &DerivedKey) -> Result<()> {
+    rewrite_encrypted_columns(conn, |value| match value {
+        Value::Text(encoded) => Ok(Value::Text(encrypt(new, &decrypt(old, &encoded)?)?)),
+        other => Ok(other),
+    })?;
+
+    for meta_key in ENCRYPTED_META_KEYS {
+        if let Some(encoded) = meta_get(conn, meta_key)? {
+            meta_set(conn, meta_key, &encrypt(new, &decrypt(old, &encoded)?)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn rewrite_encrypted_columns(
+    conn: &Connection,
+    mut rewrite: impl FnMut(Value) -> Result<Value>,
+) -> Result<()> {
+    for (table, columns) in ENCRYPTED_COLUMNS {
+        let mut stmt = conn.prepare(&format!("SELECT id, {} FROM {table}", columns.join(", ")))?;
+        let rows = stmt
+            .query_map([], |row| {
+                let id: String = row.get(0)?;
+                let values = (1..=columns.len())
+                    .map(|i| row.get::<_, Value>(i))
+                    .collect::<SqlResult<Vec<_>>>()?;
+                Ok((id, values))
+            })?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        for (id, values) in rows {
+            for (column, value) in columns.iter().zip(values) {
+                if value == Value::Null {
+                    continue;
+                }
+                conn.execute(
+                    &format!("UPDATE {table} SET {column} = ?1 WHERE id = ?2"),
+                    rusqlite::params![rewrite(value)?, id],
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KEY_LEN;
+
+    const SCHEMA_V0: &str = include_str!("../tests/fixtures/schema_v0.sql");
+
+    fn user_version(conn: &Connection) -> u32 {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap()
+    }
+
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            == 1
+    }
+
+    #[test]
+    fn fresh_database_gets_every_migration() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(user_version(&conn) as usize, MIGRATIONS.len());
+        for (table, _) in ENCRYPTED_COLUMNS {
+            assert!(table_exists(&conn, table), "{table} missing");
+        }
+    }
+
+    #[test]
+    fn migrate_is_idempotent() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn) as usize, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn unversioned_vault_keeps_its_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA_V0).unwrap();
+        assert_eq!(user_version(&conn), 0);
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn) as usize, MIGRATIONS.len());
+
+        let key = DerivedKey([7; KEY_LEN]);
+        migrate_data(&mut conn, &key).unwrap();
+        let (title, amount): (String, String) = conn
+            .query_row("SELECT title, amount FROM expenses WHERE id = 'e1'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(decrypt_value::<String>(&key, &title).unwrap(), "Groceries");
+        assert_eq!(decrypt_value::<f64>(&key, &amount).unwrap(), 42.5);
+        assert_eq!(meta_get(&conn, "salt").unwrap().as_deref(), Some("c2FsdHNhbHRzYWx0c2FsdA"));
+    }
+
+    #[test]
+    fn unversioned_vault_amounts_become_exact_decimals() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA_V0).unwrap();
+        migrate(&mut conn).unwrap();
+        let key = DerivedKey([7; KEY_LEN]);
+        migrate_data(&mut conn, &key).unwrap();
+
+        let stored = |sql: &str| -> String {
+            let encoded: String = conn.query_row(sql, [], |row| row.get(0)).unwrap();
+            decrypt_value(&key, &encoded).unwrap()
+        };
+        assert_eq!(stored("SELECT amount FROM expenses"), "42.50");
+        assert_eq!(stored("SELECT amount FROM subscriptions"), "12.99");
+        assert_eq!(stored("SELECT quantity FROM portfolio_positions WHERE id = 'p1'"), "3.25");
+        assert_eq!(stored("SELECT avg_buy_price FROM portfolio_positions WHERE id = 'p1'"), "101.40");
+        assert_eq!(stored("SELECT income FROM budget WHERE id = 'b1'"), "3200.00");
+        assert_eq!(stored("SELECT amount FROM savings_plans"), "150.00");
+    }
+
+    #[test]
+    fn unversioned_vault_amounts_in_exponent_form_are_read() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA_V0).unwrap();
+        migrate(&mut conn).unwrap();
+        let key = DerivedKey([7; KEY_LEN]);
+        migrate_data(&mut conn, &key).unwrap();
+
+        let stored = |sql: &str| -> String {
+            let encoded: String = conn.query_row(sql, [], |row| row.get(0)).unwrap();
+            decrypt_value(&key, &encoded).unwrap()
+        };
+        assert_eq!(stored("SELECT quantity FROM portfolio_positions WHERE id = 'p2'"), "0.00005");
+        assert_eq!(stored("SELECT avg_buy_price FROM portfolio_positions WHERE id = 'p2'"), "0.00");
+        assert_eq!(stored("SELECT income FROM budget WHERE id = 'b2'"), "1000000000000000.00");
+    }
+
+    #[test]
+    fn float_amounts_are_rounded_to_their_currency() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        let key = DerivedKey([7; KEY_LEN]);
+        meta_set(&conn, "data_version", "1").unwrap();
+        for (id, amount, currency) in [("a", "12.990000000000002", "EUR"), ("b", "1500.4", "JPY")] {
+            conn.execute(
+                "INSERT INTO expenses (id, title, amount, currency, category, date, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, '2024-03-02', '2024-03-02')",
+                [
+                    id,
+                    &encrypt_value(&key, "t").unwrap(),
+                    &encrypt_value(&key, amount).unwrap(),
+                    currency,
+                    &encrypt_value(&key, "c").unwrap(),
+                ],
+            )
+            .unwrap();
+        }
+
+        migrate_data(&mut conn, &key).unwrap();
+        let amount = |id: &str| -> String {
+            let encoded: String = conn
+                .query_row("SELECT amount FROM expenses WHERE id = ?1", [id], |row| row.get(0))
+                .unwrap();
+            decrypt_value(&key, &encoded).unwrap()
+        };
+        assert_eq!(amount("a"), "12.99");
+        assert_eq!(amount("b"), "1500");
+    }
+
+    #[test]
+    fn expense_categories_become_linked_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        let key = DerivedKey([7; KEY_LEN]);
+        meta_set(&conn, "data_version", "2").unwrap();
+        for (id, category) in [("a", "Food"), ("b", "food "), ("c", "Groceries")] {
+            conn.execute(
+                "INSERT INTO expenses (id, title, amount, currency, category, date, created_at)
+                 VALUES (?1, ?2, ?3, 'EUR', ?4, '2024-03-02', ?1)",
+                [
+                    id,
+                    &encrypt_value(&key, "t").unwrap(),
+                    &encrypt_value(&key, "1.00").unwrap(),
+                    &encrypt_value(&key, category).unwrap(),
+                ],
+            )
+            .unwrap();
+        }
+
+        migrate_data(&mut conn, &key).unwrap();
+        let category_of = |id: &str| -> (String, String) {
+            let (category_id, encoded): (String, String) = conn
+                .query_row(
+                    "SELECT c.id, c.name FROM expenses e JOIN categories c ON c.id = e.category_id
+                     WHERE e.id = ?1",
+                    [id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            (category_id, decrypt_value(&key, &encoded).unwrap())
+        };
+        assert_eq!(category_of("a"), category_of("b"));
+        assert_eq!(category_of("a").1, "Food");
+        assert_eq!(category_of("c").1, "Groceries");
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM categories", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn budget_income_becomes_an_entry() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        let key = DerivedKey([7; KEY_LEN]);
+        meta_set(&conn, "data_version", "3").unwrap();
+        for (id, month, income) in [("a", "2024-03", "2500.00"), ("b", "2024-04", "0.00")] {
+            conn.execute(
+                "INSERT INTO budget (id, month, income, created_at) VALUES (?1, ?2, ?3, ?1)",
+                [id, month, &encrypt_value(&key, income).unwrap()],
+            )
+            .unwrap();
+        }
+
+        migrate_data(&mut conn, &key).unwrap();
+        let (id, date, amount): (String, String, String) = conn
+            .query_row("SELECT id, date, amount FROM income", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((id.as_str(), date.as_str()), ("budget-2024-03", "2024-03-01"));
+        assert_eq!(decrypt_value::<Money>(&key, &amount).unwrap(), "2500".parse().unwrap());
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM income", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn newer_schema_is_rejected() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let newer = MIGRATIONS.len() as u32 + 1;
+        conn.pragma_update(None, "user_version", newer).unwrap();
+
+        let err = migrate(&mut conn).unwrap_err();
+        assert!(matches!(err, VaultError::UnsupportedSchema { version } if version == newer));
+    }
+
+    #[test]
+    fn failed_migration_rolls_back_alone() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            "CREATE TABLE first (id TEXT);",
+            "CREATE TABLE second (id TEXT); INSERT INTO missing VALUES (1);",
+        ];
+
+        assert!(apply_migrations(&mut conn, &migrations).is_err());
+        assert_eq!(user_version(&conn), 1);
+        assert!(table_exists(&conn, "first"));
+        assert!(!table_exists(&conn, "second"));
+    }
+}
@@ -1,6 +1,10 @@
+use serde::ser::SerializeStruct;
 use serde::Serialize;
+use serde_json::{json, Value};
 use thiserror::Error;
 
+use crate::validation::FieldError;
+
 #[derive(Debug, Error)]
 pub enum VaultError {
     #[error("database error: {0}")]
@@ -9,22 +13,80 @@ pub enum VaultError {
     Crypto(String),
     #[error("vault is locked")]
     Locked,
+    #[error("{entity} {id} not found")]
+    NotFound { entity: &'static str, id: String },
     #[error("vault not initialized")]
     NotInitialized,
+    #[error("vault already exists")]
+    AlreadyInitialized,
     #[error("invalid passphrase")]
     InvalidPassphrase,
     #[error("invalid totp code")]
     InvalidTotp,
+    #[error("too many failed unlock attempts, retry in {retry_after_secs}s")]
+    Throttled { retry_after_secs: u64 },
+    #[error("vault schema version {version} is newer than this app supports")]
+    UnsupportedSchema { version: u32 },
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("network error: {0}")]
+    Network(String),
+    #[error("parse error: {0}")]
+    Parse(String),
+    #[error("invalid input: {}", list_fields(.0))]
+    Validation(Vec<FieldError>),
+}
+
+fn list_fields(errors: &[FieldError]) -> String {
+    errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+}
+
+impl VaultError {
+    /// Stable identifier the frontend matches on instead of the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            VaultError::Database(_) => "database",
+            VaultError::Crypto(_) => "crypto",
+            VaultError::Locked => "locked",
+            VaultError::NotFound { .. } => "not_found",
+            VaultError::NotInitialized => "not_initialized",
+            VaultError::AlreadyInitialized => "already_initialized",
+            VaultError::InvalidPassphrase => "invalid_passphrase",
+            VaultError::InvalidTotp => "invalid_totp",
+            VaultError::Throttled { .. } => "throttled",
+            VaultError::UnsupportedSchema { .. } => "unsupported_schema",
+            VaultError::Io(_) => "io",
+            VaultError::Network(_) => "network",
+            VaultError::Parse(_) => "parse",
+            VaultError::Validation(_) => "validation",
+        }
+    }
+
+    /// Structured data for variants that carry more than a message.
+    pub fn details(&self) -> Value {
+        match self {
+            VaultError::NotFound { entity, id } => json!({ "entity": entity, "id": id }),
+            VaultError::Throttled { retry_after_secs } => {
+                json!({ "retry_after_secs": retry_after_secs })
+            }
+            VaultError::UnsupportedSchema { version } => json!({ "version": version }),
+            VaultError::Validation(errors) => json!({ "fields": errors }),
+            _ => Value::Null,
+        }
+    }
 }
 
+/// Sent to the frontend as `{ code, message, details }`.
 impl Serialize for VaultError {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(&self.to_string())
+        let mut error = serializer.serialize_struct("VaultError", 3)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.serialize_field("details", &self.details())?;
+        error.end()
     }
 }
 
@@ -1,14 +1,24 @@
+mod cli;
 mod commands;
 mod crypto;
 mod db;
 mod error;
+mod money;
+mod validation;
+mod vault;
 
 use std::sync::Mutex;
-use tauri::Manager;
+use tauri::{Manager, WindowEvent};
 
+use commands::api::ApiState;
 use commands::auth::VaultState;
 use commands::totp::TotpState;
 
+/// Entry point of the `financevault-cli` binary.
+pub fn run_cli() -> std::process::ExitCode {
+    cli::run(std::env::args().skip(1))
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -23,50 +33,112 @@ pub fn run() {
             std::fs::create_dir_all(&data_dir)?;
             let db_path = data_dir.join("vault.db");
 
-            app.manage(VaultState {
-                key: Mutex::new(None),
-                db_path,
-            });
+            app.manage(VaultState::new(db_path));
             app.manage(TotpState {
                 enabled: Mutex::new(false),
                 secret: Mutex::new(None),
             });
+            app.manage(ApiState::default());
+            commands::auto_lock::spawn_watcher(app.handle().clone());
+            commands::backup::spawn_auto_backup(app.handle().clone());
+            commands::trash::spawn_trash_purge(app.handle().clone());
+            commands::api::spawn_api_server(app.handle().clone());
             Ok(())
         })
+        .on_window_event(|window, event| {
+            if let WindowEvent::Focused(focused) = event {
+                commands::auto_lock::window_focus_changed(&window.state::<VaultState>(), *focused);
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             commands::auth::vault_exists,
             commands::auth::is_locked,
             commands::auth::setup_vault,
             commands::auth::unlock,
             commands::auth::lock,
+            commands::auth::change_passphrase,
+            commands::auth::get_kdf_params,
+            commands::auth::kdf_benchmark,
+            commands::auth::get_lockout_policy,
+            commands::auth::set_lockout_policy,
+            commands::auto_lock::get_auto_lock,
+            commands::auto_lock::set_auto_lock,
+            commands::backup::export_backup,
+            commands::backup::restore_backup,
+            commands::backup::get_auto_backup,
+            commands::backup::set_auto_backup,
+            commands::backup::list_backups,
+            commands::backup::restore_auto_backup,
+            commands::trash::get_trash,
+            commands::trash::empty_trash,
+            commands::trash::get_trash_settings,
+            commands::trash::set_trash_settings,
+            commands::history::undo_last_change,
+            commands::history::get_history,
+            commands::api::get_api_settings,
+            commands::api::set_api_settings,
+            commands::api::regenerate_api_token,
+            commands::api::get_api_audit_log,
             commands::totp::totp_generate_secret,
             commands::totp::totp_get_qr_base64,
             commands::totp::totp_get_url,
             commands::totp::totp_verify,
+            commands::totp::totp_get_config,
             commands::totp::totp_enable,
             commands::totp::totp_is_enabled,
+            commands::totp::totp_disable,
+            commands::totp::totp_rotate,
+            commands::totp::totp_regenerate_recovery_codes,
+            commands::totp::totp_recovery_codes_remaining,
             commands::expenses::get_expenses,
             commands::expenses::create_expense,
             commands::expenses::update_expense,
             commands::expenses::delete_expense,
+            commands::expenses::restore_expense,
+            commands::categories::get_categories,
+            commands::categories::create_category,
+            commands::categories::update_category,
+            commands::categories::delete_category,
+            commands::categories::merge_categories,
             commands::subscriptions::get_subscriptions,
             commands::subscriptions::create_subscription,
             commands::subscriptions::update_subscription,
             commands::subscriptions::delete_subscription,
+            commands::subscriptions::restore_subscription,
             commands::budget::get_budget_months,
             commands::budget::upsert_budget_month,
             commands::budget::get_budget_summary,
+            commands::budget::get_category_budgets,
+            commands::budget::set_category_budget,
+            commands::budget::delete_category_budget,
+            commands::budget::get_overspent_categories,
+            commands::budget::get_envelope_summary,
+            commands::budget::get_envelope_transfers,
+            commands::budget::move_between_envelopes,
             commands::yahoo::resolve_isin,
             commands::yahoo::fetch_quotes,
             commands::portfolio::get_positions,
             commands::portfolio::create_position,
             commands::portfolio::update_position,
             commands::portfolio::delete_position,
+            commands::portfolio::restore_position,
             commands::portfolio::import_pdf,
             commands::savings::get_savings_plans,
             commands::savings::create_savings_plan,
             commands::savings::update_savings_plan,
             commands::savings::delete_savings_plan,
+            commands::savings::restore_savings_plan,
+            commands::income::get_income,
+            commands::income::create_income,
+            commands::income::update_income,
+            commands::income::delete_income,
+            commands::income::restore_income,
+            commands::income::get_recurring_income,
+            commands::income::create_recurring_income,
+            commands::income::update_recurring_income,
+            commands::income::delete_recurring_income,
+            commands::income::restore_recurring_income,
+            commands::income::book_recurring_income,
             commands::dashboard::get_dashboard_summary,
         ])
         .run(tauri::generate_context!())
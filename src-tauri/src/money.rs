@@ -0,0 +1,383 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, Neg, Sub};
+use std::str::FromStr;
+
+/// Currency assumed where a row has none, such as budget income.
+pub const DEFAULT_CURRENCY: &str = "EUR";
+
+/// Most digits kept when parsing an amount before it is rounded to a
+/// currency. Enough for any ISO 4217 currency and for float noise like
+/// `0.30000000000000004` to round away.
+const MAX_PARSED_DIGITS: u32 = 9;
+
+/// Largest exponent accepted in text like `1.0e+15`; beyond it nothing fits
+/// an `i64` or everything rounds to zero anyway.
+const MAX_EXPONENT: u32 = 400;
+
+/// Number of minor-unit digits of an ISO 4217 currency. Currencies not
+/// listed use two, like the euro.
+pub fn minor_digits(currency: &str) -> u32 {
+    match currency {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF"
+        | "UGX" | "UYI" | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        "CLF" | "UYW" => 4,
+        _ => 2,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError(String);
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid decimal: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseDecimalError {}
+
+/// Divides, rounding half away from zero.
+fn div_round(n: i128, d: i128) -> i128 {
+    let (q, r) = (n / d, n % d);
+    if r.abs() * 2 >= d.abs() {
+        q + n.signum() * d.signum()
+    } else {
+        q
+    }
+}
+
+fn pow10(digits: u32) -> i128 {
+    10i128.pow(digits)
+}
+
+fn saturate(value: i128) -> i64 {
+    value.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// Rewrites exponent notation, which SQLite uses for small and large REAL
+/// values (`5.0e-05`), as a plain decimal (`0.0000500`). Text without an
+/// exponent is returned trimmed.
+fn plain_decimal(text: &str) -> Option<Cow<'_, str>> {
+    let text = text.trim();
+    let Some((mantissa, exponent)) = text.split_once(['e', 'E']) else {
+        return Some(Cow::Borrowed(text));
+    };
+    let exponent: i64 = exponent.parse().ok()?;
+    if exponent.unsigned_abs() > MAX_EXPONENT as u64 {
+        return None;
+    }
+    let (sign, unsigned) = match mantissa.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let digits = format!("{whole}{fraction}");
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let point = whole.len() as i64 + exponent;
+    let shifted = if point <= 0 {
+        format!("0.{}{digits}", "0".repeat(point.unsigned_abs() as usize))
+    } else if point as usize >= digits.len() {
+        format!("{digits}{}", "0".repeat(point as usize - digits.len()))
+    } else {
+        let (whole, fraction) = digits.split_at(point as usize);
+        format!("{whole}.{fraction}")
+    };
+    Some(Cow::Owned(format!("{sign}{shifted}")))
+}
+
+/// Parses a decimal such as `-12.345` or `1.2e-3` into an integer scaled by
+/// `10^digits`, rounding half away from zero.
+fn parse_scaled(text: &str, digits: u32) -> Option<i64> {
+    let text = plain_decimal(text)?;
+    let text = text.as_ref();
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    if !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut value: i128 = 0;
+    let mut fraction_digits = fraction.bytes();
+    let kept = whole
+        .bytes()
+        .chain((0..digits).map(|_| fraction_digits.next().unwrap_or(b'0')));
+    for digit in kept {
+        value = value.checked_mul(10)?.checked_add((digit - b'0') as i128)?;
+    }
+    if fraction_digits.next().is_some_and(|digit| digit >= b'5') {
+        value += 1;
+    }
+    i64::try_from(if negative { -value } else { value }).ok()
+}
+
+/// Formats `value / 10^digits` as a plain decimal with exactly `digits`
+/// fraction digits.
+fn format_scaled(value: i64, digits: u32) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let magnitude = value.unsigned_abs() as u128;
+    if digits == 0 {
+        return format!("{sign}{magnitude}");
+    }
+    let scale = pow10(digits) as u128;
+    format!(
+        "{sign}{}.{:0width$}",
+        magnitude / scale,
+        magnitude % scale,
+        width = digits as usize
+    )
+}
+
+/// An exact amount of money as an integer count of minor units, e.g. cents.
+/// Values read from the database are in the minor units of their row's
+/// currency (see `in_currency`); sums of amounts with different digits are
+/// exact as well.
+///
+/// Serialized as a JSON number in major units (`42.5`), which is what the
+/// frontend has always sent and received.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Money {
+    minor: i64,
+    digits: u32,
+}
+
+impl Money {
+    pub const ZERO: Money = Money { minor: 0, digits: 0 };
+
+    /// Converts a float in major units, rounding to the currency's minor
+    /// unit. Goes through the shortest decimal representation, so `1.005`
+    /// becomes 1.01 rather than whatever `1.005 * 100.0` happens to be.
+    /// NaN, infinities and amounts too large to store are refused.
+    pub fn from_major(major: f64, currency: &str) -> Result<Money, ParseDecimalError> {
+        Ok(Money::from_f64(major)?.in_currency(currency))
+    }
+
+    fn from_f64(major: f64) -> Result<Money, ParseDecimalError> {
+        major.to_string().parse()
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.minor < 0
+    }
+
+    pub fn to_major(self) -> f64 {
+        self.minor as f64 / pow10(self.digits) as f64
+    }
+
+    /// Rounds (half away from zero) or extends to the minor unit of `currency`.
+    pub fn in_currency(self, currency: &str) -> Money {
+        self.with_digits(minor_digits(currency))
+    }
+
+    fn with_digits(self, digits: u32) -> Money {
+        let minor = if digits >= self.digits {
+            saturate(self.minor as i128 * pow10(digits - self.digits))
+        } else {
+            saturate(div_round(self.minor as i128, pow10(self.digits - digits)))
+        };
+        Money { minor, digits }
+    }
+
+    /// Divides by `divisor`, rounding to the nearest minor unit. Used to
+    /// spread yearly and quarterly amounts over months.
+    pub fn div_round(self, divisor: i64) -> Money {
+        Money {
+            minor: saturate(div_round(self.minor as i128, divisor as i128)),
+            digits: self.digits,
+        }
+    }
+
+    /// Brings both amounts to the finer of their two scales.
+    fn aligned(self, other: Money) -> (Money, Money) {
+        let digits = self.digits.max(other.digits);
+        (self.with_digits(digits), other.with_digits(digits))
+    }
+}
+
+/// Compares values, so `42.5` equals `42.50`.
+impl PartialEq for Money {
+    fn eq(&self, other: &Money) -> bool {
+        let (a, b) = self.aligned(*other);
+        a.minor == b.minor
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        let (a, b) = self.aligned(other);
+        Money {
+            minor: a.minor.saturating_add(b.minor),
+            digits: a.digits,
+        }
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        self + -other
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money {
+            minor: self.minor.saturating_neg(),
+            digits: self.digits,
+        }
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+/// Plain decimal with as many fraction digits as the amount's scale, e.g.
+/// `42.50` for EUR. This is the form stored in the database.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_scaled(self.minor, self.digits))
+    }
+}
+
+impl FromStr for Money {
+    type Err = ParseDecimalError;
+
+    fn from_str(text: &str) -> Result<Money, ParseDecimalError> {
+        let plain = plain_decimal(text).ok_or_else(|| ParseDecimalError(text.to_string()))?;
+        let digits = plain
+            .split_once('.')
+            .map_or(0, |(_, fraction)| fraction.len() as u32)
+            .min(MAX_PARSED_DIGITS);
+        parse_scaled(&plain, digits)
+            .map(|minor| Money { minor, digits })
+            .ok_or_else(|| ParseDecimalError(text.to_string()))
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_major())
+    }
+}
+
+/// Accepts a JSON number in major units. The result still has to be put
+/// into its currency with `in_currency` before it is stored.
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+        let major = f64::deserialize(deserializer)?;
+        Money::from_f64(major).map_err(|_| out_of_range(major))
+    }
+}
+
+fn out_of_range<E: serde::de::Error>(value: f64) -> E {
+    E::invalid_value(serde::de::Unexpected::Float(value), &"a finite number that fits the vault")
+}
+
+/// Digits kept for share and coin quantities; enough for one satoshi.
+const QUANTITY_DIGITS: u32 = 8;
+
+/// A non-money quantity such as a number of shares or coins, exact to
+/// eight decimal places. Serialized as a JSON number like `Money`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quantity(i64);
+
+impl Quantity {
+    /// Refuses NaN, infinities and values too large to store.
+    pub fn from_f64(value: f64) -> Result<Quantity, ParseDecimalError> {
+        value.to_string().parse()
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / pow10(QUANTITY_DIGITS) as f64
+    }
+
+    /// The value of this many units at `price` each, rounded to the price's
+    /// minor unit.
+    pub fn times(self, price: Money) -> Money {
+        Money {
+            minor: saturate(div_round(
+                self.0 as i128 * price.minor as i128,
+                pow10(QUANTITY_DIGITS),
+            )),
+            digits: price.digits,
+        }
+    }
+}
+
+/// Plain decimal without trailing zeros, e.g. `3.25`.
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let full = format_scaled(self.0, QUANTITY_DIGITS);
+        f.write_str(full.trim_end_matches('0').trim_end_matches('.'))
+    }
+}
+
+impl FromStr for Quantity {
+    type Err = ParseDecimalError;
+
+    fn from_str(text: &str) -> Result<Quantity, ParseDecimalError> {
+        parse_scaled(text, QUANTITY_DIGITS)
+            .map(Quantity)
+            .ok_or_else(|| ParseDecimalError(text.to_string()))
+    }
+}
+
+impl Serialize for Quantity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Quantity, D::Error> {
+        let value = f64::deserialize(deserializer)?;
+        Quantity::from_f64(value).map_err(|_| out_of_range(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_numbers_that_cannot_be_stored_are_refused() {
+        for json in ["1e30", "-1e30", "1e400"] {
+            assert!(serde_json::from_str::<Money>(json).is_err(), "{json}");
+            assert!(serde_json::from_str::<Quantity>(json).is_err(), "{json}");
+        }
+        assert_eq!(serde_json::from_str::<Money>("12.5").unwrap(), "12.5".parse().unwrap());
+    }
+
+    #[test]
+    fn non_finite_floats_are_refused() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1e30] {
+            assert!(Money::from_major(value, "EUR").is_err(), "{value}");
+            assert!(Quantity::from_f64(value).is_err(), "{value}");
+        }
+        assert_eq!(Money::from_major(1.005, "EUR").unwrap(), "1.01".parse().unwrap());
+    }
+}
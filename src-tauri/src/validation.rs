@@ -0,0 +1,205 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::fmt;
+
+use crate::error::{Result, VaultError};
+use crate::money::{Money, Quantity};
+
+/// Active ISO 4217 currency codes.
+const CURRENCIES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD",
+    "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD",
+    "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ",
+    "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD", "JOD",
+    "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR",
+    "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR",
+    "MWK", "MXN", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN",
+    "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR",
+    "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB",
+    "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS",
+    "VES", "VND", "VUV", "WST", "XAF", "XCD", "XCG", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWG",
+];
+
+pub const BILLING_INTERVALS: &[&str] = &["monthly", "yearly"];
+pub const SAVINGS_INTERVALS: &[&str] = &["monthly", "quarterly", "yearly"];
+pub const INCOME_INTERVALS: &[&str] = &["monthly", "quarterly", "yearly"];
+pub const ASSET_TYPES: &[&str] = &["stock", "etf", "crypto", "other"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// A rule's verdict on one value; the message says what was expected.
+pub type Check = std::result::Result<(), String>;
+
+pub fn not_blank(value: &str) -> Check {
+    if value.trim().is_empty() {
+        return Err("must not be empty".into());
+    }
+    Ok(())
+}
+
+/// A calendar date written as `YYYY-MM-DD`.
+pub fn iso_date(value: &str) -> Check {
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(_) if value.len() == 10 => Ok(()),
+        _ => Err("must be a date like 2024-03-31".into()),
+    }
+}
+
+/// A month written as `YYYY-MM`.
+pub fn iso_month(value: &str) -> Check {
+    iso_date(&format!("{value}-01")).map_err(|_| "must be a month like 2024-03".into())
+}
+
+pub fn currency(value: &str) -> Check {
+    if !CURRENCIES.contains(&value) {
+        return Err("must be an ISO 4217 currency code like EUR".into());
+    }
+    Ok(())
+}
+
+/// Two letters, nine alphanumerics and a Luhn check digit over the value
+/// with letters expanded to numbers (A = 10 … Z = 35).
+pub fn isin(value: &str) -> Check {
+    let invalid = || Err("must be a 12-character ISIN with a valid check digit".into());
+    let bytes = value.as_bytes();
+    if bytes.len() != 12
+        || !bytes[..2].iter().all(u8::is_ascii_uppercase)
+        || !bytes[2..11].iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+        || !bytes[11].is_ascii_digit()
+    {
+        return invalid();
+    }
+
+    let digits: Vec<u32> = value
+        .chars()
+        .flat_map(|c| {
+            let n = c.to_digit(36).unwrap_or(0);
+            if n >= 10 {
+                vec![n / 10, n % 10]
+            } else {
+                vec![n]
+            }
+        })
+        .collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                doubled / 10 + doubled % 10
+            } else {
+                d
+            }
+        })
+        .sum();
+    if !sum.is_multiple_of(10) {
+        return invalid();
+    }
+    Ok(())
+}
+
+/// A color written as `#rrggbb`.
+pub fn hex_color(value: &str) -> Check {
+    match value.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => Ok(()),
+        _ => Err("must be a color like #3b82f6".into()),
+    }
+}
+
+pub fn one_of(value: &str, allowed: &[&str]) -> Check {
+    if !allowed.contains(&value) {
+        return Err(format!("must be one of {}", allowed.join(", ")));
+    }
+    Ok(())
+}
+
+/// How long key derivation may be tuned to take. Every unlock waits this
+/// long, so a mistyped target must not lock the owner out for hours.
+pub const KDF_TARGET_MS: std::ops::RangeInclusive<u64> = 100..=10_000;
+
+pub fn kdf_target_ms(value: u64) -> Check {
+    if !KDF_TARGET_MS.contains(&value) {
+        return Err(format!(
+            "must be {} to {} milliseconds",
+            KDF_TARGET_MS.start(),
+            KDF_TARGET_MS.end()
+        ));
+    }
+    Ok(())
+}
+
+pub fn not_negative(value: Money) -> Check {
+    if value.is_negative() {
+        return Err("must not be negative".into());
+    }
+    Ok(())
+}
+
+pub fn positive(value: Money) -> Check {
+    if value.is_negative() || value == Money::ZERO {
+        return Err("must be greater than zero".into());
+    }
+    Ok(())
+}
+
+pub fn positive_quantity(value: Quantity) -> Check {
+    if !value.is_positive() {
+        return Err("must be greater than zero".into());
+    }
+    Ok(())
+}
+
+/// Collects every failing field of an input so they can be reported together.
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(&mut self, field: &str, check: Check) -> &mut Self {
+        if let Err(message) = check {
+            self.errors.push(FieldError {
+                field: field.to_string(),
+                message,
+            });
+        }
+        self
+    }
+
+    /// Like `check`, for fields an update may leave out.
+    pub fn check_opt<T>(
+        &mut self,
+        field: &str,
+        value: Option<T>,
+        rule: impl FnOnce(T) -> Check,
+    ) -> &mut Self {
+        match value {
+            Some(value) => self.check(field, rule(value)),
+            None => self,
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(VaultError::Validation(std::mem::take(&mut self.errors)))
+    }
+}
@@ -0,0 +1,692 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::categories;
+use super::history::{self, ChangeAction};
+use super::income;
+use super::Vault;
+use crate::crypto::{encrypt_value, DerivedKey};
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::money::{Money, DEFAULT_CURRENCY};
+use crate::validation::{self, Validator};
+
+/// The income planned for a month. What actually came in is recorded as
+/// income entries.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BudgetMonth {
+    pub id: String,
+    pub month: String,
+    pub income: Money,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BudgetSummary {
+    pub month: String,
+    /// The income entries dated in the month.
+    pub income: Money,
+    /// The income set for the month with `upsert_budget_month`.
+    pub planned_income: Money,
+    pub total_expenses: Money,
+    pub total_subscriptions_monthly: Money,
+    pub remaining: Money,
+    /// Every category with a limit or with expenses in the month.
+    pub categories: Vec<CategorySpending>,
+}
+
+/// A spending limit for one category in one month, in the default currency.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CategoryBudget {
+    pub id: String,
+    pub category_id: String,
+    pub month: String,
+    pub amount: Money,
+    pub created_at: String,
+}
+
+/// How much went to a category in a month. `spent` includes the expenses
+/// of its subcategories, so a limit on "Food" covers "Food / Snacks" too.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CategorySpending {
+    pub category_id: String,
+    pub name: String,
+    pub limit: Option<Money>,
+    pub spent: Money,
+    /// Negative once the limit is exceeded.
+    pub remaining: Option<Money>,
+    /// Rounded to one decimal; `None` without a limit or with a limit of 0.
+    pub percent_used: Option<f64>,
+}
+
+impl CategorySpending {
+    pub fn is_over_limit(&self) -> bool {
+        self.remaining.is_some_and(Money::is_negative)
+    }
+}
+
+fn budget_month_from_row(row: &rusqlite::Row, key: &DerivedKey) -> rusqlite::Result<BudgetMonth> {
+    Ok(BudgetMonth {
+        id: row.get(0)?,
+        month: row.get(1)?,
+        income: db::get_money(row, 2, key, DEFAULT_CURRENCY)?,
+        created_at: row.get(3)?,
+    })
+}
+
+/// Overwrites the income of a stored budget month; used to undo updates.
+pub(super) fn put_budget_month(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    budget: &BudgetMonth,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE budget SET month = ?1, income = ?2 WHERE id = ?3",
+        rusqlite::params![
+            budget.month,
+            encrypt_value(key, &budget.income.in_currency(DEFAULT_CURRENCY))?,
+            budget.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(VaultError::NotFound { entity: "budget month", id: budget.id.clone() });
+    }
+    Ok(())
+}
+
+const CATEGORY_BUDGET_COLUMNS: &str = "id, category_id, month, amount, created_at";
+
+fn category_budget_from_row(
+    row: &rusqlite::Row,
+    key: &DerivedKey,
+) -> rusqlite::Result<CategoryBudget> {
+    Ok(CategoryBudget {
+        id: row.get(0)?,
+        category_id: row.get(1)?,
+        month: row.get(2)?,
+        amount: db::get_money(row, 3, key, DEFAULT_CURRENCY)?,
+        created_at: row.get(4)?,
+    })
+}
+
+/// Writes every field of `budget`, inserting it if it is gone; used to undo
+/// updates and deletes.
+pub(super) fn put_category_budget(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    budget: &CategoryBudget,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO category_budgets (id, category_id, month, amount, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(id) DO UPDATE SET category_id = ?2, month = ?3, amount = ?4",
+        rusqlite::params![
+            budget.id,
+            budget.category_id,
+            budget.month,
+            encrypt_value(key, &budget.amount.in_currency(DEFAULT_CURRENCY))?,
+            budget.created_at
+        ],
+    )?;
+    Ok(())
+}
+
+fn category_budgets_where(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    condition: &str,
+    value: &str,
+) -> Result<Vec<CategoryBudget>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {CATEGORY_BUDGET_COLUMNS} FROM category_budgets WHERE {condition} = ?1"
+    ))?;
+    let rows = stmt.query_map([value], |row| category_budget_from_row(row, key))?;
+    rows.collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(VaultError::Database)
+}
+
+/// Deletes every limit of a category that is about to go away, logging each
+/// one so undo brings them back after the category.
+pub(super) fn drop_category_budgets(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    category_id: &str,
+) -> Result<()> {
+    for budget in category_budgets_where(conn, key, "category_id", category_id)? {
+        conn.execute("DELETE FROM category_budgets WHERE id = ?1", [&budget.id])?;
+        history::record(
+            conn,
+            key,
+            "category budget",
+            &budget.id,
+            ChangeAction::Delete,
+            Some(&budget),
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// Hands the limits of a category being merged away to `into`. A month both
+/// have a limit for keeps one limit with the two amounts added up.
+pub(super) fn move_category_budgets(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    category_id: &str,
+    into: &str,
+) -> Result<()> {
+    let mut kept = category_budgets_where(conn, key, "category_id", into)?;
+    for budget in category_budgets_where(conn, key, "category_id", category_id)? {
+        let (id, before, after) = match kept.iter_mut().find(|b| b.month == budget.month) {
+            Some(existing) => {
+                conn.execute("DELETE FROM category_budgets WHERE id = ?1", [&budget.id])?;
+                history::record(
+                    conn,
+                    key,
+                    "category budget",
+                    &budget.id,
+                    ChangeAction::Delete,
+                    Some(&budget),
+                    None,
+                )?;
+                let before = existing.clone();
+                existing.amount = existing.amount + budget.amount;
+                (existing.id.clone(), before, existing.clone())
+            }
+            None => {
+                let after = CategoryBudget { category_id: into.to_string(), ..budget.clone() };
+                (budget.id.clone(), budget, after)
+            }
+        };
+        put_category_budget(conn, key, &after)?;
+        history::record(
+            conn,
+            key,
+            "category budget",
+            &id,
+            ChangeAction::Update,
+            Some(&before),
+            Some(&after),
+        )?;
+    }
+    Ok(())
+}
+
+fn percent(part: Money, whole: Money) -> Option<f64> {
+    let whole = whole.to_major();
+    (whole != 0.0).then(|| (part.to_major() / whole * 1000.0).round() / 10.0)
+}
+
+impl Vault<'_> {
+    /// Every budgeted month, newest first.
+    pub fn budget_months(&self) -> Result<Vec<BudgetMonth>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, month, income, created_at FROM budget ORDER BY month DESC")?;
+        let rows = stmt.query_map([], |row| budget_month_from_row(row, self.key))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(VaultError::Database)
+    }
+
+    /// Sets the planned income of `month`, creating the month if it has none
+    /// yet.
+    pub fn upsert_budget_month(&self, month: String, income: Money) -> Result<BudgetMonth> {
+        Validator::new()
+            .check("month", validation::iso_month(&month))
+            .check("income", validation::not_negative(income))
+            .finish()?;
+        let now = Utc::now().to_rfc3339();
+        let income = income.in_currency(DEFAULT_CURRENCY);
+        let encrypted_income = encrypt_value(self.key, &income)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        let existing = tx
+            .query_row(
+                "SELECT id, month, income, created_at FROM budget WHERE month = ?1",
+                [&month],
+                |row| budget_month_from_row(row, self.key),
+            )
+            .optional()?;
+
+        let budget = if let Some(before) = existing {
+            tx.execute(
+                "UPDATE budget SET income = ?1 WHERE id = ?2",
+                rusqlite::params![encrypted_income, before.id],
+            )?;
+            let after = BudgetMonth { income, ..before.clone() };
+            history::record(
+                &tx,
+                self.key,
+                "budget month",
+                &after.id,
+                ChangeAction::Update,
+                Some(&before),
+                Some(&after),
+            )?;
+            after
+        } else {
+            let id = Uuid::new_v4().to_string();
+            tx.execute(
+                "INSERT INTO budget (id, month, income, created_at) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![id, month, encrypted_income, now],
+            )?;
+            let created = BudgetMonth { id, month, income, created_at: now };
+            history::record(
+                &tx,
+                self.key,
+                "budget month",
+                &created.id,
+                ChangeAction::Create,
+                None,
+                Some(&created),
+            )?;
+            created
+        };
+        tx.commit()?;
+        Ok(budget)
+    }
+
+    /// The month's income entries against its expenses and the monthly share
+    /// of every subscription.
+    pub fn budget_summary(&self, month: String) -> Result<BudgetSummary> {
+        Validator::new().check("month", validation::iso_month(&month)).finish()?;
+
+        let income = income::income_between(self.conn, self.key, &month, &month)?;
+        let planned_income = self
+            .conn
+            .query_row(
+                "SELECT income FROM budget WHERE month = ?1",
+                [&month],
+                |row| db::get_money(row, 0, self.key, DEFAULT_CURRENCY),
+            )
+            .unwrap_or(Money::ZERO);
+
+        let total_expenses: Money = {
+            let mut stmt = self.conn.prepare(
+                "SELECT amount, currency FROM expenses WHERE strftime('%Y-%m', date) = ?1 AND deleted_at IS NULL",
+            )?;
+            let rows = stmt.query_map([&month], |row| {
+                db::get_money(row, 0, self.key, &row.get::<_, String>(1)?)
+            })?;
+
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(VaultError::Database)?
+                .into_iter()
+                .sum()
+        };
+
+        let total_subscriptions_monthly: Money = {
+            let mut stmt = self
+                .conn
+                .prepare("SELECT amount, currency, billing FROM subscriptions WHERE deleted_at IS NULL")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    db::get_money(row, 0, self.key, &row.get::<_, String>(1)?)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?;
+
+            rows.fold(Money::ZERO, |acc, r| {
+                if let Ok((amount, billing)) = r {
+                    acc + if billing == "yearly" { amount.div_round(12) } else { amount }
+                } else {
+                    acc
+                }
+            })
+        };
+
+        let remaining = income - total_expenses - total_subscriptions_monthly;
+        let categories = self.category_spending(&month)?;
+
+        Ok(BudgetSummary {
+            month,
+            income,
+            planned_income,
+            total_expenses,
+            total_subscriptions_monthly,
+            remaining,
+            categories,
+        })
+    }
+
+    /// The limits set for `month`.
+    pub fn category_budgets(&self, month: String) -> Result<Vec<CategoryBudget>> {
+        Validator::new().check("month", validation::iso_month(&month)).finish()?;
+        category_budgets_where(self.conn, self.key, "month", &month)
+    }
+
+    /// Sets the limit of a category for `month`, replacing any earlier one.
+    pub fn set_category_budget(
+        &self,
+        category_id: String,
+        month: String,
+        amount: Money,
+    ) -> Result<CategoryBudget> {
+        Validator::new()
+            .check("month", validation::iso_month(&month))
+            .check("amount", validation::not_negative(amount))
+            .finish()?;
+        let amount = amount.in_currency(DEFAULT_CURRENCY);
+
+        let tx = self.conn.unchecked_transaction()?;
+        categories::usable_category(&tx, self.key, &category_id)?;
+        let existing = tx
+            .query_row(
+                &format!(
+                    "SELECT {CATEGORY_BUDGET_COLUMNS} FROM category_budgets
+                     WHERE category_id = ?1 AND month = ?2"
+                ),
+                [&category_id, &month],
+                |row| category_budget_from_row(row, self.key),
+            )
+            .optional()?;
+
+        let (action, before, after) = match existing {
+            Some(before) => {
+                let after = CategoryBudget { amount, ..before.clone() };
+                (ChangeAction::Update, Some(before), after)
+            }
+            None => {
+                let created = CategoryBudget {
+                    id: Uuid::new_v4().to_string(),
+                    category_id,
+                    month,
+                    amount,
+                    created_at: Utc::now().to_rfc3339(),
+                };
+                (ChangeAction::Create, None, created)
+            }
+        };
+        put_category_budget(&tx, self.key, &after)?;
+        history::record(
+            &tx,
+            self.key,
+            "category budget",
+            &after.id,
+            action,
+            before.as_ref(),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Removes a limit for good; undo puts it back.
+    pub fn delete_category_budget(&self, id: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let before = category_budgets_where(&tx, self.key, "id", id)?
+            .pop()
+            .ok_or_else(|| VaultError::NotFound { entity: "category budget", id: id.to_string() })?;
+        tx.execute("DELETE FROM category_budgets WHERE id = ?1", [id])?;
+        history::record(
+            &tx,
+            self.key,
+            "category budget",
+            id,
+            ChangeAction::Delete,
+            Some(&before),
+            None,
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The categories that spent more than their limit in `month`.
+    pub fn overspent_categories(&self, month: String) -> Result<Vec<CategorySpending>> {
+        Validator::new().check("month", validation::iso_month(&month)).finish()?;
+        let mut lines = self.category_spending(&month)?;
+        lines.retain(CategorySpending::is_over_limit);
+        Ok(lines)
+    }
+
+    fn category_spending(&self, month: &str) -> Result<Vec<CategorySpending>> {
+        let all = categories::all_categories(self.conn, self.key)?;
+        let parents: HashMap<&str, Option<&str>> =
+            all.iter().map(|c| (c.id.as_str(), c.parent_id.as_deref())).collect();
+
+        let mut spent: HashMap<String, Money> = HashMap::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT amount, currency, category_id FROM expenses
+             WHERE strftime('%Y-%m', date) = ?1 AND deleted_at IS NULL",
+        )?;
+        let rows = stmt.query_map([month], |row| {
+            Ok((
+                db::get_money(row, 0, self.key, &row.get::<_, String>(1)?)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (amount, category_id) = row?;
+            // Counts towards the category and every one above it.
+            let mut current = Some(category_id.as_str());
+            while let Some(id) = current {
+                let total = spent.entry(id.to_string()).or_default();
+                *total = *total + amount;
+                current = parents.get(id).copied().flatten();
+            }
+        }
+
+        let limits: HashMap<String, Money> =
+            category_budgets_where(self.conn, self.key, "month", month)?
+                .into_iter()
+                .map(|b| (b.category_id, b.amount))
+                .collect();
+
+        Ok(all
+            .into_iter()
+            .filter(|c| limits.contains_key(&c.id) || spent.contains_key(&c.id))
+            .map(|c| {
+                let limit = limits.get(&c.id).copied();
+                let spent = spent.get(&c.id).copied().unwrap_or_default();
+                CategorySpending {
+                    remaining: limit.map(|limit| limit - spent),
+                    percent_used: limit.and_then(|limit| percent(spent, limit)),
+                    category_id: c.id,
+                    name: c.name,
+                    limit,
+                    spent,
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::categories::CreateCategory;
+    use crate::vault::expenses::CreateExpense;
+    use crate::vault::income::CreateIncome;
+    use crate::vault::subscriptions::CreateSubscription;
+    use crate::vault::test_support;
+
+    fn money(text: &str) -> Money {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn upsert_updates_the_existing_month() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+
+        let created = vault.upsert_budget_month("2024-03".into(), money("2500")).unwrap();
+        let updated = vault.upsert_budget_month("2024-03".into(), money("2750")).unwrap();
+        assert_eq!(updated.id, created.id);
+
+        let months = vault.budget_months().unwrap();
+        assert_eq!(months.len(), 1);
+        assert_eq!(months[0].income, money("2750"));
+    }
+
+    #[test]
+    fn malformed_month_is_rejected() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let result = vault.upsert_budget_month("2024-3".into(), money("1"));
+        assert!(matches!(result, Err(VaultError::Validation(_))));
+        assert!(matches!(vault.budget_summary("March".into()), Err(VaultError::Validation(_))));
+    }
+
+    #[test]
+    fn summary_subtracts_expenses_and_monthly_subscriptions() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        vault.upsert_budget_month("2024-03".into(), money("2200")).unwrap();
+        let entries = [
+            ("Salary", "1800", "2024-03-28"),
+            ("Refund", "200", "2024-03-02"),
+            ("Bonus", "5000", "2024-04-15"),
+        ];
+        for (source, amount, date) in entries {
+            vault
+                .create_income(CreateIncome {
+                    source: source.into(),
+                    amount: money(amount),
+                    currency: None,
+                    date: date.into(),
+                })
+                .unwrap();
+        }
+        let food = test_support::category(&vault, "Food");
+        for (date, amount) in [("2024-03-05", "100"), ("2024-03-20", "50.50"), ("2024-04-01", "999")] {
+            vault
+                .create_expense(CreateExpense {
+                    title: "Groceries".into(),
+                    amount: money(amount),
+                    currency: None,
+                    category_id: food.clone(),
+                    date: date.into(),
+                })
+                .unwrap();
+        }
+        vault
+            .create_subscription(CreateSubscription {
+                name: "Insurance".into(),
+                amount: money("120"),
+                currency: None,
+                billing: "yearly".into(),
+                next_billing: "2024-12-01".into(),
+            })
+            .unwrap();
+
+        let summary = vault.budget_summary("2024-03".into()).unwrap();
+        assert_eq!(summary.income, money("2000"));
+        assert_eq!(summary.planned_income, money("2200"));
+        assert_eq!(summary.total_expenses, money("150.50"));
+        assert_eq!(summary.total_subscriptions_monthly, money("10"));
+        assert_eq!(summary.remaining, money("1839.50"));
+    }
+
+    fn spend(vault: &Vault<'_>, category_id: &str, date: &str, amount: &str) {
+        vault
+            .create_expense(CreateExpense {
+                title: "Shopping".into(),
+                amount: money(amount),
+                currency: None,
+                category_id: category_id.into(),
+                date: date.into(),
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn category_lines_roll_up_subcategories() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = test_support::category(&vault, "Food");
+        let snacks = vault
+            .create_category(CreateCategory {
+                name: "Snacks".into(),
+                parent_id: Some(food.clone()),
+                color: None,
+                icon: None,
+            })
+            .unwrap();
+        let home = test_support::category(&vault, "Home");
+        vault.set_category_budget(food.clone(), "2024-03".into(), money("400")).unwrap();
+        spend(&vault, &food, "2024-03-02", "250");
+        spend(&vault, &snacks.id, "2024-03-09", "50");
+        spend(&vault, &home, "2024-03-12", "80");
+        spend(&vault, &food, "2024-04-01", "999");
+
+        let summary = vault.budget_summary("2024-03".into()).unwrap();
+        let line = |id: &str| summary.categories.iter().find(|l| l.category_id == id).unwrap();
+        assert_eq!(summary.categories.len(), 3);
+        assert_eq!(line(&food).spent, money("300"));
+        assert_eq!(line(&food).remaining, Some(money("100")));
+        assert_eq!(line(&food).percent_used, Some(75.0));
+        assert_eq!(line(&snacks.id).spent, money("50"));
+        assert_eq!(line(&home).limit, None);
+        assert_eq!(line(&home).percent_used, None);
+    }
+
+    #[test]
+    fn setting_a_limit_again_replaces_it() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = test_support::category(&vault, "Food");
+        let march = || "2024-03".to_string();
+        let first = vault.set_category_budget(food.clone(), march(), money("400")).unwrap();
+        let second = vault.set_category_budget(food.clone(), march(), money("450")).unwrap();
+        assert_eq!(second.id, first.id);
+
+        let budgets = vault.category_budgets(march()).unwrap();
+        assert_eq!(budgets.len(), 1);
+        assert_eq!(budgets[0].amount, money("450"));
+        assert!(vault.category_budgets("2024-04".into()).unwrap().is_empty());
+
+        let unknown = vault.set_category_budget("nope".into(), march(), money("1"));
+        assert!(matches!(unknown, Err(VaultError::Validation(_))));
+        let negative = vault.set_category_budget(food, march(), money("-1"));
+        assert!(matches!(negative, Err(VaultError::Validation(_))));
+    }
+
+    #[test]
+    fn overspent_lists_only_categories_past_their_limit() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = test_support::category(&vault, "Food");
+        let home = test_support::category(&vault, "Home");
+        let fun = test_support::category(&vault, "Fun");
+        vault.set_category_budget(food.clone(), "2024-03".into(), money("400")).unwrap();
+        vault.set_category_budget(home.clone(), "2024-03".into(), money("100")).unwrap();
+        spend(&vault, &food, "2024-03-02", "400.01");
+        spend(&vault, &home, "2024-03-02", "100");
+        spend(&vault, &fun, "2024-03-02", "500");
+
+        let over = vault.overspent_categories("2024-03".into()).unwrap();
+        assert_eq!(over.len(), 1);
+        assert_eq!(over[0].category_id, food);
+        assert_eq!(over[0].remaining, Some(money("-0.01")));
+    }
+
+    #[test]
+    fn deleting_a_category_takes_its_limits_and_undo_brings_them_back() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let gifts = test_support::category(&vault, "Gifts");
+        vault.set_category_budget(gifts.clone(), "2024-12".into(), money("300")).unwrap();
+
+        vault.delete_category(&gifts).unwrap();
+        assert!(vault.category_budgets("2024-12".into()).unwrap().is_empty());
+
+        vault.undo_last_change().unwrap();
+        vault.undo_last_change().unwrap();
+        let budgets = vault.category_budgets("2024-12".into()).unwrap();
+        assert_eq!(budgets.len(), 1);
+        assert_eq!(budgets[0].category_id, gifts);
+    }
+
+    #[test]
+    fn summary_of_an_unbudgeted_month_has_no_income() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let summary = vault.budget_summary("2030-01".into()).unwrap();
+        assert_eq!(summary.income, Money::ZERO);
+        assert_eq!(summary.remaining, Money::ZERO);
+    }
+}
@@ -0,0 +1,587 @@
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::budget;
+use super::envelopes;
+use super::expenses::{self, Expense};
+use super::history::{self, ChangeAction};
+use super::Vault;
+use crate::crypto::{encrypt_value, DerivedKey};
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::validation::{self, Check, FieldError, Validator};
+
+const MAX_ICON_CHARS: usize = 32;
+
+/// An expense category. Categories nest through `parent_id`; archived ones
+/// keep their expenses but take no new ones.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    /// `#rrggbb`.
+    pub color: Option<String>,
+    /// An icon name or emoji, shown by the frontend as it sees fit.
+    pub icon: Option<String>,
+    pub archived: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCategory {
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+}
+
+/// Fields left out stay as they are. For `parent_id`, `color` and `icon` an
+/// empty string clears the value, which moves the category to the top level
+/// in the case of `parent_id`.
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateCategory {
+    pub name: Option<String>,
+    pub parent_id: Option<String>,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub archived: Option<bool>,
+}
+
+fn icon(value: &str) -> Check {
+    if value.trim().is_empty() || value.chars().count() > MAX_ICON_CHARS {
+        return Err(format!("must be 1 to {MAX_ICON_CHARS} characters"));
+    }
+    Ok(())
+}
+
+impl CreateCategory {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check("name", validation::not_blank(&self.name))
+            .check_opt("color", self.color.as_deref(), validation::hex_color)
+            .check_opt("icon", self.icon.as_deref(), icon)
+            .finish()
+    }
+}
+
+impl UpdateCategory {
+    fn validate(&self) -> Result<()> {
+        fn set(value: &Option<String>) -> Option<&str> {
+            value.as_deref().filter(|v| !v.is_empty())
+        }
+        Validator::new()
+            .check_opt("name", self.name.as_deref(), validation::not_blank)
+            .check_opt("color", set(&self.color), validation::hex_color)
+            .check_opt("icon", set(&self.icon), icon)
+            .finish()
+    }
+}
+
+fn invalid(field: &str, message: impl Into<String>) -> VaultError {
+    VaultError::Validation(vec![FieldError { field: field.into(), message: message.into() }])
+}
+
+const CATEGORY_COLUMNS: &str = "id, name, parent_id, color, icon, archived, created_at";
+
+fn category_from_row(row: &rusqlite::Row, key: &DerivedKey) -> rusqlite::Result<Category> {
+    Ok(Category {
+        id: row.get(0)?,
+        name: db::get_decrypted(row, 1, key)?,
+        parent_id: row.get(2)?,
+        color: row.get(3)?,
+        icon: row.get(4)?,
+        archived: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+fn find_category(conn: &rusqlite::Connection, key: &DerivedKey, id: &str) -> Result<Category> {
+    conn.query_row(
+        &format!("SELECT {CATEGORY_COLUMNS} FROM categories WHERE id = ?1"),
+        [id],
+        |row| category_from_row(row, key),
+    )
+    .optional()?
+    .ok_or_else(|| VaultError::NotFound { entity: "category", id: id.to_string() })
+}
+
+pub(super) fn all_categories(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+) -> Result<Vec<Category>> {
+    let mut stmt = conn.prepare(&format!("SELECT {CATEGORY_COLUMNS} FROM categories"))?;
+    let rows = stmt.query_map([], |row| category_from_row(row, key))?;
+    let mut categories = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+    categories.sort_by_key(|c| c.name.to_lowercase());
+    Ok(categories)
+}
+
+/// The category an expense may be filed under: one that exists and is not
+/// archived. Reported against the expense's `category_id` field.
+pub(super) fn usable_category(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    id: &str,
+) -> Result<Category> {
+    match find_category(conn, key, id) {
+        Ok(category) if category.archived => Err(invalid("category_id", "category is archived")),
+        Ok(category) => Ok(category),
+        Err(VaultError::NotFound { .. }) => Err(invalid("category_id", "unknown category")),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes every field of `category`, inserting it if it is gone; used to
+/// undo updates and deletes.
+pub(super) fn put_category(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    category: &Category,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO categories (id, name, parent_id, color, icon, archived, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(id) DO UPDATE SET
+             name = excluded.name,
+             parent_id = excluded.parent_id,
+             color = excluded.color,
+             icon = excluded.icon,
+             archived = excluded.archived",
+        rusqlite::params![
+            category.id,
+            encrypt_value(key, &category.name)?,
+            category.parent_id,
+            category.color,
+            category.icon,
+            category.archived,
+            category.created_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// Checks where a category named `name` may go under `parent_id`: the
+/// parent must exist and must not be the category itself or one of its
+/// descendants, and no sibling may have the same name ignoring case.
+fn check_placement(
+    categories: &[Category],
+    id: Option<&str>,
+    name: &str,
+    parent_id: Option<&str>,
+) -> Result<()> {
+    let mut ancestor = parent_id;
+    while let Some(current) = ancestor {
+        if Some(current) == id {
+            return Err(invalid("parent_id", "must not be the category itself or inside it"));
+        }
+        let Some(parent) = categories.iter().find(|c| c.id == current) else {
+            return Err(invalid("parent_id", "unknown category"));
+        };
+        ancestor = parent.parent_id.as_deref();
+    }
+
+    let name = name.trim().to_lowercase();
+    let taken = categories.iter().any(|c| {
+        Some(c.id.as_str()) != id
+            && c.parent_id.as_deref() == parent_id
+            && c.name.trim().to_lowercase() == name
+    });
+    if taken {
+        return Err(invalid("name", "a category with this name already exists here"));
+    }
+    Ok(())
+}
+
+impl Vault<'_> {
+    /// Every category including archived ones, by name. Children point to
+    /// their parent through `parent_id`.
+    pub fn list_categories(&self) -> Result<Vec<Category>> {
+        all_categories(self.conn, self.key)
+    }
+
+    pub fn create_category(&self, input: CreateCategory) -> Result<Category> {
+        input.validate()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let parent_id = input.parent_id.filter(|p| !p.is_empty());
+        check_placement(&all_categories(&tx, self.key)?, None, &input.name, parent_id.as_deref())?;
+
+        let category = Category {
+            id: Uuid::new_v4().to_string(),
+            name: input.name.trim().to_string(),
+            parent_id,
+            color: input.color,
+            icon: input.icon,
+            archived: false,
+            created_at: Utc::now().to_rfc3339(),
+        };
+        put_category(&tx, self.key, &category)?;
+        history::record(
+            &tx,
+            self.key,
+            "category",
+            &category.id,
+            ChangeAction::Create,
+            None,
+            Some(&category),
+        )?;
+        tx.commit()?;
+        Ok(category)
+    }
+
+    /// Changes the given fields and returns the stored category. Archiving
+    /// leaves its children and expenses where they are.
+    pub fn update_category(&self, id: &str, input: UpdateCategory) -> Result<Category> {
+        input.validate()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let before = find_category(&tx, self.key, id)?;
+        let cleared = |value: String| Some(value).filter(|v| !v.is_empty());
+        let after = Category {
+            name: input.name.map_or(before.name.clone(), |n| n.trim().to_string()),
+            parent_id: input.parent_id.map_or(before.parent_id.clone(), cleared),
+            color: input.color.map_or(before.color.clone(), cleared),
+            icon: input.icon.map_or(before.icon.clone(), cleared),
+            archived: input.archived.unwrap_or(before.archived),
+            ..before.clone()
+        };
+        check_placement(
+            &all_categories(&tx, self.key)?,
+            Some(id),
+            &after.name,
+            after.parent_id.as_deref(),
+        )?;
+
+        put_category(&tx, self.key, &after)?;
+        history::record(
+            &tx,
+            self.key,
+            "category",
+            id,
+            ChangeAction::Update,
+            Some(&before),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Deletes a category that nothing refers to. One that still has
+    /// expenses, even trashed ones, or subcategories has to be merged into
+    /// another or archived instead. Its monthly limits and envelope transfers
+    /// go with it.
+    pub fn delete_category(&self, id: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let before = find_category(&tx, self.key, id)?;
+        let (expense_count, child_count): (i64, i64) = tx.query_row(
+            "SELECT (SELECT COUNT(*) FROM expenses WHERE category_id = ?1),
+                    (SELECT COUNT(*) FROM categories WHERE parent_id = ?1)",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if expense_count > 0 || child_count > 0 {
+            return Err(invalid(
+                "id",
+                format!(
+                    "still has {expense_count} expenses and {child_count} subcategories; \
+                     merge or archive it instead"
+                ),
+            ));
+        }
+
+        budget::drop_category_budgets(&tx, self.key, id)?;
+        envelopes::drop_envelope_transfers(&tx, self.key, id)?;
+        tx.execute("DELETE FROM categories WHERE id = ?1", [id])?;
+        history::record(&tx, self.key, "category", id, ChangeAction::Delete, Some(&before), None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Folds the `sources` categories into `into`: their expenses, trashed
+    /// ones included, their subcategories, monthly limits and envelope
+    /// transfers move over, then the sources are deleted. Limits add up with
+    /// the target's own for the same month, and transfers that would go from
+    /// the target to itself are dropped. `into` must not be archived.
+    /// Every step is logged, so undo takes it back one step at a time.
+    pub fn merge_categories(&self, mut sources: Vec<String>, into: &str) -> Result<Category> {
+        sources.sort();
+        sources.dedup();
+        let tx = self.conn.unchecked_transaction()?;
+        let target = find_category(&tx, self.key, into)?;
+        let categories = all_categories(&tx, self.key)?;
+
+        let mut errors = Validator::new();
+        if target.archived {
+            errors.check("into", Err("category is archived".into()));
+        }
+        if sources.is_empty() {
+            errors.check("sources", Err("must name at least one category".into()));
+        }
+        if sources.iter().any(|s| s == into) {
+            errors.check("sources", Err("must not contain the target category".into()));
+        }
+        if let Some(missing) = sources.iter().find(|s| !categories.iter().any(|c| &c.id == *s)) {
+            errors.check("sources", Err(format!("unknown category {missing}")));
+        }
+        let mut ancestor = target.parent_id.as_deref();
+        while let Some(current) = ancestor {
+            if sources.iter().any(|s| s == current) {
+                errors.check("into", Err("must not be inside a category being merged".into()));
+                break;
+            }
+            ancestor = categories.iter().find(|c| c.id == current).and_then(|c| c.parent_id.as_deref());
+        }
+        // Subcategories moving over must not clash with the target's own,
+        // nor with each other.
+        let mut placed: Vec<Category> =
+            categories.iter().filter(|c| !sources.contains(&c.id)).cloned().collect();
+        for index in 0..placed.len() {
+            let moves = placed[index].parent_id.as_ref().is_some_and(|p| sources.contains(p));
+            if !moves || placed[index].id == into {
+                continue;
+            }
+            let child = placed[index].clone();
+            if check_placement(&placed, Some(&child.id), &child.name, Some(into)).is_err() {
+                errors.check(
+                    "sources",
+                    Err(format!("{} already has a subcategory named {}", target.name, child.name)),
+                );
+            }
+            placed[index].parent_id = Some(into.to_string());
+        }
+        errors.finish()?;
+
+        for source in &sources {
+            let moved: Vec<String> = tx
+                .prepare("SELECT id FROM expenses WHERE category_id = ?1")?
+                .query_map([source], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            for expense_id in moved {
+                let before = expenses::find_expense(&tx, self.key, &expense_id)?;
+                let after = Expense {
+                    category_id: target.id.clone(),
+                    category: target.name.clone(),
+                    ..before.clone()
+                };
+                expenses::put_expense(&tx, self.key, &after)?;
+                history::record(
+                    &tx,
+                    self.key,
+                    "expense",
+                    &expense_id,
+                    ChangeAction::Update,
+                    Some(&before),
+                    Some(&after),
+                )?;
+            }
+
+            let children = categories
+                .iter()
+                .filter(|c| c.parent_id.as_deref() == Some(source) && !sources.contains(&c.id));
+            for child in children {
+                let after = Category { parent_id: Some(target.id.clone()), ..child.clone() };
+                put_category(&tx, self.key, &after)?;
+                history::record(
+                    &tx,
+                    self.key,
+                    "category",
+                    &child.id,
+                    ChangeAction::Update,
+                    Some(child),
+                    Some(&after),
+                )?;
+            }
+
+            let before = find_category(&tx, self.key, source)?;
+            budget::move_category_budgets(&tx, self.key, source, &target.id)?;
+            envelopes::move_envelope_transfers(&tx, self.key, source, &target.id)?;
+            tx.execute("DELETE FROM categories WHERE id = ?1", [source])?;
+            history::record(&tx, self.key, "category", source, ChangeAction::Delete, Some(&before), None)?;
+        }
+        tx.commit()?;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use crate::vault::expenses::CreateExpense;
+    use crate::vault::test_support;
+
+    fn named(name: &str, parent_id: Option<&str>) -> CreateCategory {
+        CreateCategory {
+            name: name.into(),
+            parent_id: parent_id.map(Into::into),
+            color: None,
+            icon: None,
+        }
+    }
+
+    fn expense_in(vault: &Vault<'_>, category_id: &str) -> Expense {
+        vault
+            .create_expense(CreateExpense {
+                title: "Lunch".into(),
+                amount: "12.5".parse().unwrap(),
+                currency: None,
+                category_id: category_id.into(),
+                date: "2024-03-05".into(),
+            })
+            .unwrap()
+    }
+
+    fn field_errors(result: Result<impl std::fmt::Debug>) -> Vec<String> {
+        match result {
+            Err(VaultError::Validation(errors)) => errors.into_iter().map(|e| e.field).collect(),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn sibling_names_are_unique_ignoring_case() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+
+        assert_eq!(field_errors(vault.create_category(named("food ", None))), ["name"]);
+        let nested = vault.create_category(named("Food", Some(&food.id))).unwrap();
+        assert_eq!(nested.parent_id.as_deref(), Some(food.id.as_str()));
+    }
+
+    #[test]
+    fn a_category_cannot_move_inside_itself() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+        let groceries = vault.create_category(named("Groceries", Some(&food.id))).unwrap();
+
+        let update = UpdateCategory { parent_id: Some(groceries.id.clone()), ..Default::default() };
+        assert_eq!(field_errors(vault.update_category(&food.id, update)), ["parent_id"]);
+
+        let to_top = UpdateCategory { parent_id: Some(String::new()), ..Default::default() };
+        assert_eq!(vault.update_category(&groceries.id, to_top).unwrap().parent_id, None);
+    }
+
+    #[test]
+    fn archived_categories_take_no_new_expenses() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+        expense_in(&vault, &food.id);
+
+        let archive = UpdateCategory { archived: Some(true), ..Default::default() };
+        vault.update_category(&food.id, archive).unwrap();
+        assert_eq!(vault.list_expenses().unwrap().len(), 1);
+
+        let input = CreateExpense {
+            title: "Dinner".into(),
+            amount: "30".parse().unwrap(),
+            currency: None,
+            category_id: food.id.clone(),
+            date: "2024-03-06".into(),
+        };
+        assert_eq!(field_errors(vault.create_expense(input)), ["category_id"]);
+    }
+
+    #[test]
+    fn used_categories_are_not_deleted() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+        let expense = expense_in(&vault, &food.id);
+        vault.delete_expense(&expense.id).unwrap();
+
+        assert_eq!(field_errors(vault.delete_category(&food.id)), ["id"]);
+
+        let empty = vault.create_category(named("Empty", None)).unwrap();
+        vault.delete_category(&empty.id).unwrap();
+        assert_eq!(vault.list_categories().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn merge_moves_expenses_and_subcategories() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+        let groceries = vault.create_category(named("Groceries", None)).unwrap();
+        let market = vault.create_category(named("Market", Some(&groceries.id))).unwrap();
+        let expense = expense_in(&vault, &groceries.id);
+
+        vault.merge_categories(vec![groceries.id.clone()], &food.id).unwrap();
+
+        let moved = &vault.list_expenses().unwrap()[0];
+        assert_eq!((moved.id.as_str(), moved.category.as_str()), (expense.id.as_str(), "Food"));
+        let categories = vault.list_categories().unwrap();
+        let names: Vec<_> = categories.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Food", "Market"]);
+        assert_eq!((categories[1].id.as_str(), categories[1].parent_id.as_deref()), (market.id.as_str(), Some(food.id.as_str())));
+    }
+
+    #[test]
+    fn merging_into_a_subcategory_of_a_source_is_refused() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+        let groceries = vault.create_category(named("Groceries", Some(&food.id))).unwrap();
+
+        assert_eq!(field_errors(vault.merge_categories(vec![food.id], &groceries.id)), ["into"]);
+    }
+
+    #[test]
+    fn merging_into_an_archived_category_is_refused() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+        let old = vault.create_category(named("Old", None)).unwrap();
+        let archive = UpdateCategory { archived: Some(true), ..Default::default() };
+        vault.update_category(&old.id, archive).unwrap();
+
+        assert_eq!(field_errors(vault.merge_categories(vec![food.id], &old.id)), ["into"]);
+    }
+
+    #[test]
+    fn merging_refuses_subcategories_whose_name_is_taken() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+        let fun = vault.create_category(named("Fun", None)).unwrap();
+        vault.create_category(named("Snacks", Some(&food.id))).unwrap();
+        vault.create_category(named("snacks", Some(&fun.id))).unwrap();
+
+        assert_eq!(field_errors(vault.merge_categories(vec![fun.id], &food.id)), ["sources"]);
+        assert_eq!(vault.list_categories().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn merge_moves_limits_and_adds_up_shared_months() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+        let groceries = vault.create_category(named("Groceries", None)).unwrap();
+        let money = |s: &str| -> Money { s.parse().unwrap() };
+        vault.set_category_budget(food.id.clone(), "2024-03".into(), money("100")).unwrap();
+        vault.set_category_budget(groceries.id.clone(), "2024-03".into(), money("50")).unwrap();
+        vault.set_category_budget(groceries.id.clone(), "2024-04".into(), money("30")).unwrap();
+
+        vault.merge_categories(vec![groceries.id], &food.id).unwrap();
+
+        for (month, amount) in [("2024-03", "150"), ("2024-04", "30")] {
+            let limits = vault.category_budgets(month.into()).unwrap();
+            let moved: Vec<_> = limits.iter().map(|l| (l.category_id.as_str(), l.amount)).collect();
+            assert_eq!(moved, [(food.id.as_str(), money(amount))]);
+        }
+    }
+
+    #[test]
+    fn deleting_a_category_can_be_undone() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = vault.create_category(named("Food", None)).unwrap();
+
+        vault.delete_category(&food.id).unwrap();
+        vault.undo_last_change().unwrap();
+        assert_eq!(vault.list_categories().unwrap()[0].name, "Food");
+    }
+}
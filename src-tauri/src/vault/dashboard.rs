@@ -0,0 +1,190 @@
+use serde::Serialize;
+
+use super::{income, Vault};
+use crate::crypto::DerivedKey;
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::money::{Money, Quantity};
+
+#[derive(Debug, Serialize)]
+pub struct DashboardSummary {
+    pub budget_income: Money,
+    pub budget_expenses: Money,
+    pub budget_subscriptions: Money,
+    pub budget_remaining: Money,
+    pub expense_count_this_month: i64,
+    pub subscriptions_count: i64,
+    pub savings_plans_count: i64,
+    pub savings_plans_monthly: Money,
+    pub portfolio_positions: i64,
+    pub portfolio_invested: Money,
+}
+
+/// Decrypts the amount in column 0 of every row, in the currency named in
+/// column 1, and applies `weight` to it, which gets the amount and the row
+/// for normalizing intervals.
+fn sum_decrypted<P: rusqlite::Params>(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    sql: &str,
+    params: P,
+    weight: impl Fn(Money, &rusqlite::Row) -> rusqlite::Result<Money>,
+) -> Result<Money> {
+    let mut stmt = conn.prepare(sql).map_err(VaultError::Database)?;
+    let rows = stmt
+        .query_map(params, |row| {
+            weight(db::get_money(row, 0, key, &row.get::<_, String>(1)?)?, row)
+        })
+        .map_err(VaultError::Database)?;
+
+    rows.sum::<rusqlite::Result<Money>>().map_err(VaultError::Database)
+}
+
+impl Vault<'_> {
+    /// Overview figures, with the budget taken from `month` (YYYY-MM).
+    pub fn dashboard_summary(&self, month: &str) -> Result<DashboardSummary> {
+        let (conn, key) = (self.conn, self.key);
+
+        let budget_income = income::income_between(conn, key, month, month)?;
+
+        let budget_expenses = sum_decrypted(
+            conn,
+            key,
+            "SELECT amount, currency FROM expenses WHERE strftime('%Y-%m', date) = ?1 AND deleted_at IS NULL",
+            [month],
+            |amount, _| Ok(amount),
+        )?;
+
+        let budget_subscriptions = sum_decrypted(
+            conn,
+            key,
+            "SELECT amount, currency, billing FROM subscriptions WHERE deleted_at IS NULL",
+            [],
+            |amount, r| {
+                Ok(match r.get::<_, String>(2)?.as_str() {
+                    "monthly" => amount,
+                    "yearly" => amount.div_round(12),
+                    _ => Money::ZERO,
+                })
+            },
+        )?;
+
+        let expense_count_this_month: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM expenses WHERE strftime('%Y-%m', date) = ?1 AND deleted_at IS NULL",
+                [month],
+                |r| r.get(0),
+            )
+            .unwrap_or(0);
+
+        let subscriptions_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM subscriptions WHERE deleted_at IS NULL", [], |r| r.get(0))
+            .unwrap_or(0);
+
+        let savings_plans_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM savings_plans WHERE deleted_at IS NULL", [], |r| r.get(0))
+            .unwrap_or(0);
+
+        let savings_plans_monthly = sum_decrypted(
+            conn,
+            key,
+            "SELECT amount, currency, interval FROM savings_plans WHERE deleted_at IS NULL",
+            [],
+            |amount, r| {
+                Ok(match r.get::<_, String>(2)?.as_str() {
+                    "monthly" => amount,
+                    "quarterly" => amount.div_round(3),
+                    "yearly" => amount.div_round(12),
+                    _ => Money::ZERO,
+                })
+            },
+        )?;
+
+        let portfolio_positions: i64 = conn
+            .query_row("SELECT COUNT(*) FROM portfolio_positions WHERE deleted_at IS NULL", [], |r| r.get(0))
+            .unwrap_or(0);
+
+        let portfolio_invested = sum_decrypted(
+            conn,
+            key,
+            "SELECT avg_buy_price, currency, quantity FROM portfolio_positions WHERE deleted_at IS NULL",
+            [],
+            |price, r| Ok(db::get_decrypted::<Quantity>(r, 2, key)?.times(price)),
+        )?;
+
+        Ok(DashboardSummary {
+            budget_income,
+            budget_expenses,
+            budget_subscriptions,
+            budget_remaining: budget_income - budget_expenses - budget_subscriptions,
+            expense_count_this_month,
+            subscriptions_count,
+            savings_plans_count,
+            savings_plans_monthly,
+            portfolio_positions,
+            portfolio_invested,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::portfolio::CreatePosition;
+    use crate::vault::savings::CreateSavingsPlan;
+    use crate::vault::test_support;
+
+    fn money(text: &str) -> Money {
+        text.parse().unwrap()
+    }
+
+    fn etf() -> CreatePosition {
+        CreatePosition {
+            isin: "IE00B4L5Y983".into(),
+            ticker: "EUNL.DE".into(),
+            name: "MSCI World".into(),
+            asset_type: "etf".into(),
+            quantity: "2.5".parse().unwrap(),
+            avg_buy_price: money("80.10"),
+            currency: None,
+            country: None,
+        }
+    }
+
+    #[test]
+    fn empty_vault_has_an_all_zero_summary() {
+        let (conn, key) = test_support::open();
+        let summary = Vault::new(&conn, &key).dashboard_summary("2024-03").unwrap();
+        assert_eq!(summary.budget_remaining, Money::ZERO);
+        assert_eq!(summary.portfolio_positions, 0);
+        assert_eq!(summary.savings_plans_monthly, Money::ZERO);
+    }
+
+    #[test]
+    fn savings_and_portfolio_are_normalized() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        for (interval, amount) in [("monthly", "50"), ("quarterly", "300"), ("yearly", "1200")] {
+            vault
+                .create_savings_plan(CreateSavingsPlan {
+                    name: "Plan".into(),
+                    isin: "IE00B4L5Y983".into(),
+                    ticker: "EUNL.DE".into(),
+                    amount: money(amount),
+                    currency: None,
+                    interval: interval.into(),
+                    next_date: "2024-04-01".into(),
+                })
+                .unwrap();
+        }
+        let sold = vault.create_position(etf()).unwrap();
+        vault.create_position(etf()).unwrap();
+        vault.delete_position(&sold.id).unwrap();
+
+        let summary = vault.dashboard_summary("2024-03").unwrap();
+        assert_eq!(summary.savings_plans_count, 3);
+        assert_eq!(summary.savings_plans_monthly, money("250"));
+        assert_eq!(summary.portfolio_positions, 1);
+        assert_eq!(summary.portfolio_invested, money("200.25"));
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::categories;
+use super::history::{self, ChangeAction};
+use super::{income, Vault};
+use crate::crypto::{encrypt_value, DerivedKey};
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::money::{Money, DEFAULT_CURRENCY};
+use crate::validation::{self, Validator};
+
+/// Money moved from one category's envelope to another's in `month`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EnvelopeTransfer {
+    pub id: String,
+    pub month: String,
+    pub from_category_id: String,
+    pub to_category_id: String,
+    pub amount: Money,
+    pub created_at: String,
+}
+
+/// One category's envelope in a month. Unlike the limits in the budget
+/// summary, only the category's own expenses count; subcategories are
+/// envelopes of their own.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EnvelopeLine {
+    pub category_id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    /// The month's budget for the category plus transfers in, minus
+    /// transfers out.
+    pub assigned: Money,
+    /// The month's expenses, as a negative amount.
+    pub activity: Money,
+    /// What is left after this month, including everything carried over
+    /// from earlier ones. Negative when overspent.
+    pub available: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnvelopeSummary {
+    pub month: String,
+    pub income: Money,
+    /// Income up to this month that no envelope has been given yet.
+    pub ready_to_assign: Money,
+    pub assigned: Money,
+    pub activity: Money,
+    pub available: Money,
+    pub categories: Vec<EnvelopeLine>,
+}
+
+const TRANSFER_COLUMNS: &str = "id, month, from_category_id, to_category_id, amount, created_at";
+
+fn transfer_from_row(row: &rusqlite::Row, key: &DerivedKey) -> rusqlite::Result<EnvelopeTransfer> {
+    Ok(EnvelopeTransfer {
+        id: row.get(0)?,
+        month: row.get(1)?,
+        from_category_id: row.get(2)?,
+        to_category_id: row.get(3)?,
+        amount: db::get_money(row, 4, key, DEFAULT_CURRENCY)?,
+        created_at: row.get(5)?,
+    })
+}
+
+fn transfers_where(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    condition: &str,
+    params: &[&str],
+) -> Result<Vec<EnvelopeTransfer>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TRANSFER_COLUMNS} FROM envelope_transfers WHERE {condition} ORDER BY created_at"
+    ))?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+        transfer_from_row(row, key)
+    })?;
+    rows.collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(VaultError::Database)
+}
+
+/// Writes every field of `transfer`, inserting it if it is gone; used by
+/// category merges and to undo updates and deletes.
+pub(super) fn put_envelope_transfer(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    transfer: &EnvelopeTransfer,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO envelope_transfers
+             (id, month, from_category_id, to_category_id, amount, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(id) DO UPDATE SET
+             month = ?2, from_category_id = ?3, to_category_id = ?4, amount = ?5",
+        rusqlite::params![
+            transfer.id,
+            transfer.month,
+            transfer.from_category_id,
+            transfer.to_category_id,
+            encrypt_value(key, &transfer.amount.in_currency(DEFAULT_CURRENCY))?,
+            transfer.created_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// Deletes every transfer into or out of a category that is about to go
+/// away, logging each one so undo brings them back after the category.
+pub(super) fn drop_envelope_transfers(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    category_id: &str,
+) -> Result<()> {
+    let condition = "from_category_id = ?1 OR to_category_id = ?1";
+    for transfer in transfers_where(conn, key, condition, &[category_id])? {
+        conn.execute("DELETE FROM envelope_transfers WHERE id = ?1", [&transfer.id])?;
+        history::record(
+            conn,
+            key,
+            "envelope transfer",
+            &transfer.id,
+            ChangeAction::Delete,
+            Some(&transfer),
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// Points the transfers of a category being merged away at `into` instead.
+/// Transfers that would then go from `into` to itself are deleted.
+pub(super) fn move_envelope_transfers(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    category_id: &str,
+    into: &str,
+) -> Result<()> {
+    let condition = "from_category_id = ?1 OR to_category_id = ?1";
+    for transfer in transfers_where(conn, key, condition, &[category_id])? {
+        let repoint = |id: &str| if id == category_id { into } else { id }.to_string();
+        let after = EnvelopeTransfer {
+            from_category_id: repoint(&transfer.from_category_id),
+            to_category_id: repoint(&transfer.to_category_id),
+            ..transfer.clone()
+        };
+        if after.from_category_id == after.to_category_id {
+            conn.execute("DELETE FROM envelope_transfers WHERE id = ?1", [&transfer.id])?;
+            history::record(
+                conn,
+                key,
+                "envelope transfer",
+                &transfer.id,
+                ChangeAction::Delete,
+                Some(&transfer),
+                None,
+            )?;
+            continue;
+        }
+        put_envelope_transfer(conn, key, &after)?;
+        history::record(
+            conn,
+            key,
+            "envelope transfer",
+            &transfer.id,
+            ChangeAction::Update,
+            Some(&transfer),
+            Some(&after),
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Envelope {
+    assigned: Money,
+    activity: Money,
+    available: Money,
+}
+
+impl Envelope {
+    fn add(&mut self, amount: Money, this_month: bool, assigned: bool) {
+        if this_month {
+            if assigned {
+                self.assigned = self.assigned + amount;
+            } else {
+                self.activity = self.activity + amount;
+            }
+        }
+        self.available = self.available + amount;
+    }
+}
+
+impl Vault<'_> {
+    /// The transfers made in `month`, oldest first.
+    pub fn envelope_transfers(&self, month: String) -> Result<Vec<EnvelopeTransfer>> {
+        Validator::new().check("month", validation::iso_month(&month)).finish()?;
+        transfers_where(self.conn, self.key, "month = ?1", &[&month])
+    }
+
+    /// Moves `amount` from one envelope to another in `month`. The source may
+    /// end up negative, as it would after overspending.
+    pub fn move_between_envelopes(
+        &self,
+        month: String,
+        from_category_id: String,
+        to_category_id: String,
+        amount: Money,
+    ) -> Result<EnvelopeTransfer> {
+        let mut errors = Validator::new();
+        errors
+            .check("month", validation::iso_month(&month))
+            .check("amount", validation::positive(amount));
+        if from_category_id == to_category_id {
+            errors.check("to_category_id", Err("must differ from from_category_id".into()));
+        }
+        errors.finish()?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        // Reported against the field that names the bad category.
+        let ends = [("from_category_id", &from_category_id), ("to_category_id", &to_category_id)];
+        for (field, id) in ends {
+            categories::usable_category(&tx, self.key, id).map_err(|e| match e {
+                VaultError::Validation(mut errors) => {
+                    errors.iter_mut().for_each(|error| error.field = field.into());
+                    VaultError::Validation(errors)
+                }
+                other => other,
+            })?;
+        }
+        let transfer = EnvelopeTransfer {
+            id: Uuid::new_v4().to_string(),
+            month,
+            from_category_id,
+            to_category_id,
+            amount: amount.in_currency(DEFAULT_CURRENCY),
+            created_at: Utc::now().to_rfc3339(),
+        };
+        put_envelope_transfer(&tx, self.key, &transfer)?;
+        history::record(
+            &tx,
+            self.key,
+            "envelope transfer",
+            &transfer.id,
+            ChangeAction::Create,
+            None,
+            Some(&transfer),
+        )?;
+        tx.commit()?;
+        Ok(transfer)
+    }
+
+    /// The budget of `month` as envelopes: each category's budget for the
+    /// month is what it is assigned, and whatever is left or overspent
+    /// carries into the next month. Envelopes start with the first month
+    /// that has a category budget or a transfer; earlier expenses and income
+    /// do not count.
+    pub fn envelope_summary(&self, month: String) -> Result<EnvelopeSummary> {
+        Validator::new().check("month", validation::iso_month(&month)).finish()?;
+
+        let first: Option<String> = self.conn.query_row(
+            "SELECT MIN(month) FROM (
+                 SELECT month FROM category_budgets UNION ALL SELECT month FROM envelope_transfers
+             )",
+            [],
+            |row| row.get(0),
+        )?;
+        let start = first.filter(|first| *first < month).unwrap_or_else(|| month.clone());
+        let range = [start.as_str(), month.as_str()];
+
+        let mut envelopes: HashMap<String, Envelope> = HashMap::new();
+        let mut assigned_so_far = Money::ZERO;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT category_id, month, amount FROM category_budgets WHERE month BETWEEN ?1 AND ?2",
+        )?;
+        let rows = stmt.query_map(range, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                db::get_money(row, 2, self.key, DEFAULT_CURRENCY)?,
+            ))
+        })?;
+        for row in rows {
+            let (category_id, budget_month, amount) = row?;
+            assigned_so_far = assigned_so_far + amount;
+            envelopes.entry(category_id).or_default().add(amount, budget_month == month, true);
+        }
+
+        for transfer in transfers_where(self.conn, self.key, "month BETWEEN ?1 AND ?2", &range)? {
+            let this_month = transfer.month == month;
+            envelopes
+                .entry(transfer.from_category_id)
+                .or_default()
+                .add(-transfer.amount, this_month, true);
+            envelopes
+                .entry(transfer.to_category_id)
+                .or_default()
+                .add(transfer.amount, this_month, true);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT category_id, strftime('%Y-%m', date), amount, currency FROM expenses
+             WHERE strftime('%Y-%m', date) BETWEEN ?1 AND ?2 AND deleted_at IS NULL",
+        )?;
+        let rows = stmt.query_map(range, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                db::get_money(row, 2, self.key, &row.get::<_, String>(3)?)?,
+            ))
+        })?;
+        for row in rows {
+            let (category_id, expense_month, amount) = row?;
+            envelopes.entry(category_id).or_default().add(-amount, expense_month == month, false);
+        }
+
+        let income = income::income_between(self.conn, self.key, &month, &month)?;
+        let income_so_far = income::income_between(self.conn, self.key, &start, &month)?;
+
+        let lines: Vec<EnvelopeLine> = categories::all_categories(self.conn, self.key)?
+            .into_iter()
+            .filter_map(|c| {
+                let envelope = envelopes.remove(&c.id).unwrap_or_default();
+                let empty = envelope.assigned == Money::ZERO
+                    && envelope.activity == Money::ZERO
+                    && envelope.available == Money::ZERO;
+                (!c.archived || !empty).then_some(EnvelopeLine {
+                    category_id: c.id,
+                    name: c.name,
+                    parent_id: c.parent_id,
+                    assigned: envelope.assigned,
+                    activity: envelope.activity,
+                    available: envelope.available,
+                })
+            })
+            .collect();
+
+        Ok(EnvelopeSummary {
+            ready_to_assign: income_so_far - assigned_so_far,
+            assigned: lines.iter().map(|l| l.assigned).sum(),
+            activity: lines.iter().map(|l| l.activity).sum(),
+            available: lines.iter().map(|l| l.available).sum(),
+            month,
+            income,
+            categories: lines,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::expenses::CreateExpense;
+    use crate::vault::income::CreateIncome;
+    use crate::vault::test_support;
+
+    fn money(text: &str) -> Money {
+        text.parse().unwrap()
+    }
+
+    fn spend(vault: &Vault<'_>, category_id: &str, date: &str, amount: &str) {
+        vault
+            .create_expense(CreateExpense {
+                title: "Shopping".into(),
+                amount: money(amount),
+                currency: None,
+                category_id: category_id.into(),
+                date: date.into(),
+            })
+            .unwrap();
+    }
+
+    fn line<'s>(summary: &'s EnvelopeSummary, category_id: &str) -> &'s EnvelopeLine {
+        summary.categories.iter().find(|l| l.category_id == category_id).unwrap()
+    }
+
+    #[test]
+    fn leftovers_and_overspending_carry_into_the_next_month() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = test_support::category(&vault, "Food");
+        let fun = test_support::category(&vault, "Fun");
+        let salary = CreateIncome {
+            source: "Salary".into(),
+            amount: money("1000"),
+            currency: None,
+            date: "2024-03-01".into(),
+        };
+        vault.create_income(salary).unwrap();
+        vault.set_category_budget(food.clone(), "2024-03".into(), money("400")).unwrap();
+        vault.set_category_budget(fun.clone(), "2024-03".into(), money("100")).unwrap();
+        spend(&vault, &food, "2024-03-10", "350");
+        spend(&vault, &fun, "2024-03-11", "130");
+        spend(&vault, &food, "2024-02-10", "999");
+
+        let march = vault.envelope_summary("2024-03".into()).unwrap();
+        assert_eq!(line(&march, &food).activity, money("-350"));
+        assert_eq!(line(&march, &food).available, money("50"));
+        assert_eq!(line(&march, &fun).available, money("-30"));
+        assert_eq!(march.ready_to_assign, money("500"));
+
+        vault.set_category_budget(food.clone(), "2024-04".into(), money("300")).unwrap();
+        spend(&vault, &food, "2024-04-02", "20");
+        let april = vault.envelope_summary("2024-04".into()).unwrap();
+        assert_eq!(line(&april, &food).assigned, money("300"));
+        assert_eq!(line(&april, &food).activity, money("-20"));
+        assert_eq!(line(&april, &food).available, money("330"));
+        assert_eq!(line(&april, &fun).assigned, Money::ZERO);
+        assert_eq!(line(&april, &fun).available, money("-30"));
+        assert_eq!(april.ready_to_assign, money("200"));
+        assert_eq!(april.available, money("300"));
+    }
+
+    #[test]
+    fn moving_money_shifts_assigned_between_envelopes() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = test_support::category(&vault, "Food");
+        let fun = test_support::category(&vault, "Fun");
+        vault.set_category_budget(food.clone(), "2024-03".into(), money("400")).unwrap();
+        vault
+            .move_between_envelopes("2024-03".into(), food.clone(), fun.clone(), money("75"))
+            .unwrap();
+
+        let summary = vault.envelope_summary("2024-03".into()).unwrap();
+        assert_eq!(line(&summary, &food).assigned, money("325"));
+        assert_eq!(line(&summary, &fun).assigned, money("75"));
+        assert_eq!(summary.assigned, money("400"));
+        assert_eq!(vault.envelope_transfers("2024-03".into()).unwrap().len(), 1);
+
+        vault.undo_last_change().unwrap();
+        assert!(vault.envelope_transfers("2024-03".into()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn moves_need_two_categories_and_a_positive_amount() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = test_support::category(&vault, "Food");
+        let month = || "2024-03".to_string();
+
+        let same = vault.move_between_envelopes(month(), food.clone(), food.clone(), money("1"));
+        assert!(matches!(same, Err(VaultError::Validation(_))));
+        let zero = vault.move_between_envelopes(month(), food.clone(), "x".into(), Money::ZERO);
+        assert!(matches!(zero, Err(VaultError::Validation(_))));
+        match vault.move_between_envelopes(month(), food, "nope".into(), money("1")) {
+            Err(VaultError::Validation(errors)) => assert_eq!(errors[0].field, "to_category_id"),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn merging_categories_points_their_transfers_at_the_target() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let food = test_support::category(&vault, "Food");
+        let fun = test_support::category(&vault, "Fun");
+        let home = test_support::category(&vault, "Home");
+        let month = || "2024-03".to_string();
+        vault.move_between_envelopes(month(), food.clone(), fun.clone(), money("75")).unwrap();
+        vault.move_between_envelopes(month(), home.clone(), fun.clone(), money("20")).unwrap();
+
+        vault.merge_categories(vec![fun], &food).unwrap();
+
+        let transfers = vault.envelope_transfers(month()).unwrap();
+        let ends: Vec<_> = transfers
+            .iter()
+            .map(|t| (t.from_category_id.as_str(), t.to_category_id.as_str(), t.amount))
+            .collect();
+        assert_eq!(ends, [(home.as_str(), food.as_str(), money("20"))]);
+    }
+}
@@ -0,0 +1,373 @@
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::categories;
+use super::history::{self, ChangeAction};
+use super::{trash, Vault};
+use crate::crypto::{encrypt_value, DerivedKey};
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::money::{Money, DEFAULT_CURRENCY};
+use crate::validation::{self, Validator};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Expense {
+    pub id: String,
+    pub title: String,
+    pub amount: Money,
+    pub currency: String,
+    /// Missing from history entries logged before categories existed.
+    #[serde(default)]
+    pub category_id: String,
+    /// Name of the category, for display.
+    pub category: String,
+    pub date: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExpense {
+    pub title: String,
+    pub amount: Money,
+    pub currency: Option<String>,
+    pub category_id: String,
+    pub date: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateExpense {
+    pub title: Option<String>,
+    pub amount: Option<Money>,
+    pub currency: Option<String>,
+    pub category_id: Option<String>,
+    pub date: Option<String>,
+}
+
+impl CreateExpense {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check("title", validation::not_blank(&self.title))
+            .check("amount", validation::not_negative(self.amount))
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check("category_id", validation::not_blank(&self.category_id))
+            .check("date", validation::iso_date(&self.date))
+            .finish()
+    }
+}
+
+impl UpdateExpense {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check_opt("title", self.title.as_deref(), validation::not_blank)
+            .check_opt("amount", self.amount, validation::not_negative)
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check_opt("category_id", self.category_id.as_deref(), validation::not_blank)
+            .check_opt("date", self.date.as_deref(), validation::iso_date)
+            .finish()
+    }
+}
+
+/// Expenses with the name of their category; filter with `e.` columns.
+const SELECT_EXPENSES: &str =
+    "SELECT e.id, e.title, e.amount, e.currency, e.category_id, c.name, e.date, e.created_at
+     FROM expenses e JOIN categories c ON c.id = e.category_id";
+
+fn expense_from_row(row: &rusqlite::Row, key: &DerivedKey) -> rusqlite::Result<Expense> {
+    let currency: String = row.get(3)?;
+    Ok(Expense {
+        id: row.get(0)?,
+        title: db::get_decrypted(row, 1, key)?,
+        amount: db::get_money(row, 2, key, &currency)?,
+        currency,
+        category_id: row.get(4)?,
+        category: db::get_decrypted(row, 5, key)?,
+        date: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+/// Reads one expense, whether or not it is in the trash.
+pub(super) fn find_expense(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    id: &str,
+) -> Result<Expense> {
+    conn.query_row(
+        &format!("{SELECT_EXPENSES} WHERE e.id = ?1"),
+        [id],
+        |row| expense_from_row(row, key),
+    )
+    .optional()?
+    .ok_or_else(|| VaultError::NotFound { entity: "expense", id: id.to_string() })
+}
+
+/// Overwrites every field of a stored expense; used to undo updates and to
+/// move expenses between categories. A snapshot without a `category_id`
+/// keeps the category the expense has now.
+pub(super) fn put_expense(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    expense: &Expense,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE expenses SET title = ?1, amount = ?2, currency = ?3,
+             category_id = COALESCE(NULLIF(?4, ''), category_id), category = ?5, date = ?6
+         WHERE id = ?7",
+        rusqlite::params![
+            encrypt_value(key, &expense.title)?,
+            encrypt_value(key, &expense.amount.in_currency(&expense.currency))?,
+            expense.currency,
+            expense.category_id,
+            encrypt_value(key, &expense.category)?,
+            expense.date,
+            expense.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(VaultError::NotFound { entity: "expense", id: expense.id.clone() });
+    }
+    Ok(())
+}
+
+impl Vault<'_> {
+    /// Expenses outside the trash, newest first.
+    pub fn list_expenses(&self) -> Result<Vec<Expense>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{SELECT_EXPENSES} WHERE e.deleted_at IS NULL ORDER BY e.date DESC"
+        ))?;
+        let rows = stmt.query_map([], |row| expense_from_row(row, self.key))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(VaultError::Database)
+    }
+
+    pub fn create_expense(&self, input: CreateExpense) -> Result<Expense> {
+        input.validate()?;
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        let currency = input.currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+        let amount = input.amount.in_currency(&currency);
+
+        let tx = self.conn.unchecked_transaction()?;
+        let category = categories::usable_category(&tx, self.key, &input.category_id)?;
+        tx.execute(
+            "INSERT INTO expenses (id, title, amount, currency, category_id, category, date, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                id,
+                encrypt_value(self.key, &input.title)?,
+                encrypt_value(self.key, &amount)?,
+                currency,
+                category.id,
+                encrypt_value(self.key, &category.name)?,
+                input.date,
+                now
+            ],
+        )?;
+
+        let expense = Expense {
+            id,
+            title: input.title,
+            amount,
+            currency,
+            category_id: category.id,
+            category: category.name,
+            date: input.date,
+            created_at: now,
+        };
+        history::record(
+            &tx,
+            self.key,
+            "expense",
+            &expense.id,
+            ChangeAction::Create,
+            None,
+            Some(&expense),
+        )?;
+        tx.commit()?;
+        Ok(expense)
+    }
+
+    /// Changes the given fields in one statement and returns the stored expense.
+    pub fn update_expense(&self, id: &str, input: UpdateExpense) -> Result<Expense> {
+        input.validate()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let before = find_expense(&tx, self.key, id)?;
+        // Rounded to the currency the row ends up with, so changing only the
+        // currency rescales the stored amount as well.
+        let currency = input.currency.as_deref().unwrap_or(&before.currency);
+        let amount = input.amount.unwrap_or(before.amount).in_currency(currency);
+        let category = input
+            .category_id
+            .map(|category_id| categories::usable_category(&tx, self.key, &category_id))
+            .transpose()?;
+        let updated = tx.execute(
+            "UPDATE expenses SET
+                 title = COALESCE(?1, title),
+                 amount = ?2,
+                 currency = COALESCE(?3, currency),
+                 category_id = COALESCE(?4, category_id),
+                 category = COALESCE(?5, category),
+                 date = COALESCE(?6, date)
+             WHERE id = ?7 AND deleted_at IS NULL",
+            rusqlite::params![
+                input.title.map(|title| encrypt_value(self.key, &title)).transpose()?,
+                encrypt_value(self.key, &amount)?,
+                input.currency,
+                category.as_ref().map(|category| &category.id),
+                category.as_ref().map(|category| encrypt_value(self.key, &category.name)).transpose()?,
+                input.date,
+                id
+            ],
+        )?;
+        if updated == 0 {
+            return Err(VaultError::NotFound { entity: "expense", id: id.to_string() });
+        }
+
+        let after = find_expense(&tx, self.key, id)?;
+        history::record(
+            &tx,
+            self.key,
+            "expense",
+            id,
+            ChangeAction::Update,
+            Some(&before),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Moves the expense to the trash; see `restore_expense`.
+    pub fn delete_expense(&self, id: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if trash::move_to_trash(&tx, "expenses", id)? {
+            let before = find_expense(&tx, self.key, id)?;
+            history::record(&tx, self.key, "expense", id, ChangeAction::Delete, Some(&before), None)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn restore_expense(&self, id: &str) -> Result<Expense> {
+        let tx = self.conn.unchecked_transaction()?;
+        trash::restore_from_trash(&tx, "expenses", "expense", id)?;
+        let after = find_expense(&tx, self.key, id)?;
+        history::record(&tx, self.key, "expense", id, ChangeAction::Restore, None, Some(&after))?;
+        tx.commit()?;
+        Ok(after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::test_support;
+
+    fn lunch(vault: &Vault) -> CreateExpense {
+        CreateExpense {
+            title: "Lunch".into(),
+            amount: "12.5".parse().unwrap(),
+            currency: None,
+            category_id: test_support::category(vault, "Food"),
+            date: "2024-03-05".into(),
+        }
+    }
+
+    #[test]
+    fn created_expense_is_listed_in_its_currency() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+
+        let created = vault.create_expense(lunch(&vault)).unwrap();
+        assert_eq!(created.currency, "EUR");
+        assert_eq!(created.amount.to_string(), "12.50");
+
+        let listed = vault.list_expenses().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, created.id);
+        assert_eq!(listed[0].title, "Lunch");
+    }
+
+    #[test]
+    fn fields_are_stored_encrypted() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        vault.create_expense(lunch(&vault)).unwrap();
+
+        let title: String = conn.query_row("SELECT title FROM expenses", [], |r| r.get(0)).unwrap();
+        assert_ne!(title, "Lunch");
+    }
+
+    #[test]
+    fn invalid_input_names_every_failing_field() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let input = CreateExpense {
+            amount: "-1".parse().unwrap(),
+            currency: Some("EURO".into()),
+            date: "05.03.2024".into(),
+            ..lunch(&vault)
+        };
+
+        match vault.create_expense(input) {
+            Err(VaultError::Validation(errors)) => {
+                let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+                assert_eq!(fields, ["amount", "currency", "date"]);
+            }
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+        assert!(vault.list_expenses().unwrap().is_empty());
+    }
+
+    #[test]
+    fn update_changes_only_the_given_fields() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let created = vault.create_expense(lunch(&vault)).unwrap();
+
+        let update = UpdateExpense { amount: Some("20".parse().unwrap()), ..Default::default() };
+        let updated = vault.update_expense(&created.id, update).unwrap();
+        assert_eq!(updated.amount.to_string(), "20.00");
+        assert_eq!(updated.title, "Lunch");
+    }
+
+    #[test]
+    fn updated_amount_is_rounded_to_the_resulting_currency() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let created = vault.create_expense(lunch(&vault)).unwrap();
+
+        let update = UpdateExpense { amount: Some("12.345".parse().unwrap()), ..Default::default() };
+        let updated = vault.update_expense(&created.id, update).unwrap();
+        assert_eq!(updated.amount.to_string(), "12.35");
+
+        let update = UpdateExpense { currency: Some("JPY".into()), ..Default::default() };
+        let updated = vault.update_expense(&created.id, update).unwrap();
+        assert_eq!(updated.amount.to_string(), "12");
+    }
+
+    #[test]
+    fn updating_a_missing_expense_is_not_found() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let result = vault.update_expense("nope", UpdateExpense::default());
+        assert!(matches!(result, Err(VaultError::NotFound { entity: "expense", .. })));
+    }
+
+    #[test]
+    fn deleted_expense_is_hidden_until_restored() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let created = vault.create_expense(lunch(&vault)).unwrap();
+
+        vault.delete_expense(&created.id).unwrap();
+        assert!(vault.list_expenses().unwrap().is_empty());
+        assert!(vault.update_expense(&created.id, UpdateExpense::default()).is_err());
+
+        let restored = vault.restore_expense(&created.id).unwrap();
+        assert_eq!(restored.title, "Lunch");
+        assert_eq!(vault.list_expenses().unwrap().len(), 1);
+    }
+}
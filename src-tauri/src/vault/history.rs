@@ -0,0 +1,405 @@
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{
+    budget, categories, envelopes, expenses, income, portfolio, savings, subscriptions, trash,
+    Vault,
+};
+use crate::crypto::{encrypt_value, DerivedKey};
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::validation::FieldError;
+
+/// Entities whose changes are logged: (entity name, table).
+const TRACKED_ENTITIES: &[(&str, &str)] = &[
+    ("expense", "expenses"),
+    ("subscription", "subscriptions"),
+    ("position", "portfolio_positions"),
+    ("savings plan", "savings_plans"),
+    ("budget month", "budget"),
+    ("category", "categories"),
+    ("category budget", "category_budgets"),
+    ("envelope transfer", "envelope_transfers"),
+    ("income", "income"),
+    ("recurring income", "recurring_income"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Create,
+    Update,
+    /// Moved to the trash.
+    Delete,
+    /// Taken out of the trash.
+    Restore,
+    /// Reverted the change named in `reverts`.
+    Undo,
+}
+
+impl ChangeAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangeAction::Create => "create",
+            ChangeAction::Update => "update",
+            ChangeAction::Delete => "delete",
+            ChangeAction::Restore => "restore",
+            ChangeAction::Undo => "undo",
+        }
+    }
+
+    fn parse(value: &str) -> rusqlite::Result<Self> {
+        Ok(match value {
+            "create" => ChangeAction::Create,
+            "update" => ChangeAction::Update,
+            "delete" => ChangeAction::Delete,
+            "restore" => ChangeAction::Restore,
+            "undo" => ChangeAction::Undo,
+            other => {
+                return Err(rusqlite::Error::InvalidColumnType(
+                    3,
+                    format!("action {other}"),
+                    rusqlite::types::Type::Text,
+                ))
+            }
+        })
+    }
+}
+
+/// One entry of the change log. `before` and `after` are the entity as the
+/// frontend sees it; a create has no `before`, a delete no `after`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub id: String,
+    pub entity: String,
+    pub entity_id: String,
+    pub action: ChangeAction,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub reverts: Option<String>,
+    pub changed_at: String,
+}
+
+const CHANGE_COLUMNS: &str =
+    "id, entity, entity_id, action, before_json, after_json, reverts, changed_at";
+
+fn change_from_row(row: &rusqlite::Row, key: &DerivedKey) -> rusqlite::Result<Change> {
+    Ok(Change {
+        id: row.get(0)?,
+        entity: row.get(1)?,
+        entity_id: row.get(2)?,
+        action: ChangeAction::parse(&row.get::<_, String>(3)?)?,
+        before: db::get_decrypted_opt(row, 4, key)?,
+        after: db::get_decrypted_opt(row, 5, key)?,
+        reverts: row.get(6)?,
+        changed_at: row.get(7)?,
+    })
+}
+
+fn tracked_entity(entity: &str) -> Result<(&'static str, &'static str)> {
+    TRACKED_ENTITIES
+        .iter()
+        .find(|(name, _)| *name == entity)
+        .copied()
+        .ok_or_else(|| {
+            VaultError::Validation(vec![FieldError {
+                field: "entity".into(),
+                message: format!("unknown entity {entity}"),
+            }])
+        })
+}
+
+fn to_json<T: Serialize>(value: Option<&T>) -> Result<Option<Value>> {
+    value
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(|e| VaultError::Parse(e.to_string()))
+}
+
+fn from_json<T: DeserializeOwned>(value: Value) -> Result<T> {
+    serde_json::from_value(value).map_err(|e| VaultError::Parse(e.to_string()))
+}
+
+fn insert_change(conn: &rusqlite::Connection, key: &DerivedKey, change: &Change) -> Result<()> {
+    conn.execute(
+        &format!("INSERT INTO change_log ({CHANGE_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"),
+        rusqlite::params![
+            change.id,
+            change.entity,
+            change.entity_id,
+            change.action.as_str(),
+            change.before.as_ref().map(|v| encrypt_value(key, v)).transpose()?,
+            change.after.as_ref().map(|v| encrypt_value(key, v)).transpose()?,
+            change.reverts,
+            change.changed_at,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Appends a change to the log. Callers pass the transaction that makes the
+/// change, so the log never disagrees with the data.
+pub(super) fn record<T: Serialize>(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    entity: &str,
+    entity_id: &str,
+    action: ChangeAction,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<()> {
+    insert_change(
+        conn,
+        key,
+        &Change {
+            id: Uuid::new_v4().to_string(),
+            entity: entity.to_string(),
+            entity_id: entity_id.to_string(),
+            action,
+            before: to_json(before)?,
+            after: to_json(after)?,
+            reverts: None,
+            changed_at: Utc::now().to_rfc3339(),
+        },
+    )
+}
+
+/// Writes a logged snapshot back over the entity's row.
+fn put_snapshot(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    entity: &str,
+    snapshot: Value,
+) -> Result<()> {
+    match entity {
+        "expense" => expenses::put_expense(conn, key, &from_json(snapshot)?),
+        "subscription" => subscriptions::put_subscription(conn, key, &from_json(snapshot)?),
+        "position" => portfolio::put_position(conn, key, &from_json(snapshot)?),
+        "savings plan" => savings::put_savings_plan(conn, key, &from_json(snapshot)?),
+        "budget month" => budget::put_budget_month(conn, key, &from_json(snapshot)?),
+        "category" => categories::put_category(conn, key, &from_json(snapshot)?),
+        "category budget" => budget::put_category_budget(conn, key, &from_json(snapshot)?),
+        "envelope transfer" => envelopes::put_envelope_transfer(conn, key, &from_json(snapshot)?),
+        "income" => income::put_income(conn, key, &from_json(snapshot)?),
+        "recurring income" => income::put_recurring_income(conn, key, &from_json(snapshot)?),
+        _ => Err(VaultError::Parse(format!("change log entry for unknown entity {entity}"))),
+    }
+}
+
+/// Applies the inverse of `change` to the data.
+fn revert(conn: &rusqlite::Connection, key: &DerivedKey, change: &Change) -> Result<()> {
+    let (entity, table) = tracked_entity(&change.entity)?;
+    let id = change.entity_id.as_str();
+    let missing = || VaultError::NotFound { entity, id: id.to_string() };
+    match change.action {
+        // Budget months, category limits, envelope transfers and categories
+        // have no trash; every other entity goes there, so undoing a create
+        // never destroys anything for good.
+        ChangeAction::Create
+            if matches!(table, "budget" | "category_budgets" | "envelope_transfers") =>
+        {
+            if conn.execute(&format!("DELETE FROM {table} WHERE id = ?1"), [id])? == 0 {
+                return Err(missing());
+            }
+        }
+        // A category that expenses, even trashed ones, still point to is
+        // archived instead.
+        ChangeAction::Create if table == "categories" => {
+            let deleted = conn.execute(
+                "DELETE FROM categories WHERE id = ?1
+                 AND NOT EXISTS (SELECT 1 FROM expenses WHERE category_id = ?1)
+                 AND NOT EXISTS (SELECT 1 FROM categories WHERE parent_id = ?1)",
+                [id],
+            )?;
+            if deleted == 0 && conn.execute("UPDATE categories SET archived = 1 WHERE id = ?1", [id])? == 0 {
+                return Err(missing());
+            }
+        }
+        ChangeAction::Delete
+            if matches!(table, "categories" | "category_budgets" | "envelope_transfers") =>
+        {
+            let before = change.before.clone().ok_or_else(missing)?;
+            put_snapshot(conn, key, entity, before)?;
+        }
+        ChangeAction::Create | ChangeAction::Restore => {
+            if !trash::move_to_trash(conn, table, id)? {
+                return Err(missing());
+            }
+        }
+        ChangeAction::Delete => trash::restore_from_trash(conn, table, entity, id)?,
+        ChangeAction::Update => {
+            let before = change.before.clone().ok_or_else(missing)?;
+            put_snapshot(conn, key, entity, before)?;
+        }
+        ChangeAction::Undo => unreachable!("undo entries are never reverted"),
+    }
+    Ok(())
+}
+
+impl Vault<'_> {
+    /// Reverts the newest change that has not been undone yet and returns the
+    /// log entry recording the undo, or `None` when there is nothing left.
+    pub fn undo_last_change(&self) -> Result<Option<Change>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let last = tx
+            .query_row(
+                &format!(
+                    "SELECT {CHANGE_COLUMNS} FROM change_log c
+                     WHERE action != 'undo'
+                       AND NOT EXISTS (SELECT 1 FROM change_log u WHERE u.reverts = c.id)
+                     ORDER BY rowid DESC LIMIT 1"
+                ),
+                [],
+                |row| change_from_row(row, self.key),
+            )
+            .optional()?;
+        let Some(last) = last else {
+            return Ok(None);
+        };
+
+        revert(&tx, self.key, &last)?;
+        let undo = Change {
+            id: Uuid::new_v4().to_string(),
+            entity: last.entity.clone(),
+            entity_id: last.entity_id.clone(),
+            action: ChangeAction::Undo,
+            before: last.after.clone(),
+            after: last.before.clone(),
+            reverts: Some(last.id.clone()),
+            changed_at: Utc::now().to_rfc3339(),
+        };
+        insert_change(&tx, self.key, &undo)?;
+        tx.commit()?;
+        Ok(Some(undo))
+    }
+
+    /// Every logged change of one entity, newest first.
+    pub fn history(&self, entity: &str, id: &str) -> Result<Vec<Change>> {
+        let (entity, _) = tracked_entity(entity)?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {CHANGE_COLUMNS} FROM change_log
+             WHERE entity = ?1 AND entity_id = ?2
+             ORDER BY rowid DESC"
+        ))?;
+        let rows = stmt.query_map([entity, id], |row| change_from_row(row, self.key))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(VaultError::Database)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use crate::vault::expenses::{CreateExpense, UpdateExpense};
+    use crate::vault::test_support;
+
+    fn coffee(vault: &Vault) -> String {
+        vault
+            .create_expense(CreateExpense {
+                title: "Coffee".into(),
+                amount: "3.20".parse().unwrap(),
+                currency: None,
+                category_id: test_support::category(vault, "Food"),
+                date: "2024-03-05".into(),
+            })
+            .unwrap()
+            .id
+    }
+
+    fn actions(changes: &[Change]) -> Vec<ChangeAction> {
+        changes.iter().map(|c| c.action).collect()
+    }
+
+    #[test]
+    fn every_change_is_logged_newest_first() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let id = coffee(&vault);
+        let update = UpdateExpense { title: Some("Espresso".into()), ..Default::default() };
+        vault.update_expense(&id, update).unwrap();
+        vault.delete_expense(&id).unwrap();
+        vault.restore_expense(&id).unwrap();
+
+        let history = vault.history("expense", &id).unwrap();
+        assert_eq!(
+            actions(&history),
+            [ChangeAction::Restore, ChangeAction::Delete, ChangeAction::Update, ChangeAction::Create]
+        );
+        assert_eq!(history[2].before.as_ref().unwrap()["title"], "Coffee");
+        assert_eq!(history[2].after.as_ref().unwrap()["title"], "Espresso");
+    }
+
+    #[test]
+    fn snapshots_are_stored_encrypted() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        coffee(&vault);
+
+        let after: String =
+            conn.query_row("SELECT after_json FROM change_log", [], |r| r.get(0)).unwrap();
+        assert!(!after.contains("Coffee"));
+    }
+
+    #[test]
+    fn undo_skips_entries_emptied_from_the_trash() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let kept = coffee(&vault);
+        let gone = coffee(&vault);
+        vault.delete_expense(&gone).unwrap();
+        vault.empty_trash().unwrap();
+
+        assert!(vault.history("expense", &gone).unwrap().is_empty());
+        let undo = vault.undo_last_change().unwrap().unwrap();
+        assert_eq!(undo.entity_id, kept);
+        assert!(vault.list_expenses().unwrap().is_empty());
+    }
+
+    #[test]
+    fn undo_walks_back_through_the_log() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let id = coffee(&vault);
+        let update = UpdateExpense { amount: Some("4".parse().unwrap()), ..Default::default() };
+        vault.update_expense(&id, update).unwrap();
+        vault.delete_expense(&id).unwrap();
+
+        let undo = vault.undo_last_change().unwrap().unwrap();
+        assert_eq!(undo.action, ChangeAction::Undo);
+        assert_eq!(vault.list_expenses().unwrap()[0].amount.to_string(), "4.00");
+
+        vault.undo_last_change().unwrap();
+        assert_eq!(vault.list_expenses().unwrap()[0].amount.to_string(), "3.20");
+
+        vault.undo_last_change().unwrap();
+        assert!(vault.list_expenses().unwrap().is_empty());
+
+        // The trashed expense still uses its category, so undoing the
+        // category's creation archives it.
+        vault.undo_last_change().unwrap();
+        assert!(vault.list_categories().unwrap()[0].archived);
+        assert!(vault.undo_last_change().unwrap().is_none());
+    }
+
+    #[test]
+    fn undoing_a_new_budget_month_deletes_it() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        vault.upsert_budget_month("2024-03".into(), Money::ZERO).unwrap();
+
+        vault.undo_last_change().unwrap();
+        assert!(vault.budget_months().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unknown_entity_is_a_validation_error() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        assert!(matches!(vault.history("invoice", "x"), Err(VaultError::Validation(_))));
+    }
+}
@@ -0,0 +1,674 @@
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::history::{self, ChangeAction};
+use super::{trash, Vault};
+use crate::crypto::{encrypt_value, DerivedKey};
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::money::{Money, DEFAULT_CURRENCY};
+use crate::validation::{self, Validator, INCOME_INTERVALS};
+
+/// Money that came in on one day: a salary payment, an invoice, a refund.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Income {
+    pub id: String,
+    pub source: String,
+    pub amount: Money,
+    pub currency: String,
+    pub date: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateIncome {
+    pub source: String,
+    pub amount: Money,
+    pub currency: Option<String>,
+    pub date: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateIncome {
+    pub source: Option<String>,
+    pub amount: Option<Money>,
+    pub currency: Option<String>,
+    pub date: Option<String>,
+}
+
+/// Income that arrives on a schedule. Each occurrence is booked as an
+/// `Income` entry once its date has come; `next_date` is the next one due.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecurringIncome {
+    pub id: String,
+    pub source: String,
+    pub amount: Money,
+    pub currency: String,
+    pub interval: String,
+    pub next_date: String,
+    /// The occurrence the schedule counts from: the first one, or the
+    /// `next_date` last set by hand.
+    pub anchor_date: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRecurringIncome {
+    pub source: String,
+    pub amount: Money,
+    pub currency: Option<String>,
+    pub interval: String,
+    pub next_date: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateRecurringIncome {
+    pub source: Option<String>,
+    pub amount: Option<Money>,
+    pub currency: Option<String>,
+    pub interval: Option<String>,
+    pub next_date: Option<String>,
+}
+
+impl CreateIncome {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check("source", validation::not_blank(&self.source))
+            .check("amount", validation::not_negative(self.amount))
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check("date", validation::iso_date(&self.date))
+            .finish()
+    }
+}
+
+impl UpdateIncome {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check_opt("source", self.source.as_deref(), validation::not_blank)
+            .check_opt("amount", self.amount, validation::not_negative)
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check_opt("date", self.date.as_deref(), validation::iso_date)
+            .finish()
+    }
+}
+
+impl CreateRecurringIncome {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check("source", validation::not_blank(&self.source))
+            .check("amount", validation::not_negative(self.amount))
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check("interval", validation::one_of(&self.interval, INCOME_INTERVALS))
+            .check("next_date", validation::iso_date(&self.next_date))
+            .finish()
+    }
+}
+
+impl UpdateRecurringIncome {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check_opt("source", self.source.as_deref(), validation::not_blank)
+            .check_opt("amount", self.amount, validation::not_negative)
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check_opt("interval", self.interval.as_deref(), |interval| {
+                validation::one_of(interval, INCOME_INTERVALS)
+            })
+            .check_opt("next_date", self.next_date.as_deref(), validation::iso_date)
+            .finish()
+    }
+}
+
+const INCOME_COLUMNS: &str = "id, source, amount, currency, date, created_at";
+const RECURRING_INCOME_COLUMNS: &str =
+    "id, source, amount, currency, interval, next_date, anchor_date, created_at";
+
+fn income_from_row(row: &rusqlite::Row, key: &DerivedKey) -> rusqlite::Result<Income> {
+    let currency: String = row.get(3)?;
+    Ok(Income {
+        id: row.get(0)?,
+        source: db::get_decrypted(row, 1, key)?,
+        amount: db::get_money(row, 2, key, &currency)?,
+        currency,
+        date: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+fn recurring_income_from_row(
+    row: &rusqlite::Row,
+    key: &DerivedKey,
+) -> rusqlite::Result<RecurringIncome> {
+    let currency: String = row.get(3)?;
+    Ok(RecurringIncome {
+        id: row.get(0)?,
+        source: db::get_decrypted(row, 1, key)?,
+        amount: db::get_money(row, 2, key, &currency)?,
+        currency,
+        interval: row.get(4)?,
+        next_date: row.get(5)?,
+        anchor_date: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+/// Reads one income entry, whether or not it is in the trash.
+fn find_income(conn: &rusqlite::Connection, key: &DerivedKey, id: &str) -> Result<Income> {
+    conn.query_row(
+        &format!("SELECT {INCOME_COLUMNS} FROM income WHERE id = ?1"),
+        [id],
+        |row| income_from_row(row, key),
+    )
+    .optional()?
+    .ok_or_else(|| VaultError::NotFound { entity: "income", id: id.to_string() })
+}
+
+/// Reads one recurring income, whether or not it is in the trash.
+fn find_recurring_income(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    id: &str,
+) -> Result<RecurringIncome> {
+    conn.query_row(
+        &format!("SELECT {RECURRING_INCOME_COLUMNS} FROM recurring_income WHERE id = ?1"),
+        [id],
+        |row| recurring_income_from_row(row, key),
+    )
+    .optional()?
+    .ok_or_else(|| VaultError::NotFound { entity: "recurring income", id: id.to_string() })
+}
+
+fn insert_income(conn: &rusqlite::Connection, key: &DerivedKey, income: &Income) -> Result<()> {
+    conn.execute(
+        "INSERT INTO income (id, source, amount, currency, date, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            income.id,
+            encrypt_value(key, &income.source)?,
+            encrypt_value(key, &income.amount)?,
+            income.currency,
+            income.date,
+            income.created_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// Overwrites every field of a stored income entry; used to undo updates.
+pub(super) fn put_income(conn: &rusqlite::Connection, key: &DerivedKey, income: &Income) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE income SET source = ?1, amount = ?2, currency = ?3, date = ?4 WHERE id = ?5",
+        rusqlite::params![
+            encrypt_value(key, &income.source)?,
+            encrypt_value(key, &income.amount.in_currency(&income.currency))?,
+            income.currency,
+            income.date,
+            income.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(VaultError::NotFound { entity: "income", id: income.id.clone() });
+    }
+    Ok(())
+}
+
+/// Overwrites every field of a stored recurring income; used to undo
+/// updates.
+pub(super) fn put_recurring_income(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    recurring: &RecurringIncome,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE recurring_income SET source = ?1, amount = ?2, currency = ?3, interval = ?4,
+             next_date = ?5, anchor_date = ?6
+         WHERE id = ?7",
+        rusqlite::params![
+            encrypt_value(key, &recurring.source)?,
+            encrypt_value(key, &recurring.amount.in_currency(&recurring.currency))?,
+            recurring.currency,
+            recurring.interval,
+            recurring.next_date,
+            recurring.anchor_date,
+            recurring.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(VaultError::NotFound { entity: "recurring income", id: recurring.id.clone() });
+    }
+    Ok(())
+}
+
+/// Income entries outside the trash dated in `from` to `to` (YYYY-MM, both
+/// included), added up regardless of currency like the expense totals.
+pub(super) fn income_between(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    from: &str,
+    to: &str,
+) -> Result<Money> {
+    let mut stmt = conn.prepare(
+        "SELECT amount, currency FROM income
+         WHERE strftime('%Y-%m', date) BETWEEN ?1 AND ?2 AND deleted_at IS NULL",
+    )?;
+    let rows = stmt.query_map([from, to], |row| {
+        db::get_money(row, 0, key, &row.get::<_, String>(1)?)
+    })?;
+    rows.sum::<rusqlite::Result<Money>>().map_err(VaultError::Database)
+}
+
+/// The occurrence after `date`, a whole number of intervals after `anchor`.
+/// A day past the end of a shorter month moves to its last day, and later
+/// occurrences go back to the anchor's day.
+fn next_occurrence(anchor: &str, date: &str, interval: &str) -> Result<String> {
+    let step = match interval {
+        "quarterly" => 3,
+        "yearly" => 12,
+        _ => 1,
+    };
+    let parse = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok();
+    parse(anchor)
+        .zip(parse(date))
+        .and_then(|(anchor, date)| {
+            let elapsed = (date.year() - anchor.year()) * 12 + date.month() as i32
+                - anchor.month() as i32;
+            let steps = u32::try_from(elapsed).unwrap_or(0) / step + 1;
+            anchor.checked_add_months(Months::new(steps * step))
+        })
+        .map(|date| date.format("%Y-%m-%d").to_string())
+        .ok_or_else(|| VaultError::Parse(format!("cannot schedule income after {date}")))
+}
+
+impl Vault<'_> {
+    /// Income entries outside the trash, newest first.
+    pub fn list_income(&self) -> Result<Vec<Income>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {INCOME_COLUMNS} FROM income WHERE deleted_at IS NULL ORDER BY date DESC"
+        ))?;
+        let rows = stmt.query_map([], |row| income_from_row(row, self.key))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(VaultError::Database)
+    }
+
+    pub fn create_income(&self, input: CreateIncome) -> Result<Income> {
+        input.validate()?;
+        let currency = input.currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+        let income = Income {
+            id: Uuid::new_v4().to_string(),
+            source: input.source,
+            amount: input.amount.in_currency(&currency),
+            currency,
+            date: input.date,
+            created_at: Utc::now().to_rfc3339(),
+        };
+
+        let tx = self.conn.unchecked_transaction()?;
+        insert_income(&tx, self.key, &income)?;
+        history::record(&tx, self.key, "income", &income.id, ChangeAction::Create, None, Some(&income))?;
+        tx.commit()?;
+        Ok(income)
+    }
+
+    /// Changes the given fields and returns the stored entry.
+    pub fn update_income(&self, id: &str, input: UpdateIncome) -> Result<Income> {
+        input.validate()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let before = find_income(&tx, self.key, id)?;
+        let currency = input.currency.unwrap_or_else(|| before.currency.clone());
+        let after = Income {
+            source: input.source.unwrap_or_else(|| before.source.clone()),
+            amount: input.amount.unwrap_or(before.amount).in_currency(&currency),
+            currency,
+            date: input.date.unwrap_or_else(|| before.date.clone()),
+            ..before.clone()
+        };
+        let updated = tx.execute(
+            "UPDATE income SET source = ?1, amount = ?2, currency = ?3, date = ?4
+             WHERE id = ?5 AND deleted_at IS NULL",
+            rusqlite::params![
+                encrypt_value(self.key, &after.source)?,
+                encrypt_value(self.key, &after.amount)?,
+                after.currency,
+                after.date,
+                id
+            ],
+        )?;
+        if updated == 0 {
+            return Err(VaultError::NotFound { entity: "income", id: id.to_string() });
+        }
+        history::record(
+            &tx,
+            self.key,
+            "income",
+            id,
+            ChangeAction::Update,
+            Some(&before),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Moves the entry to the trash; see `restore_income`.
+    pub fn delete_income(&self, id: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if trash::move_to_trash(&tx, "income", id)? {
+            let before = find_income(&tx, self.key, id)?;
+            history::record(&tx, self.key, "income", id, ChangeAction::Delete, Some(&before), None)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn restore_income(&self, id: &str) -> Result<Income> {
+        let tx = self.conn.unchecked_transaction()?;
+        trash::restore_from_trash(&tx, "income", "income", id)?;
+        let after = find_income(&tx, self.key, id)?;
+        history::record(&tx, self.key, "income", id, ChangeAction::Restore, None, Some(&after))?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Recurring income outside the trash, next occurrence first.
+    pub fn list_recurring_income(&self) -> Result<Vec<RecurringIncome>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {RECURRING_INCOME_COLUMNS} FROM recurring_income
+             WHERE deleted_at IS NULL ORDER BY next_date ASC"
+        ))?;
+        let rows = stmt.query_map([], |row| recurring_income_from_row(row, self.key))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(VaultError::Database)
+    }
+
+    pub fn create_recurring_income(&self, input: CreateRecurringIncome) -> Result<RecurringIncome> {
+        input.validate()?;
+        let currency = input.currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+        let recurring = RecurringIncome {
+            id: Uuid::new_v4().to_string(),
+            source: input.source,
+            amount: input.amount.in_currency(&currency),
+            currency,
+            interval: input.interval,
+            anchor_date: input.next_date.clone(),
+            next_date: input.next_date,
+            created_at: Utc::now().to_rfc3339(),
+        };
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO recurring_income
+                 (id, source, amount, currency, interval, next_date, anchor_date, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                recurring.id,
+                encrypt_value(self.key, &recurring.source)?,
+                encrypt_value(self.key, &recurring.amount)?,
+                recurring.currency,
+                recurring.interval,
+                recurring.next_date,
+                recurring.anchor_date,
+                recurring.created_at
+            ],
+        )?;
+        history::record(
+            &tx,
+            self.key,
+            "recurring income",
+            &recurring.id,
+            ChangeAction::Create,
+            None,
+            Some(&recurring),
+        )?;
+        tx.commit()?;
+        Ok(recurring)
+    }
+
+    /// Changes the given fields and returns the stored definition. Entries
+    /// booked before stay as they are; a new `next_date` also becomes the
+    /// day later occurrences count from.
+    pub fn update_recurring_income(
+        &self,
+        id: &str,
+        input: UpdateRecurringIncome,
+    ) -> Result<RecurringIncome> {
+        input.validate()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let before = find_recurring_income(&tx, self.key, id)?;
+        let currency = input.currency.unwrap_or_else(|| before.currency.clone());
+        let after = RecurringIncome {
+            source: input.source.unwrap_or_else(|| before.source.clone()),
+            amount: input.amount.unwrap_or(before.amount).in_currency(&currency),
+            currency,
+            interval: input.interval.unwrap_or_else(|| before.interval.clone()),
+            anchor_date: input.next_date.clone().unwrap_or_else(|| before.anchor_date.clone()),
+            next_date: input.next_date.unwrap_or_else(|| before.next_date.clone()),
+            ..before.clone()
+        };
+        let updated = tx.execute(
+            "UPDATE recurring_income SET source = ?1, amount = ?2, currency = ?3, interval = ?4,
+                 next_date = ?5, anchor_date = ?6
+             WHERE id = ?7 AND deleted_at IS NULL",
+            rusqlite::params![
+                encrypt_value(self.key, &after.source)?,
+                encrypt_value(self.key, &after.amount)?,
+                after.currency,
+                after.interval,
+                after.next_date,
+                after.anchor_date,
+                id
+            ],
+        )?;
+        if updated == 0 {
+            return Err(VaultError::NotFound { entity: "recurring income", id: id.to_string() });
+        }
+        history::record(
+            &tx,
+            self.key,
+            "recurring income",
+            id,
+            ChangeAction::Update,
+            Some(&before),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Moves the definition to the trash; nothing more is booked from it.
+    pub fn delete_recurring_income(&self, id: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if trash::move_to_trash(&tx, "recurring_income", id)? {
+            let before = find_recurring_income(&tx, self.key, id)?;
+            history::record(
+                &tx,
+                self.key,
+                "recurring income",
+                id,
+                ChangeAction::Delete,
+                Some(&before),
+                None,
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn restore_recurring_income(&self, id: &str) -> Result<RecurringIncome> {
+        let tx = self.conn.unchecked_transaction()?;
+        trash::restore_from_trash(&tx, "recurring_income", "recurring income", id)?;
+        let after = find_recurring_income(&tx, self.key, id)?;
+        history::record(
+            &tx,
+            self.key,
+            "recurring income",
+            id,
+            ChangeAction::Restore,
+            None,
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Books every occurrence of recurring income dated on or before `today`
+    /// (YYYY-MM-DD) as an income entry and moves each definition's
+    /// `next_date` past it. Returns the new entries; the app runs this on
+    /// every unlock. Entries take their id from the definition and the date,
+    /// so an occurrence is booked only once even when undo moves `next_date`
+    /// back.
+    pub fn book_recurring_income(&self, today: &str) -> Result<Vec<Income>> {
+        Validator::new().check("today", validation::iso_date(today)).finish()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let due = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT {RECURRING_INCOME_COLUMNS} FROM recurring_income
+                 WHERE deleted_at IS NULL AND next_date <= ?1"
+            ))?;
+            let rows = stmt.query_map([today], |row| recurring_income_from_row(row, self.key))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        let now = Utc::now().to_rfc3339();
+        let mut booked = Vec::new();
+        for before in due {
+            let mut after = before.clone();
+            while after.next_date.as_str() <= today {
+                let id = format!("{}-{}", after.id, after.next_date);
+                let booked_before = tx
+                    .query_row("SELECT 1 FROM income WHERE id = ?1", [&id], |_| Ok(()))
+                    .optional()?
+                    .is_some();
+                if !booked_before {
+                    let income = Income {
+                        id,
+                        source: after.source.clone(),
+                        amount: after.amount,
+                        currency: after.currency.clone(),
+                        date: after.next_date.clone(),
+                        created_at: now.clone(),
+                    };
+                    insert_income(&tx, self.key, &income)?;
+                    history::record(
+                        &tx,
+                        self.key,
+                        "income",
+                        &income.id,
+                        ChangeAction::Create,
+                        None,
+                        Some(&income),
+                    )?;
+                    booked.push(income);
+                }
+                after.next_date =
+                    next_occurrence(&after.anchor_date, &after.next_date, &after.interval)?;
+            }
+            put_recurring_income(&tx, self.key, &after)?;
+            history::record(
+                &tx,
+                self.key,
+                "recurring income",
+                &after.id,
+                ChangeAction::Update,
+                Some(&before),
+                Some(&after),
+            )?;
+        }
+        tx.commit()?;
+        Ok(booked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::test_support;
+
+    fn money(text: &str) -> Money {
+        text.parse().unwrap()
+    }
+
+    fn salary() -> CreateRecurringIncome {
+        CreateRecurringIncome {
+            source: "Salary".into(),
+            amount: money("3200"),
+            currency: None,
+            interval: "monthly".into(),
+            next_date: "2024-01-31".into(),
+        }
+    }
+
+    #[test]
+    fn entries_are_created_updated_and_trashed() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let input = CreateIncome {
+            source: "Invoice #12".into(),
+            amount: money("850"),
+            currency: Some("USD".into()),
+            date: "2024-03-14".into(),
+        };
+        let created = vault.create_income(input).unwrap();
+        assert_eq!(vault.list_income().unwrap()[0].source, "Invoice #12");
+
+        let update = UpdateIncome { amount: Some(money("900")), ..Default::default() };
+        let updated = vault.update_income(&created.id, update).unwrap();
+        assert_eq!(updated.amount, money("900"));
+        assert_eq!(updated.currency, "USD");
+
+        vault.delete_income(&created.id).unwrap();
+        assert!(vault.list_income().unwrap().is_empty());
+        assert_eq!(vault.restore_income(&created.id).unwrap().amount, money("900"));
+    }
+
+    #[test]
+    fn due_occurrences_are_booked_once() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let recurring = vault.create_recurring_income(salary()).unwrap();
+
+        let booked = vault.book_recurring_income("2024-03-31").unwrap();
+        let dates: Vec<_> = booked.iter().map(|i| i.date.as_str()).collect();
+        assert_eq!(dates, ["2024-01-31", "2024-02-29", "2024-03-31"]);
+        assert!(vault.book_recurring_income("2024-03-31").unwrap().is_empty());
+
+        let listed = vault.list_recurring_income().unwrap();
+        assert_eq!(listed[0].id, recurring.id);
+        assert_eq!(listed[0].next_date, "2024-04-30");
+        assert_eq!(vault.list_income().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn undoing_the_schedule_does_not_book_twice() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        vault.create_recurring_income(salary()).unwrap();
+        vault.book_recurring_income("2024-02-29").unwrap();
+
+        vault.undo_last_change().unwrap();
+        assert_eq!(vault.list_recurring_income().unwrap()[0].next_date, "2024-01-31");
+        assert!(vault.book_recurring_income("2024-02-29").unwrap().is_empty());
+        assert_eq!(vault.list_income().unwrap().len(), 2);
+        assert_eq!(vault.list_recurring_income().unwrap()[0].next_date, "2024-03-31");
+    }
+
+    #[test]
+    fn trashed_definitions_book_nothing() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let recurring = vault.create_recurring_income(salary()).unwrap();
+        vault.delete_recurring_income(&recurring.id).unwrap();
+        assert!(vault.book_recurring_income("2024-12-31").unwrap().is_empty());
+    }
+
+    #[test]
+    fn unknown_intervals_are_refused() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let input = CreateRecurringIncome { interval: "weekly".into(), ..salary() };
+        match vault.create_recurring_income(input) {
+            Err(VaultError::Validation(errors)) => assert_eq!(errors[0].field, "interval"),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Typed operations on an unlocked vault. Nothing in here knows about Tauri;
+//! commands, the tests and any other front end borrow a connection and the
+//! session key and call these methods.
+
+use rusqlite::Connection;
+
+use crate::crypto::DerivedKey;
+
+pub mod budget;
+pub mod categories;
+pub mod dashboard;
+pub mod envelopes;
+pub mod expenses;
+pub mod history;
+pub mod income;
+pub mod portfolio;
+pub mod savings;
+pub mod subscriptions;
+pub mod trash;
+
+/// An open, unlocked vault: a connection and the key its fields are
+/// encrypted with. Methods that write run in their own transaction.
+pub struct Vault<'a> {
+    conn: &'a Connection,
+    key: &'a DerivedKey,
+}
+
+impl<'a> Vault<'a> {
+    pub fn new(conn: &'a Connection, key: &'a DerivedKey) -> Self {
+        Self { conn, key }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use rusqlite::Connection;
+
+    use crate::crypto::{DerivedKey, KEY_LEN};
+    use crate::db;
+    use crate::vault::categories::CreateCategory;
+    use crate::vault::Vault;
+
+    /// A migrated in-memory database and a fixed key.
+    pub fn open() -> (Connection, DerivedKey) {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::migrate(&mut conn).unwrap();
+        (conn, DerivedKey([7; KEY_LEN]))
+    }
+
+    /// Id of the top-level category named `name`, created on first use.
+    pub fn category(vault: &Vault<'_>, name: &str) -> String {
+        let existing = vault.list_categories().unwrap().into_iter().find(|c| c.name == name);
+        existing.map(|c| c.id).unwrap_or_else(|| {
+            let input = CreateCategory { name: name.into(), parent_id: None, color: None, icon: None };
+            vault.create_category(input).unwrap().id
+        })
+    }
+}
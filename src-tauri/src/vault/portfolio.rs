@@ -0,0 +1,317 @@
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::history::{self, ChangeAction};
+use super::{trash, Vault};
+use crate::crypto::{encrypt_value, DerivedKey};
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::money::{Money, Quantity, DEFAULT_CURRENCY};
+use crate::validation::{self, Validator, ASSET_TYPES};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Position {
+    pub id: String,
+    pub isin: String,
+    pub ticker: String,
+    pub name: String,
+    pub asset_type: String,
+    pub quantity: Quantity,
+    pub avg_buy_price: Money,
+    pub currency: String,
+    pub country: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePosition {
+    pub isin: String,
+    pub ticker: String,
+    pub name: String,
+    pub asset_type: String,
+    pub quantity: Quantity,
+    pub avg_buy_price: Money,
+    pub currency: Option<String>,
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdatePosition {
+    pub quantity: Option<Quantity>,
+    pub avg_buy_price: Option<Money>,
+    pub currency: Option<String>,
+    pub country: Option<String>,
+    pub asset_type: Option<String>,
+}
+
+impl CreatePosition {
+    /// The ISIN may be left empty, e.g. for crypto held without one.
+    fn validate(&self) -> Result<()> {
+        let isin = Some(self.isin.as_str()).filter(|isin| !isin.is_empty());
+        Validator::new()
+            .check_opt("isin", isin, validation::isin)
+            .check("ticker", validation::not_blank(&self.ticker))
+            .check("asset_type", validation::one_of(&self.asset_type, ASSET_TYPES))
+            .check("quantity", validation::positive_quantity(self.quantity))
+            .check("avg_buy_price", validation::not_negative(self.avg_buy_price))
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .finish()
+    }
+}
+
+impl UpdatePosition {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check_opt("quantity", self.quantity, validation::positive_quantity)
+            .check_opt("avg_buy_price", self.avg_buy_price, validation::not_negative)
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check_opt("asset_type", self.asset_type.as_deref(), |asset_type| {
+                validation::one_of(asset_type, ASSET_TYPES)
+            })
+            .finish()
+    }
+}
+
+const POSITION_COLUMNS: &str =
+    "id, isin, ticker, name, asset_type, quantity, avg_buy_price, currency, country, created_at";
+
+fn position_from_row(row: &rusqlite::Row, key: &DerivedKey) -> rusqlite::Result<Position> {
+    let currency: String = row.get(7)?;
+    Ok(Position {
+        id: row.get(0)?,
+        isin: db::get_decrypted_opt(row, 1, key)?.unwrap_or_default(),
+        ticker: db::get_decrypted(row, 2, key)?,
+        name: db::get_decrypted(row, 3, key)?,
+        asset_type: row.get(4)?,
+        quantity: db::get_decrypted(row, 5, key)?,
+        avg_buy_price: db::get_money(row, 6, key, &currency)?,
+        currency,
+        country: db::get_decrypted_opt(row, 8, key)?.unwrap_or_default(),
+        created_at: row.get(9)?,
+    })
+}
+
+/// Reads one position, whether or not it is in the trash.
+fn find_position(conn: &rusqlite::Connection, key: &DerivedKey, id: &str) -> Result<Position> {
+    conn.query_row(
+        &format!("SELECT {POSITION_COLUMNS} FROM portfolio_positions WHERE id = ?1"),
+        [id],
+        |row| position_from_row(row, key),
+    )
+    .optional()?
+    .ok_or_else(|| VaultError::NotFound { entity: "position", id: id.to_string() })
+}
+
+/// Overwrites every field of a stored position; used to undo updates.
+pub(super) fn put_position(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    position: &Position,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE portfolio_positions SET isin = ?1, ticker = ?2, name = ?3, asset_type = ?4, quantity = ?5,
+             avg_buy_price = ?6, currency = ?7, country = ?8
+         WHERE id = ?9",
+        rusqlite::params![
+            encrypt_value(key, &position.isin)?,
+            encrypt_value(key, &position.ticker)?,
+            encrypt_value(key, &position.name)?,
+            position.asset_type,
+            encrypt_value(key, &position.quantity)?,
+            encrypt_value(key, &position.avg_buy_price.in_currency(&position.currency))?,
+            position.currency,
+            encrypt_value(key, &position.country)?,
+            position.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(VaultError::NotFound { entity: "position", id: position.id.clone() });
+    }
+    Ok(())
+}
+
+impl Vault<'_> {
+    /// Positions outside the trash, oldest first.
+    pub fn list_positions(&self) -> Result<Vec<Position>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {POSITION_COLUMNS} FROM portfolio_positions WHERE deleted_at IS NULL ORDER BY created_at ASC"
+        ))?;
+        let rows = stmt.query_map([], |row| position_from_row(row, self.key))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(VaultError::Database)
+    }
+
+    pub fn create_position(&self, input: CreatePosition) -> Result<Position> {
+        input.validate()?;
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        let currency = input.currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+        let country = input.country.unwrap_or_default();
+        let avg_buy_price = input.avg_buy_price.in_currency(&currency);
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO portfolio_positions (id, isin, ticker, name, asset_type, quantity, avg_buy_price, currency, country, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            rusqlite::params![
+                id,
+                encrypt_value(self.key, &input.isin)?,
+                encrypt_value(self.key, &input.ticker)?,
+                encrypt_value(self.key, &input.name)?,
+                input.asset_type,
+                encrypt_value(self.key, &input.quantity)?,
+                encrypt_value(self.key, &avg_buy_price)?,
+                currency,
+                encrypt_value(self.key, &country)?,
+                now
+            ],
+        )?;
+
+        let position = Position {
+            id,
+            isin: input.isin,
+            ticker: input.ticker,
+            name: input.name,
+            asset_type: input.asset_type,
+            quantity: input.quantity,
+            avg_buy_price,
+            currency,
+            country,
+            created_at: now,
+        };
+        history::record(
+            &tx,
+            self.key,
+            "position",
+            &position.id,
+            ChangeAction::Create,
+            None,
+            Some(&position),
+        )?;
+        tx.commit()?;
+        Ok(position)
+    }
+
+    /// Changes the given fields in one statement and returns the stored position.
+    pub fn update_position(&self, id: &str, input: UpdatePosition) -> Result<Position> {
+        input.validate()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let before = find_position(&tx, self.key, id)?;
+        let currency = input.currency.as_deref().unwrap_or(&before.currency);
+        let avg_buy_price = input.avg_buy_price.unwrap_or(before.avg_buy_price).in_currency(currency);
+        let updated = tx.execute(
+            "UPDATE portfolio_positions SET
+                 quantity = COALESCE(?1, quantity),
+                 avg_buy_price = ?2,
+                 currency = COALESCE(?3, currency),
+                 country = COALESCE(?4, country),
+                 asset_type = COALESCE(?5, asset_type)
+             WHERE id = ?6 AND deleted_at IS NULL",
+            rusqlite::params![
+                input.quantity.map(|quantity| encrypt_value(self.key, &quantity)).transpose()?,
+                encrypt_value(self.key, &avg_buy_price)?,
+                input.currency,
+                input.country.map(|country| encrypt_value(self.key, &country)).transpose()?,
+                input.asset_type,
+                id
+            ],
+        )?;
+        if updated == 0 {
+            return Err(VaultError::NotFound { entity: "position", id: id.to_string() });
+        }
+
+        let after = find_position(&tx, self.key, id)?;
+        history::record(
+            &tx,
+            self.key,
+            "position",
+            id,
+            ChangeAction::Update,
+            Some(&before),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Moves the position to the trash; see `restore_position`.
+    pub fn delete_position(&self, id: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if trash::move_to_trash(&tx, "portfolio_positions", id)? {
+            let before = find_position(&tx, self.key, id)?;
+            history::record(&tx, self.key, "position", id, ChangeAction::Delete, Some(&before), None)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn restore_position(&self, id: &str) -> Result<Position> {
+        let tx = self.conn.unchecked_transaction()?;
+        trash::restore_from_trash(&tx, "portfolio_positions", "position", id)?;
+        let after = find_position(&tx, self.key, id)?;
+        history::record(&tx, self.key, "position", id, ChangeAction::Restore, None, Some(&after))?;
+        tx.commit()?;
+        Ok(after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::test_support;
+
+    fn bitcoin() -> CreatePosition {
+        CreatePosition {
+            isin: String::new(),
+            ticker: "BTC-EUR".into(),
+            name: "Bitcoin".into(),
+            asset_type: "crypto".into(),
+            quantity: "0.125".parse().unwrap(),
+            avg_buy_price: "40000".parse().unwrap(),
+            currency: None,
+            country: None,
+        }
+    }
+
+    #[test]
+    fn position_without_isin_is_accepted() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let created = vault.create_position(bitcoin()).unwrap();
+
+        let listed = vault.list_positions().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, created.id);
+        assert_eq!(listed[0].isin, "");
+        assert_eq!(listed[0].quantity.to_string(), "0.125");
+    }
+
+    #[test]
+    fn zero_quantity_is_rejected() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let input = CreatePosition { quantity: "0".parse().unwrap(), ..bitcoin() };
+        match vault.create_position(input) {
+            Err(VaultError::Validation(errors)) => assert_eq!(errors[0].field, "quantity"),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn update_and_trash_round_trip() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let created = vault.create_position(bitcoin()).unwrap();
+
+        let update = UpdatePosition { quantity: Some("0.5".parse().unwrap()), ..Default::default() };
+        let updated = vault.update_position(&created.id, update).unwrap();
+        assert_eq!(updated.quantity.to_string(), "0.5");
+
+        vault.delete_position(&created.id).unwrap();
+        assert!(vault.list_positions().unwrap().is_empty());
+        vault.restore_position(&created.id).unwrap();
+        assert_eq!(vault.list_positions().unwrap()[0].quantity.to_string(), "0.5");
+    }
+}
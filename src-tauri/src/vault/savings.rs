@@ -0,0 +1,323 @@
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::history::{self, ChangeAction};
+use super::{trash, Vault};
+use crate::crypto::{encrypt_value, DerivedKey};
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::money::{Money, DEFAULT_CURRENCY};
+use crate::validation::{self, Validator, SAVINGS_INTERVALS};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SavingsPlan {
+    pub id: String,
+    pub name: String,
+    pub isin: String,
+    pub ticker: String,
+    pub amount: Money,
+    pub currency: String,
+    pub interval: String,
+    pub next_date: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSavingsPlan {
+    pub name: String,
+    pub isin: String,
+    pub ticker: String,
+    pub amount: Money,
+    pub currency: Option<String>,
+    pub interval: String,
+    pub next_date: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateSavingsPlan {
+    pub name: Option<String>,
+    pub amount: Option<Money>,
+    pub currency: Option<String>,
+    pub interval: Option<String>,
+    pub next_date: Option<String>,
+}
+
+impl CreateSavingsPlan {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check("name", validation::not_blank(&self.name))
+            .check("isin", validation::isin(&self.isin))
+            .check("amount", validation::not_negative(self.amount))
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check("interval", validation::one_of(&self.interval, SAVINGS_INTERVALS))
+            .check("next_date", validation::iso_date(&self.next_date))
+            .finish()
+    }
+}
+
+impl UpdateSavingsPlan {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check_opt("name", self.name.as_deref(), validation::not_blank)
+            .check_opt("amount", self.amount, validation::not_negative)
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check_opt("interval", self.interval.as_deref(), |interval| {
+                validation::one_of(interval, SAVINGS_INTERVALS)
+            })
+            .check_opt("next_date", self.next_date.as_deref(), validation::iso_date)
+            .finish()
+    }
+}
+
+const SAVINGS_PLAN_COLUMNS: &str =
+    "id, name, isin, ticker, amount, currency, interval, next_date, created_at";
+
+fn savings_plan_from_row(row: &rusqlite::Row, key: &DerivedKey) -> rusqlite::Result<SavingsPlan> {
+    let currency: String = row.get(5)?;
+    Ok(SavingsPlan {
+        id: row.get(0)?,
+        name: db::get_decrypted(row, 1, key)?,
+        isin: db::get_decrypted(row, 2, key)?,
+        ticker: db::get_decrypted(row, 3, key)?,
+        amount: db::get_money(row, 4, key, &currency)?,
+        currency,
+        interval: row.get(6)?,
+        next_date: row.get(7)?,
+        created_at: row.get(8)?,
+    })
+}
+
+/// Reads one savings plan, whether or not it is in the trash.
+fn find_savings_plan(conn: &rusqlite::Connection, key: &DerivedKey, id: &str) -> Result<SavingsPlan> {
+    conn.query_row(
+        &format!("SELECT {SAVINGS_PLAN_COLUMNS} FROM savings_plans WHERE id = ?1"),
+        [id],
+        |row| savings_plan_from_row(row, key),
+    )
+    .optional()?
+    .ok_or_else(|| VaultError::NotFound { entity: "savings plan", id: id.to_string() })
+}
+
+/// Overwrites every field of a stored savings plan; used to undo updates.
+pub(super) fn put_savings_plan(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    plan: &SavingsPlan,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE savings_plans SET name = ?1, isin = ?2, ticker = ?3, amount = ?4, currency = ?5,
+             interval = ?6, next_date = ?7
+         WHERE id = ?8",
+        rusqlite::params![
+            encrypt_value(key, &plan.name)?,
+            encrypt_value(key, &plan.isin)?,
+            encrypt_value(key, &plan.ticker)?,
+            encrypt_value(key, &plan.amount.in_currency(&plan.currency))?,
+            plan.currency,
+            plan.interval,
+            plan.next_date,
+            plan.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(VaultError::NotFound { entity: "savings plan", id: plan.id.clone() });
+    }
+    Ok(())
+}
+
+impl Vault<'_> {
+    /// Savings plans outside the trash, next execution first.
+    pub fn list_savings_plans(&self) -> Result<Vec<SavingsPlan>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SAVINGS_PLAN_COLUMNS} FROM savings_plans WHERE deleted_at IS NULL ORDER BY next_date ASC"
+        ))?;
+        let rows = stmt.query_map([], |row| savings_plan_from_row(row, self.key))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(VaultError::Database)
+    }
+
+    pub fn create_savings_plan(&self, input: CreateSavingsPlan) -> Result<SavingsPlan> {
+        input.validate()?;
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        let currency = input.currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+        let amount = input.amount.in_currency(&currency);
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO savings_plans (id, name, isin, ticker, amount, currency, interval, next_date, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            rusqlite::params![
+                id,
+                encrypt_value(self.key, &input.name)?,
+                encrypt_value(self.key, &input.isin)?,
+                encrypt_value(self.key, &input.ticker)?,
+                encrypt_value(self.key, &amount)?,
+                currency,
+                input.interval,
+                input.next_date,
+                now
+            ],
+        )?;
+
+        let plan = SavingsPlan {
+            id,
+            name: input.name,
+            isin: input.isin,
+            ticker: input.ticker,
+            amount,
+            currency,
+            interval: input.interval,
+            next_date: input.next_date,
+            created_at: now,
+        };
+        history::record(
+            &tx,
+            self.key,
+            "savings plan",
+            &plan.id,
+            ChangeAction::Create,
+            None,
+            Some(&plan),
+        )?;
+        tx.commit()?;
+        Ok(plan)
+    }
+
+    /// Changes the given fields in one statement and returns the stored plan.
+    pub fn update_savings_plan(&self, id: &str, input: UpdateSavingsPlan) -> Result<SavingsPlan> {
+        input.validate()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let before = find_savings_plan(&tx, self.key, id)?;
+        let currency = input.currency.as_deref().unwrap_or(&before.currency);
+        let amount = input.amount.unwrap_or(before.amount).in_currency(currency);
+        let updated = tx.execute(
+            "UPDATE savings_plans SET
+                 name = COALESCE(?1, name),
+                 amount = ?2,
+                 currency = COALESCE(?3, currency),
+                 interval = COALESCE(?4, interval),
+                 next_date = COALESCE(?5, next_date)
+             WHERE id = ?6 AND deleted_at IS NULL",
+            rusqlite::params![
+                input.name.map(|name| encrypt_value(self.key, &name)).transpose()?,
+                encrypt_value(self.key, &amount)?,
+                input.currency,
+                input.interval,
+                input.next_date,
+                id
+            ],
+        )?;
+        if updated == 0 {
+            return Err(VaultError::NotFound { entity: "savings plan", id: id.to_string() });
+        }
+
+        let after = find_savings_plan(&tx, self.key, id)?;
+        history::record(
+            &tx,
+            self.key,
+            "savings plan",
+            id,
+            ChangeAction::Update,
+            Some(&before),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Moves the savings plan to the trash; see `restore_savings_plan`.
+    pub fn delete_savings_plan(&self, id: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if trash::move_to_trash(&tx, "savings_plans", id)? {
+            let before = find_savings_plan(&tx, self.key, id)?;
+            history::record(
+                &tx,
+                self.key,
+                "savings plan",
+                id,
+                ChangeAction::Delete,
+                Some(&before),
+                None,
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn restore_savings_plan(&self, id: &str) -> Result<SavingsPlan> {
+        let tx = self.conn.unchecked_transaction()?;
+        trash::restore_from_trash(&tx, "savings_plans", "savings plan", id)?;
+        let after = find_savings_plan(&tx, self.key, id)?;
+        history::record(
+            &tx,
+            self.key,
+            "savings plan",
+            id,
+            ChangeAction::Restore,
+            None,
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::test_support;
+
+    fn world_etf() -> CreateSavingsPlan {
+        CreateSavingsPlan {
+            name: "MSCI World".into(),
+            isin: "IE00B4L5Y983".into(),
+            ticker: "EUNL.DE".into(),
+            amount: "100".parse().unwrap(),
+            currency: None,
+            interval: "monthly".into(),
+            next_date: "2024-04-02".into(),
+        }
+    }
+
+    #[test]
+    fn created_plan_is_listed() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let created = vault.create_savings_plan(world_etf()).unwrap();
+
+        let listed = vault.list_savings_plans().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, created.id);
+        assert_eq!(listed[0].isin, "IE00B4L5Y983");
+    }
+
+    #[test]
+    fn isin_with_wrong_check_digit_is_rejected() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let input = CreateSavingsPlan { isin: "IE00B4L5Y984".into(), ..world_etf() };
+        match vault.create_savings_plan(input) {
+            Err(VaultError::Validation(errors)) => assert_eq!(errors[0].field, "isin"),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn update_and_delete() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let created = vault.create_savings_plan(world_etf()).unwrap();
+
+        let update = UpdateSavingsPlan { interval: Some("quarterly".into()), ..Default::default() };
+        let updated = vault.update_savings_plan(&created.id, update).unwrap();
+        assert_eq!(updated.interval, "quarterly");
+        assert_eq!(updated.amount, created.amount);
+
+        vault.delete_savings_plan(&created.id).unwrap();
+        assert!(vault.list_savings_plans().unwrap().is_empty());
+        assert_eq!(vault.restore_savings_plan(&created.id).unwrap().interval, "quarterly");
+    }
+}
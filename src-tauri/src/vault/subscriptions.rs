@@ -0,0 +1,324 @@
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::history::{self, ChangeAction};
+use super::{trash, Vault};
+use crate::crypto::{encrypt_value, DerivedKey};
+use crate::db;
+use crate::error::{Result, VaultError};
+use crate::money::{Money, DEFAULT_CURRENCY};
+use crate::validation::{self, Validator, BILLING_INTERVALS};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Subscription {
+    pub id: String,
+    pub name: String,
+    pub amount: Money,
+    pub currency: String,
+    pub billing: String,
+    pub next_billing: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSubscription {
+    pub name: String,
+    pub amount: Money,
+    pub currency: Option<String>,
+    pub billing: String,
+    pub next_billing: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateSubscription {
+    pub name: Option<String>,
+    pub amount: Option<Money>,
+    pub currency: Option<String>,
+    pub billing: Option<String>,
+    pub next_billing: Option<String>,
+}
+
+impl CreateSubscription {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check("name", validation::not_blank(&self.name))
+            .check("amount", validation::not_negative(self.amount))
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check("billing", validation::one_of(&self.billing, BILLING_INTERVALS))
+            .check("next_billing", validation::iso_date(&self.next_billing))
+            .finish()
+    }
+}
+
+impl UpdateSubscription {
+    fn validate(&self) -> Result<()> {
+        Validator::new()
+            .check_opt("name", self.name.as_deref(), validation::not_blank)
+            .check_opt("amount", self.amount, validation::not_negative)
+            .check_opt("currency", self.currency.as_deref(), validation::currency)
+            .check_opt("billing", self.billing.as_deref(), |billing| {
+                validation::one_of(billing, BILLING_INTERVALS)
+            })
+            .check_opt("next_billing", self.next_billing.as_deref(), validation::iso_date)
+            .finish()
+    }
+}
+
+const SUBSCRIPTION_COLUMNS: &str = "id, name, amount, currency, billing, next_billing, created_at";
+
+fn subscription_from_row(row: &rusqlite::Row, key: &DerivedKey) -> rusqlite::Result<Subscription> {
+    let currency: String = row.get(3)?;
+    Ok(Subscription {
+        id: row.get(0)?,
+        name: db::get_decrypted(row, 1, key)?,
+        amount: db::get_money(row, 2, key, &currency)?,
+        currency,
+        billing: row.get(4)?,
+        next_billing: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+/// Reads one subscription, whether or not it is in the trash.
+fn find_subscription(conn: &rusqlite::Connection, key: &DerivedKey, id: &str) -> Result<Subscription> {
+    conn.query_row(
+        &format!("SELECT {SUBSCRIPTION_COLUMNS} FROM subscriptions WHERE id = ?1"),
+        [id],
+        |row| subscription_from_row(row, key),
+    )
+    .optional()?
+    .ok_or_else(|| VaultError::NotFound { entity: "subscription", id: id.to_string() })
+}
+
+/// Overwrites every field of a stored subscription; used to undo updates.
+pub(super) fn put_subscription(
+    conn: &rusqlite::Connection,
+    key: &DerivedKey,
+    subscription: &Subscription,
+) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE subscriptions SET name = ?1, amount = ?2, currency = ?3, billing = ?4, next_billing = ?5
+         WHERE id = ?6",
+        rusqlite::params![
+            encrypt_value(key, &subscription.name)?,
+            encrypt_value(key, &subscription.amount.in_currency(&subscription.currency))?,
+            subscription.currency,
+            subscription.billing,
+            subscription.next_billing,
+            subscription.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(VaultError::NotFound { entity: "subscription", id: subscription.id.clone() });
+    }
+    Ok(())
+}
+
+impl Vault<'_> {
+    /// Subscriptions outside the trash, next billing first.
+    pub fn list_subscriptions(&self) -> Result<Vec<Subscription>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SUBSCRIPTION_COLUMNS} FROM subscriptions WHERE deleted_at IS NULL ORDER BY next_billing ASC"
+        ))?;
+        let rows = stmt.query_map([], |row| subscription_from_row(row, self.key))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(VaultError::Database)
+    }
+
+    pub fn create_subscription(&self, input: CreateSubscription) -> Result<Subscription> {
+        input.validate()?;
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        let currency = input.currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+        let amount = input.amount.in_currency(&currency);
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO subscriptions (id, name, amount, currency, billing, next_billing, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                id,
+                encrypt_value(self.key, &input.name)?,
+                encrypt_value(self.key, &amount)?,
+                currency,
+                input.billing,
+                input.next_billing,
+                now
+            ],
+        )?;
+
+        let subscription = Subscription {
+            id,
+            name: input.name,
+            amount,
+            currency,
+            billing: input.billing,
+            next_billing: input.next_billing,
+            created_at: now,
+        };
+        history::record(
+            &tx,
+            self.key,
+            "subscription",
+            &subscription.id,
+            ChangeAction::Create,
+            None,
+            Some(&subscription),
+        )?;
+        tx.commit()?;
+        Ok(subscription)
+    }
+
+    /// Changes the given fields in one statement and returns the stored subscription.
+    pub fn update_subscription(&self, id: &str, input: UpdateSubscription) -> Result<Subscription> {
+        input.validate()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let before = find_subscription(&tx, self.key, id)?;
+        let currency = input.currency.as_deref().unwrap_or(&before.currency);
+        let amount = input.amount.unwrap_or(before.amount).in_currency(currency);
+        let updated = tx.execute(
+            "UPDATE subscriptions SET
+                 name = COALESCE(?1, name),
+                 amount = ?2,
+                 currency = COALESCE(?3, currency),
+                 billing = COALESCE(?4, billing),
+                 next_billing = COALESCE(?5, next_billing)
+             WHERE id = ?6 AND deleted_at IS NULL",
+            rusqlite::params![
+                input.name.map(|name| encrypt_value(self.key, &name)).transpose()?,
+                encrypt_value(self.key, &amount)?,
+                input.currency,
+                input.billing,
+                input.next_billing,
+                id
+            ],
+        )?;
+        if updated == 0 {
+            return Err(VaultError::NotFound { entity: "subscription", id: id.to_string() });
+        }
+
+        let after = find_subscription(&tx, self.key, id)?;
+        history::record(
+            &tx,
+            self.key,
+            "subscription",
+            id,
+            ChangeAction::Update,
+            Some(&before),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Moves the subscription to the trash; see `restore_subscription`.
+    pub fn delete_subscription(&self, id: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if trash::move_to_trash(&tx, "subscriptions", id)? {
+            let before = find_subscription(&tx, self.key, id)?;
+            history::record(
+                &tx,
+                self.key,
+                "subscription",
+                id,
+                ChangeAction::Delete,
+                Some(&before),
+                None,
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn restore_subscription(&self, id: &str) -> Result<Subscription> {
+        let tx = self.conn.unchecked_transaction()?;
+        trash::restore_from_trash(&tx, "subscriptions", "subscription", id)?;
+        let after = find_subscription(&tx, self.key, id)?;
+        history::record(
+            &tx,
+            self.key,
+            "subscription",
+            id,
+            ChangeAction::Restore,
+            None,
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::test_support;
+
+    fn streaming() -> CreateSubscription {
+        CreateSubscription {
+            name: "Streaming".into(),
+            amount: "9.99".parse().unwrap(),
+            currency: Some("USD".into()),
+            billing: "monthly".into(),
+            next_billing: "2024-04-01".into(),
+        }
+    }
+
+    #[test]
+    fn subscriptions_are_listed_by_next_billing() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        vault.create_subscription(streaming()).unwrap();
+        vault
+            .create_subscription(CreateSubscription {
+                name: "Cloud".into(),
+                next_billing: "2024-03-15".into(),
+                ..streaming()
+            })
+            .unwrap();
+
+        let names: Vec<_> =
+            vault.list_subscriptions().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["Cloud", "Streaming"]);
+    }
+
+    #[test]
+    fn unknown_billing_interval_is_rejected() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let input = CreateSubscription { billing: "weekly".into(), ..streaming() };
+        assert!(matches!(vault.create_subscription(input), Err(VaultError::Validation(_))));
+    }
+
+    #[test]
+    fn update_keeps_currency_and_rounds_amount() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let created = vault.create_subscription(streaming()).unwrap();
+
+        let update = UpdateSubscription {
+            amount: Some("12.499".parse().unwrap()),
+            currency: Some("JPY".into()),
+            ..Default::default()
+        };
+        let updated = vault.update_subscription(&created.id, update).unwrap();
+        assert_eq!(updated.currency, "JPY");
+        assert_eq!(updated.amount.to_string(), "12");
+    }
+
+    #[test]
+    fn delete_and_restore_round_trip() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let created = vault.create_subscription(streaming()).unwrap();
+
+        vault.delete_subscription(&created.id).unwrap();
+        assert!(vault.list_subscriptions().unwrap().is_empty());
+        vault.restore_subscription(&created.id).unwrap();
+        assert_eq!(vault.list_subscriptions().unwrap().len(), 1);
+
+        let again = vault.restore_subscription(&created.id);
+        assert!(matches!(again, Err(VaultError::NotFound { entity: "subscription", .. })));
+    }
+}
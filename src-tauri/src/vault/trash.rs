@@ -0,0 +1,269 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::Vault;
+use crate::db::{self, meta_get, meta_set};
+use crate::error::{Result, VaultError};
+use crate::validation::Validator;
+
+/// Entities that can be trashed: (entity name, table, column shown in the
+/// trash list).
+const TRASH_TABLES: &[(&str, &str, &str)] = &[
+    ("expense", "expenses", "title"),
+    ("subscription", "subscriptions", "name"),
+    ("position", "portfolio_positions", "name"),
+    ("savings plan", "savings_plans", "name"),
+    ("income", "income", "source"),
+    ("recurring income", "recurring_income", "source"),
+];
+
+const MAX_RETENTION_DAYS: u32 = 3650;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashSettings {
+    /// Trashed rows older than this are deleted for good. Zero keeps them
+    /// until the trash is emptied.
+    pub retention_days: u32,
+}
+
+impl Default for TrashSettings {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashItem {
+    pub entity: &'static str,
+    pub id: String,
+    pub label: String,
+    pub deleted_at: String,
+}
+
+/// Marks a row as deleted and returns whether it was. Rows that are already
+/// in the trash keep their original deletion time.
+pub(super) fn move_to_trash(conn: &rusqlite::Connection, table: &str, id: &str) -> Result<bool> {
+    let moved = conn.execute(
+        &format!("UPDATE {table} SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL"),
+        rusqlite::params![Utc::now().to_rfc3339(), id],
+    )?;
+    Ok(moved > 0)
+}
+
+/// Takes a row out of the trash. Fails with `NotFound` unless it is in there.
+pub(super) fn restore_from_trash(
+    conn: &rusqlite::Connection,
+    table: &str,
+    entity: &'static str,
+    id: &str,
+) -> Result<()> {
+    let restored = conn.execute(
+        &format!("UPDATE {table} SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL"),
+        [id],
+    )?;
+    if restored == 0 {
+        return Err(VaultError::NotFound { entity, id: id.to_string() });
+    }
+    Ok(())
+}
+
+/// Permanently deletes the trashed rows of `table`, or only those deleted
+/// before `cutoff` (a SQLite date modifier such as `-30 days`). Their change
+/// log entries go as well: undo could no longer bring the rows back, and the
+/// log would keep their contents around.
+fn delete_trashed(
+    conn: &rusqlite::Connection,
+    entity: &str,
+    table: &str,
+    cutoff: Option<&str>,
+) -> Result<usize> {
+    let trashed = format!(
+        "SELECT id FROM {table} WHERE deleted_at IS NOT NULL
+         AND (?1 IS NULL OR julianday(deleted_at) < julianday('now', ?1))"
+    );
+    conn.execute(
+        &format!("DELETE FROM change_log WHERE entity = ?2 AND entity_id IN ({trashed})"),
+        rusqlite::params![cutoff, entity],
+    )?;
+    Ok(conn.execute(&format!("DELETE FROM {table} WHERE id IN ({trashed})"), [cutoff])?)
+}
+
+fn load_trash_settings(conn: &rusqlite::Connection) -> Result<TrashSettings> {
+    let defaults = TrashSettings::default();
+    Ok(TrashSettings {
+        retention_days: meta_get(conn, "trash_retention_days")?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.retention_days),
+    })
+}
+
+/// Permanently deletes trashed rows older than the retention period and
+/// returns how many were removed. Needs no key, so it also runs while the
+/// vault is being unlocked.
+pub fn purge_trash(conn: &rusqlite::Connection) -> Result<usize> {
+    let settings = load_trash_settings(conn)?;
+    if settings.retention_days == 0 {
+        return Ok(0);
+    }
+    let cutoff = format!("-{} days", settings.retention_days);
+    let tx = conn.unchecked_transaction()?;
+    let mut purged = 0;
+    for (entity, table, _) in TRASH_TABLES {
+        purged += delete_trashed(&tx, entity, table, Some(&cutoff))?;
+    }
+    tx.commit()?;
+    Ok(purged)
+}
+
+impl Vault<'_> {
+    /// Everything in the trash, most recently deleted first.
+    pub fn trash(&self) -> Result<Vec<TrashItem>> {
+        let mut items = Vec::new();
+        for &(entity, table, label) in TRASH_TABLES {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT id, {label}, deleted_at FROM {table} WHERE deleted_at IS NOT NULL"
+            ))?;
+            let rows = stmt.query_map([], |row| {
+                Ok(TrashItem {
+                    entity,
+                    id: row.get(0)?,
+                    label: db::get_decrypted(row, 1, self.key)?,
+                    deleted_at: row.get(2)?,
+                })
+            })?;
+            items.extend(rows.collect::<rusqlite::Result<Vec<_>>>()?);
+        }
+        items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        Ok(items)
+    }
+
+    /// Permanently deletes everything in the trash and returns how many rows
+    /// were removed.
+    pub fn empty_trash(&self) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut removed = 0;
+        for (entity, table, _) in TRASH_TABLES {
+            removed += delete_trashed(&tx, entity, table, None)?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    pub fn trash_settings(&self) -> Result<TrashSettings> {
+        load_trash_settings(self.conn)
+    }
+
+    /// Stores the settings and purges right away under the new retention.
+    pub fn set_trash_settings(&self, settings: TrashSettings) -> Result<()> {
+        let within_limit = if settings.retention_days > MAX_RETENTION_DAYS {
+            Err(format!("must be at most {MAX_RETENTION_DAYS}"))
+        } else {
+            Ok(())
+        };
+        Validator::new().check("retention_days", within_limit).finish()?;
+
+        meta_set(self.conn, "trash_retention_days", &settings.retention_days.to_string())?;
+        purge_trash(self.conn)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::expenses::CreateExpense;
+    use crate::vault::subscriptions::CreateSubscription;
+    use crate::vault::test_support;
+
+    fn trash_two(vault: &Vault) -> (String, String) {
+        let expense = vault
+            .create_expense(CreateExpense {
+                title: "Cinema".into(),
+                amount: "14".parse().unwrap(),
+                currency: None,
+                category_id: test_support::category(vault, "Leisure"),
+                date: "2024-03-01".into(),
+            })
+            .unwrap();
+        let subscription = vault
+            .create_subscription(CreateSubscription {
+                name: "Gym".into(),
+                amount: "30".parse().unwrap(),
+                currency: None,
+                billing: "monthly".into(),
+                next_billing: "2024-04-01".into(),
+            })
+            .unwrap();
+        vault.delete_expense(&expense.id).unwrap();
+        vault.delete_subscription(&subscription.id).unwrap();
+        (expense.id, subscription.id)
+    }
+
+    fn backdate(conn: &rusqlite::Connection, table: &str, id: &str, days: u32) {
+        conn.execute(
+            &format!(
+                "UPDATE {table} SET deleted_at = strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now', ?1)
+                 WHERE id = ?2"
+            ),
+            rusqlite::params![format!("-{days} days"), id],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn trash_lists_labels_of_every_entity() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        trash_two(&vault);
+
+        let mut labels: Vec<_> = vault.trash().unwrap().into_iter().map(|i| (i.entity, i.label)).collect();
+        labels.sort();
+        assert_eq!(labels, [("expense", "Cinema".to_string()), ("subscription", "Gym".to_string())]);
+    }
+
+    #[test]
+    fn empty_trash_removes_rows_for_good() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let (expense_id, _) = trash_two(&vault);
+
+        assert_eq!(vault.empty_trash().unwrap(), 2);
+        assert!(vault.trash().unwrap().is_empty());
+        assert!(vault.restore_expense(&expense_id).is_err());
+    }
+
+    #[test]
+    fn purge_respects_the_retention_period() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let (expense_id, subscription_id) = trash_two(&vault);
+        backdate(&conn, "expenses", &expense_id, 31);
+        backdate(&conn, "subscriptions", &subscription_id, 29);
+
+        assert_eq!(purge_trash(&conn).unwrap(), 1);
+        let left: Vec<_> = vault.trash().unwrap().into_iter().map(|i| i.id).collect();
+        assert_eq!(left, [subscription_id]);
+    }
+
+    #[test]
+    fn zero_retention_never_purges() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let (expense_id, _) = trash_two(&vault);
+        backdate(&conn, "expenses", &expense_id, 5000);
+
+        vault.set_trash_settings(TrashSettings { retention_days: 0 }).unwrap();
+        assert_eq!(vault.trash_settings().unwrap().retention_days, 0);
+        assert_eq!(purge_trash(&conn).unwrap(), 0);
+        assert_eq!(vault.trash().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn retention_above_the_limit_is_rejected() {
+        let (conn, key) = test_support::open();
+        let vault = Vault::new(&conn, &key);
+        let result = vault.set_trash_settings(TrashSettings { retention_days: MAX_RETENTION_DAYS + 1 });
+        assert!(matches!(result, Err(VaultError::Validation(_))));
+        assert_eq!(vault.trash_settings().unwrap().retention_days, 30);
+    }
+}
@@ -0,0 +1,68 @@
+-- Vault as written by releases before schema versioning and field
+-- encryption: user_version 0, REAL amounts, plaintext rows.
+CREATE TABLE vault_meta (
+    key   TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE expenses (
+    id          TEXT PRIMARY KEY,
+    title       TEXT NOT NULL,
+    amount      REAL NOT NULL,
+    currency    TEXT NOT NULL DEFAULT 'EUR',
+    category    TEXT NOT NULL,
+    date        TEXT NOT NULL,
+    created_at  TEXT NOT NULL
+);
+
+CREATE TABLE subscriptions (
+    id           TEXT PRIMARY KEY,
+    name         TEXT NOT NULL,
+    amount       REAL NOT NULL,
+    currency     TEXT NOT NULL DEFAULT 'EUR',
+    billing      TEXT NOT NULL CHECK(billing IN ('monthly','yearly')),
+    next_billing TEXT NOT NULL,
+    created_at   TEXT NOT NULL
+);
+
+CREATE TABLE portfolio_positions (
+    id             TEXT PRIMARY KEY,
+    ticker         TEXT NOT NULL,
+    isin           TEXT,
+    name           TEXT NOT NULL,
+    asset_type     TEXT NOT NULL CHECK(asset_type IN ('stock','etf','crypto','other')),
+    quantity       REAL NOT NULL,
+    avg_buy_price  REAL NOT NULL,
+    currency       TEXT NOT NULL DEFAULT 'EUR',
+    country        TEXT,
+    created_at     TEXT NOT NULL
+);
+
+CREATE TABLE budget (
+    id         TEXT PRIMARY KEY,
+    month      TEXT NOT NULL,
+    income     REAL NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE savings_plans (
+    id           TEXT PRIMARY KEY,
+    name         TEXT NOT NULL,
+    isin         TEXT NOT NULL,
+    ticker       TEXT NOT NULL,
+    amount       REAL NOT NULL,
+    currency     TEXT NOT NULL DEFAULT 'EUR',
+    interval     TEXT NOT NULL CHECK(interval IN ('monthly','quarterly','yearly')),
+    next_date    TEXT NOT NULL,
+    created_at   TEXT NOT NULL
+);
+
+INSERT INTO vault_meta (key, value) VALUES ('salt', 'c2FsdHNhbHRzYWx0c2FsdA');
+INSERT INTO expenses VALUES ('e1', 'Groceries', 42.5, 'EUR', 'Food', '2024-03-02', '2024-03-02T10:00:00Z');
+INSERT INTO subscriptions VALUES ('s1', 'Streaming', 12.99, 'EUR', 'monthly', '2024-04-01', '2024-03-01T10:00:00Z');
+INSERT INTO portfolio_positions VALUES ('p1', 'VWCE.DE', 'IE00BK5BQT80', 'Vanguard FTSE All-World', 'etf', 3.25, 101.4, 'EUR', NULL, '2024-03-01T10:00:00Z');
+-- SQLite renders these as 5.0e-05 and 1.0e+15 when cast to text.
+INSERT INTO portfolio_positions VALUES ('p2', 'BTC-EUR', NULL, 'Bitcoin', 'crypto', 0.00005, 0.000042, 'EUR', NULL, '2024-03-01T10:00:00Z');
+INSERT INTO budget VALUES ('b2', '2024-04', 1e15, '2024-04-01T10:00:00Z');
+INSERT INTO budget VALUES ('b1', '2024-03', 3200, '2024-03-01T10:00:00Z');
+INSERT INTO savings_plans VALUES ('sp1', 'World ETF', 'IE00BK5BQT80', 'VWCE.DE', 150, 'EUR', 'monthly', '2024-04-01', '2024-03-01T10:00:00Z');